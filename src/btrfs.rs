@@ -0,0 +1,343 @@
+//! btrfs subvolume handling: `subvol=` normalization and enumeration
+//! (`--resolve-all-btrfs-subvols`).
+//!
+//! Lists every subvolume of a mounted btrfs so unmounted ones can be offered
+//! as commented-out fstab templates.
+
+use crate::device::subvol_from_source;
+use crate::filter::append_option;
+use crate::fstab::FstabEntry;
+use crate::log;
+use std::process::Command;
+
+/// Canonical form of a subvolume path: leading slash, no trailing slash.
+///
+/// `@home`, `/@home` and `/@home/` all become `/@home`; `/` stays `/`.
+pub fn normalize_subvol_path(path: &str) -> String {
+    let trimmed = path.trim_matches('/');
+    format!("/{}", trimmed)
+}
+
+/// Rewrite any `subvol=` option in `options` to [`normalize_subvol_path`] form.
+///
+/// `source` is the findmnt source; when it carries bracket notation
+/// (`/dev/sda2[/@home]`) and names a different subvolume than the option, a
+/// warning is printed, since the entry would then mount the wrong tree.
+pub fn normalize_subvol_option(options: &str, source: &str) -> String {
+    let normalized: Vec<String> = options
+        .split(',')
+        .map(|opt| match opt.strip_prefix("subvol=") {
+            Some(path) if !path.is_empty() => {
+                let path = normalize_subvol_path(path);
+                if let Some(mounted) = subvol_from_source(source) {
+                    if normalize_subvol_path(mounted) != path {
+                        log::warning(
+                            "btrfs-subvol-mismatch",
+                            &format!(
+                                "{} mounts subvolume {} but its options say subvol={}",
+                                source, mounted, path
+                            ),
+                            &[("source", source), ("subvol", &path)],
+                        );
+                    }
+                }
+                format!("subvol={}", path)
+            }
+            _ => opt.to_string(),
+        })
+        .collect();
+    normalized.join(",")
+}
+
+/// Give a mount made by `subvolid=` alone a `subvol=` option.
+///
+/// `filtered` has already lost `subvolid=` (see
+/// [`filter_options`](crate::filter::filter_options)), which without a
+/// `subvol=` would make the entry mount the top level. When `raw` (the
+/// mount's options before filtering) has `subvolid=N` but no `subvol=`, the
+/// path is resolved through `resolve(N, target)`, then taken from `fsroot`
+/// (FSROOT or the `[/subvol]` source notation); failing both, `subvolid=N`
+/// is kept with a warning.
+pub fn restore_subvol_option_with<F>(
+    raw: &str,
+    filtered: &str,
+    fsroot: Option<&str>,
+    target: &str,
+    resolve: F,
+) -> String
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
+    let raw_options: Vec<&str> = raw.split(',').map(str::trim).collect();
+    if raw_options.iter().any(|opt| opt.starts_with("subvol=")) {
+        return filtered.to_string();
+    }
+    let Some(id) = raw_options
+        .iter()
+        .find_map(|opt| opt.strip_prefix("subvolid="))
+    else {
+        return filtered.to_string();
+    };
+
+    let option = match resolve(id, target).or_else(|| fsroot.map(str::to_string)) {
+        Some(path) => format!("subvol={}", normalize_subvol_path(&path)),
+        None => {
+            log::warning(
+                "btrfs-subvolid-unresolved",
+                &format!(
+                    "{} is mounted by subvolid={} and its subvolume path could not be \
+                     resolved; keeping subvolid=",
+                    target, id
+                ),
+                &[("mountpoint", target), ("subvolid", id)],
+            );
+            format!("subvolid={}", id)
+        }
+    };
+    append_option(filtered, &option)
+}
+
+/// Rebuild a btrfs entry's options from the mount's FSROOT
+/// (`--prefer-fstab-options`).
+///
+/// `vfs_options` are the already filtered options. Any `subvol=` is replaced
+/// in place by `subvol=<fsroot>`, or appended when missing, and `subvolid=`
+/// is dropped, so the entry names its subvolume by path even when the kernel
+/// reported only the id. A bind mount of a directory inside a subvolume has
+/// that directory as FSROOT, which is not a subvolume; callers should only
+/// pass FSROOTs of subvolume mounts.
+pub fn btrfs_fstab_options(vfs_options: &str, fsroot: &str) -> String {
+    let subvol = format!("subvol={}", normalize_subvol_path(fsroot));
+    let mut options: Vec<&str> = Vec::new();
+    let mut has_subvol = false;
+    for opt in vfs_options.split(',').map(str::trim) {
+        if opt.is_empty() || opt.starts_with("subvolid=") {
+            continue;
+        }
+        if opt.starts_with("subvol=") {
+            if !has_subvol {
+                options.push(&subvol);
+                has_subvol = true;
+            }
+            continue;
+        }
+        options.push(opt);
+    }
+    if !has_subvol {
+        options.push(&subvol);
+    }
+    options.join(",")
+}
+
+/// Resolve a subvolume id to its path with
+/// `btrfs inspect-internal subvolid-resolve`.
+///
+/// Returns None silently on failure (no btrfs-progs, not root, ...).
+pub fn resolve_subvolid(id: &str, mountpoint: &str) -> Option<String> {
+    let output = Command::new("btrfs")
+        .args(["inspect-internal", "subvolid-resolve", id, mountpoint])
+        .output()
+        .ok()?;
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then_some(path)
+}
+
+/// List subvolume paths of the btrfs mounted at `mountpoint`.
+///
+/// Returns None silently on failure (no btrfs-progs, not root, ...).
+pub fn list_subvolumes(mountpoint: &str) -> Option<Vec<String>> {
+    let output = Command::new("btrfs")
+        .args(["subvolume", "list", mountpoint])
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(parse_subvolume_list(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    } else {
+        None
+    }
+}
+
+/// Parse `btrfs subvolume list` output into absolute subvolume paths.
+///
+/// Lines look like `ID 256 gen 42 top level 5 path @home`; the path is
+/// everything after ` path ` and is returned as `/@home`.
+pub fn parse_subvolume_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(" path "))
+        .map(|(_, path)| path.trim())
+        .filter(|path| !path.is_empty())
+        .map(|path| format!("/{}", path.trim_start_matches('/')))
+        .collect()
+}
+
+/// Commented template lines for subvolumes that are not mounted.
+///
+/// `entry` is the active entry of a mount of the same filesystem; each
+/// template reuses its fs_spec and options with `subvol=` swapped, and uses
+/// the subvolume path as a placeholder mountpoint. `mounted` holds the
+/// fsroots of the filesystem's current mounts.
+pub fn subvolume_templates(
+    entry: &FstabEntry,
+    subvolumes: &[String],
+    mounted: &[&str],
+) -> Vec<String> {
+    let base_options: Vec<&str> = entry
+        .options
+        .split(',')
+        .filter(|opt| !opt.starts_with("subvol=") && *opt != "defaults")
+        .collect();
+
+    subvolumes
+        .iter()
+        .filter(|subvol| !mounted.contains(&subvol.as_str()))
+        .map(|subvol| {
+            let mut options = base_options.clone();
+            let subvol_opt = format!("subvol={}", subvol);
+            options.push(&subvol_opt);
+            let template = FstabEntry {
+                comments: Vec::new(),
+                mountpoint: subvol.clone(),
+                mountpoint_bytes: None,
+                options: options.join(","),
+                pass: 0,
+                ..entry.clone()
+            };
+            format!("unmounted subvolume: {}", template.data_line())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_subvol_option() {
+        assert_eq!(
+            normalize_subvol_option("compress=zstd,subvol=@home", "/dev/sda2"),
+            "compress=zstd,subvol=/@home"
+        );
+        assert_eq!(
+            normalize_subvol_option("subvol=/@home/", "/dev/sda2[/@home]"),
+            "subvol=/@home"
+        );
+        assert_eq!(normalize_subvol_option("subvol=/", "/dev/sda2"), "subvol=/");
+        assert_eq!(normalize_subvol_option("defaults", "/dev/sda2"), "defaults");
+        // An empty value is left alone
+        assert_eq!(normalize_subvol_option("subvol=", "/dev/sda2"), "subvol=");
+    }
+
+    #[test]
+    fn test_restore_subvol_option() {
+        let unresolved = |_: &str, _: &str| None;
+
+        // Resolved by id
+        assert_eq!(
+            restore_subvol_option_with(
+                "rw,subvolid=257",
+                "compress=zstd",
+                None,
+                "/mnt/home",
+                |id, target| {
+                    assert_eq!((id, target), ("257", "/mnt/home"));
+                    Some("@home".to_string())
+                }
+            ),
+            "compress=zstd,subvol=/@home"
+        );
+        // From the bracket source or FSROOT when btrfs cannot say
+        assert_eq!(
+            restore_subvol_option_with(
+                "rw,subvolid=257",
+                "defaults",
+                Some("/@home"),
+                "/mnt/home",
+                unresolved
+            ),
+            "defaults,subvol=/@home"
+        );
+        // Neither: subvolid= stays so the entry still mounts the subvolume
+        assert_eq!(
+            restore_subvol_option_with(
+                "rw,subvolid=257",
+                "compress=zstd",
+                None,
+                "/mnt/home",
+                unresolved
+            ),
+            "compress=zstd,subvolid=257"
+        );
+        // A subvol= already present needs nothing
+        assert_eq!(
+            restore_subvol_option_with(
+                "rw,subvolid=257,subvol=/@home",
+                "subvol=/@home",
+                None,
+                "/mnt/home",
+                unresolved
+            ),
+            "subvol=/@home"
+        );
+        assert_eq!(
+            restore_subvol_option_with("rw,noatime", "noatime", None, "/mnt", unresolved),
+            "noatime"
+        );
+    }
+
+    #[test]
+    fn test_btrfs_fstab_options() {
+        assert_eq!(
+            btrfs_fstab_options("compress=zstd:3,space_cache=v2", "/@home"),
+            "compress=zstd:3,space_cache=v2,subvol=/@home"
+        );
+        // A stale or unnormalized subvol= is replaced where it stood
+        assert_eq!(
+            btrfs_fstab_options("subvol=@,compress=zstd,subvolid=257", "@home/"),
+            "subvol=/@home,compress=zstd"
+        );
+        assert_eq!(btrfs_fstab_options("defaults", "/"), "defaults,subvol=/");
+    }
+
+    const LIST: &str = "ID 256 gen 120 top level 5 path @\n\
+                        ID 257 gen 118 top level 5 path @home\n\
+                        ID 258 gen 90 top level 5 path @snapshots\n\
+                        ID 259 gen 12 top level 256 path @/var/lib/machines\n";
+
+    #[test]
+    fn test_parse_subvolume_list() {
+        assert_eq!(
+            parse_subvolume_list(LIST),
+            ["/@", "/@home", "/@snapshots", "/@/var/lib/machines"]
+        );
+        assert!(parse_subvolume_list("").is_empty());
+    }
+
+    #[test]
+    fn test_subvolume_templates_skip_mounted() {
+        let entry = FstabEntry {
+            comments: vec!["/dev/sda2[/@]".to_string()],
+            fs_spec: "UUID=abc".to_string(),
+            mountpoint: "/".to_string(),
+            fstype: "btrfs".to_string(),
+            options: "compress=zstd,subvol=/@".to_string(),
+            dump: 0,
+            pass: 0,
+            mountpoint_bytes: None,
+            id_fallback: false,
+            device: None,
+        };
+        let templates = subvolume_templates(&entry, &parse_subvolume_list(LIST), &["/@", "/@home"]);
+        assert_eq!(
+            templates,
+            [
+                "unmounted subvolume: UUID=abc\t/@snapshots\tbtrfs\tcompress=zstd,subvol=/@snapshots\t0\t0",
+                "unmounted subvolume: UUID=abc\t/@/var/lib/machines\tbtrfs\tcompress=zstd,subvol=/@/var/lib/machines\t0\t0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! CIFS/SMB source and credential handling (`--cifs-credentials`).
+//!
+//! A share mounted by its Windows UNC name (`\\server\share`) is written
+//! as `//server/share`, the form mount.cifs documents for fstab; the entry's
+//! comment keeps the source exactly as mounted.
+//!
+//! A running CIFS mount may show its login inline (`username=`, `domain=`,
+//! sometimes `password=`). fstab is world-readable, so a password is never
+//! copied into it; with a credentials file the whole login is replaced by a
+//! `credentials=` reference.
+
+use std::path::Path;
+
+/// Options that carry the password (`pass=` is mount.cifs's short form).
+const PASSWORD_OPTIONS: &[&str] = &["password", "pass"];
+
+/// Options that make up the login and belong in a credentials file.
+const LOGIN_OPTIONS: &[&str] = &[
+    "username",
+    "user",
+    "password",
+    "pass",
+    "domain",
+    "dom",
+    "credentials",
+    "cred",
+];
+
+/// Check whether a filesystem type is CIFS/SMB.
+pub fn is_cifs(fstype: &str) -> bool {
+    matches!(fstype, "cifs" | "smb3")
+}
+
+/// Write a CIFS source in `//server/share` form.
+///
+/// Backslashes in a UNC name (`\\server\share\dir`) become slashes, which
+/// also keeps them from being octal-escaped in fstab. Sources already using
+/// slashes are returned unchanged.
+pub fn normalize_cifs_source(source: &str) -> String {
+    if source.starts_with("\\\\") {
+        source.replace('\\', "/")
+    } else {
+        source.to_string()
+    }
+}
+
+/// Name part of a `name=value` option.
+fn option_name(opt: &str) -> &str {
+    opt.split_once('=').map_or(opt, |(name, _)| name)
+}
+
+/// Rewrite a CIFS entry's login options.
+///
+/// With `credentials`, every login option is replaced by
+/// `credentials=<PATH>`; without it only the password is removed. Returns
+/// the new options and whether a password was removed.
+pub fn rewrite_cifs_credentials(options: &str, credentials: Option<&Path>) -> (String, bool) {
+    let strip: &[&str] = if credentials.is_some() {
+        LOGIN_OPTIONS
+    } else {
+        PASSWORD_OPTIONS
+    };
+    let mut had_password = false;
+    let mut kept: Vec<String> = Vec::new();
+    for opt in options
+        .split(',')
+        .map(str::trim)
+        .filter(|opt| !opt.is_empty())
+    {
+        let name = option_name(opt);
+        had_password |= PASSWORD_OPTIONS.contains(&name);
+        if !strip.contains(&name) {
+            kept.push(opt.to_string());
+        }
+    }
+    if let Some(path) = credentials {
+        kept.push(format!("credentials={}", path.display()));
+    }
+
+    let options = if kept.is_empty() {
+        "defaults".to_string()
+    } else {
+        kept.join(",")
+    };
+    (options, had_password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_cifs_source() {
+        assert_eq!(normalize_cifs_source("\\\\server\\share"), "//server/share");
+        assert_eq!(
+            normalize_cifs_source("\\\\nas.corp\\media\\films"),
+            "//nas.corp/media/films"
+        );
+        assert_eq!(normalize_cifs_source("//server/share"), "//server/share");
+    }
+
+    #[test]
+    fn test_rewrite_cifs_credentials_with_file() {
+        let (options, had_password) = rewrite_cifs_credentials(
+            "vers=3.1.1,username=alice,password=hunter2,domain=CORP,uid=1000",
+            Some(Path::new("/etc/samba/creds")),
+        );
+        assert_eq!(options, "vers=3.1.1,uid=1000,credentials=/etc/samba/creds");
+        assert!(had_password);
+    }
+
+    #[test]
+    fn test_rewrite_cifs_credentials_strips_password_without_file() {
+        let (options, had_password) =
+            rewrite_cifs_credentials("username=alice,pass=hunter2,uid=1000", None);
+        assert_eq!(options, "username=alice,uid=1000");
+        assert!(had_password);
+
+        let (options, had_password) = rewrite_cifs_credentials("username=alice", None);
+        assert_eq!(options, "username=alice");
+        assert!(!had_password);
+    }
+}
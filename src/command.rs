@@ -0,0 +1,219 @@
+//! Running external tools (blkid, findmnt) behind a swappable interface.
+//!
+//! Lookups take a [`CommandRunner`] so their output parsing can be tested
+//! with canned output and exit codes instead of the real binaries.
+
+use std::cell::RefCell;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Runs a command to completion and returns its output.
+pub trait CommandRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output>;
+}
+
+/// Runs commands with [`std::process::Command`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        Command::new(cmd).args(args).output()
+    }
+}
+
+/// Runs commands like [`SystemRunner`], killing any that outlive `timeout`.
+///
+/// A command still running at the deadline is killed and reported as an
+/// [`io::ErrorKind::TimedOut`] error. Output is collected after the command
+/// exits, so this suits tools with small output (blkid); one that fills the
+/// pipe buffer would block and time out.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutRunner {
+    pub timeout: Duration,
+}
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl CommandRunner for TimeoutRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        let mut child = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if child.try_wait()?.is_some() {
+                return child.wait_with_output();
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} timed out after {:?}", cmd, self.timeout),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Returns a fixed response to every command and records the calls.
+#[derive(Debug, Default)]
+pub struct MockRunner {
+    /// Exit code and stdout; `None` behaves like a missing binary
+    response: Option<(i32, Vec<u8>)>,
+    calls: RefCell<Vec<String>>,
+}
+
+impl MockRunner {
+    /// A runner whose commands exit with `code` after printing `stdout`.
+    pub fn new(code: i32, stdout: &str) -> Self {
+        Self {
+            response: Some((code, stdout.as_bytes().to_vec())),
+            calls: RefCell::default(),
+        }
+    }
+
+    /// A runner whose commands fail to start, as if not installed.
+    pub fn missing() -> Self {
+        Self::default()
+    }
+
+    /// Command lines run so far, each as `cmd arg1 arg2 ...`.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+}
+
+impl CommandRunner for MockRunner {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        let mut call = vec![cmd];
+        call.extend_from_slice(args);
+        self.calls.borrow_mut().push(call.join(" "));
+
+        match &self.response {
+            Some((code, stdout)) => Ok(Output {
+                status: ExitStatus::from_raw(code << 8),
+                stdout: stdout.clone(),
+                stderr: Vec::new(),
+            }),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: command not found", cmd),
+            )),
+        }
+    }
+}
+
+/// Forwards to another runner, keeping a transcript of each command and
+/// its result (`--explain-entry`).
+pub struct TracingRunner<'a> {
+    inner: &'a dyn CommandRunner,
+    transcript: RefCell<Vec<String>>,
+}
+
+impl<'a> TracingRunner<'a> {
+    /// Trace commands run through `inner`.
+    pub fn new(inner: &'a dyn CommandRunner) -> Self {
+        Self {
+            inner,
+            transcript: RefCell::default(),
+        }
+    }
+
+    /// One line per command run so far, e.g.
+    /// `blkid -s UUID -o value /dev/sda2 -> exit 0: a1b2`.
+    pub fn transcript(&self) -> Vec<String> {
+        self.transcript.borrow().clone()
+    }
+}
+
+impl CommandRunner for TracingRunner<'_> {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        let result = self.inner.run(cmd, args);
+        let outcome = match &result {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stdout: Vec<&str> = stdout.lines().collect();
+                let status = match output.status.code() {
+                    Some(code) => format!("exit {}", code),
+                    None => "killed".to_string(),
+                };
+                if stdout.is_empty() {
+                    format!("{}, no output", status)
+                } else {
+                    format!("{}: {}", status, stdout.join(" "))
+                }
+            }
+            Err(e) => format!("failed: {}", e),
+        };
+        let mut line = vec![cmd];
+        line.extend_from_slice(args);
+        self.transcript
+            .borrow_mut()
+            .push(format!("{} -> {}", line.join(" "), outcome));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_runner_records_calls() {
+        let runner = MockRunner::new(2, "out");
+        let output = runner.run("blkid", &["-s", "UUID"]).unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(output.stdout, b"out");
+        assert_eq!(runner.calls(), ["blkid -s UUID"]);
+
+        let err = MockRunner::missing().run("blkid", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_tracing_runner_transcript() {
+        let inner = MockRunner::new(0, "a1b2\n");
+        let runner = TracingRunner::new(&inner);
+        runner.run("blkid", &["-s", "UUID", "/dev/sda2"]).unwrap();
+        assert_eq!(
+            runner.transcript(),
+            ["blkid -s UUID /dev/sda2 -> exit 0: a1b2"]
+        );
+
+        let missing = MockRunner::missing();
+        let runner = TracingRunner::new(&missing);
+        assert!(runner.run("blkid", &[]).is_err());
+        assert_eq!(
+            runner.transcript(),
+            ["blkid -> failed: blkid: command not found"]
+        );
+    }
+
+    #[test]
+    fn test_timeout_runner() {
+        let runner = TimeoutRunner {
+            timeout: Duration::from_millis(100),
+        };
+        let started = Instant::now();
+        let err = runner.run("sleep", &["5"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        let runner = TimeoutRunner {
+            timeout: Duration::from_secs(5),
+        };
+        let output = runner.run("echo", &["done"]).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"done\n");
+    }
+}
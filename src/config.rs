@@ -0,0 +1,436 @@
+//! Generation settings shared by the CLI and library callers.
+
+use crate::device::{BlkidOptions, FallbackFormat, IdFormat, IdType, NamingScheme};
+#[cfg(feature = "regex")]
+use crate::filter::OptionPattern;
+use crate::fstab::{MountpointCase, OutputEncoding};
+use crate::generate::{DedupeBy, GroupBy};
+use crate::log;
+use crate::merge::MergePolicy;
+use crate::mount::FindmntOptions;
+use crate::swap::{SwapIdType, SwapMountpoint};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Preset bundles of settings for common setups (`--profile`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// `nofail` on removable media, automount for network filesystems
+    Desktop,
+    /// `_netdev` on network filesystems, fail on identifier fallback
+    Server,
+    /// No swap entries and no comments
+    Minimal,
+}
+
+impl Profile {
+    /// The settings this profile starts from; explicit flags are applied on top.
+    pub fn config(self) -> Config {
+        let mut config = Config::default();
+        self.apply(&mut config);
+        config
+    }
+
+    /// Turn on this profile's settings in `config`, leaving the rest as is.
+    pub fn apply(self, config: &mut Config) {
+        match self {
+            Profile::Desktop => {
+                config.nofail_removable = true;
+                config.automount_network = true;
+            }
+            Profile::Server => {
+                config.netdev = true;
+                config.fail_on_fallback = true;
+            }
+            Profile::Minimal => {
+                config.no_swap = true;
+                config.no_comments = true;
+            }
+        }
+    }
+}
+
+/// Settings controlling how fstab entries are generated.
+///
+/// `Config::default()` matches the behavior of running `recfstab <ROOT>`
+/// with no flags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Identifier type used for the fs_spec field (UUID, LABEL, ...)
+    pub id_type: IdType,
+    /// Identifier types to try in order, using the first the device has
+    /// (`--id-chain`); empty means just `id_type`
+    pub id_chain: Vec<IdType>,
+    /// Identifier type for devices on removable media, overriding `id_type`
+    /// and `id_chain`
+    pub removable_id: Option<IdType>,
+    /// Identifier type for swap entries, overriding `id_type`
+    pub swap_id_type: Option<SwapIdType>,
+    /// Mountpoint field for swap entries; `None` keeps `none` for devices
+    /// and the path for swap files
+    pub swap_mountpoint: Option<SwapMountpoint>,
+    /// Warn when more swap entries than this are generated
+    pub max_swaps: Option<usize>,
+    /// Write identifiers as `TAG=value` or as `/dev/disk/by-*` paths
+    pub id_format: IdFormat,
+    /// How a device with no identifier is written (`/dev/sda1`, by-id, by-path)
+    pub fallback_format: FallbackFormat,
+    /// Fail instead of writing a device path when an identifier is missing
+    pub fail_on_fallback: bool,
+    /// Fail if no entry for `/` is generated (root not mounted under the scan root)
+    pub require_root: bool,
+    /// Which findmnt column mount options are read from
+    pub findmnt_options: FindmntOptions,
+    /// Have findmnt list only the root's filesystem and its submounts
+    /// (`--submounts --target ROOT`) instead of every mount
+    pub findmnt_scope: bool,
+    /// Which mounts count as duplicates of each other
+    pub dedupe_by: DedupeBy,
+    /// Process mounts one at a time and write each entry immediately.
+    ///
+    /// Keeps memory bounded on hosts with thousands of mounts. Only the
+    /// dedup set is retained, so features that need the full entry list
+    /// (sorting, merging) are unavailable in this mode.
+    pub streaming: bool,
+    /// Compute pass numbers like Arch's `genfstab` instead of recfstab's rules
+    pub genfstab_compat: bool,
+    /// Map btrfs swapfiles through the mount table to find their target
+    /// under the root
+    pub resolve_swapfile: bool,
+    /// Mount the root entry read-only (`ro`) and note it in a comment
+    pub root_ro: bool,
+    /// Options written verbatim for the `/` entry instead of the filtered ones
+    pub root_options: Option<String>,
+    /// Keep tmpfs mounts with an explicit `size=` instead of filtering them
+    pub keep_sized_tmpfs: bool,
+    /// Keep mounts whose target is a regular file (single-file bind mounts,
+    /// e.g. a container's `/etc/resolv.conf`), which are skipped by default
+    pub keep_file_binds: bool,
+    /// Mounts come from a device list (`--devices-from`) rather than the
+    /// mount table, so their targets are not looked at on this host
+    pub device_list: bool,
+    /// Skip the EFI System Partition (installing for a BIOS/legacy target)
+    pub no_esp: bool,
+    /// Keep `ro` on read-only mounts (which then get fsck pass 0)
+    pub keep_ro: bool,
+    /// Keep `ro` or `rw`, whichever the mount has, instead of dropping both
+    pub preserve_mount_state: bool,
+    /// Sort each entry's mount options alphabetically
+    pub sort_options: bool,
+    /// Write generic options (`nodev`, `noatime`, ...) before fs-specific
+    /// ones (`compress=`, `subvol=`)
+    pub normalize_option_order: bool,
+    /// Drop every mount option matching this pattern
+    #[cfg(feature = "regex")]
+    pub strip_options: Option<OptionPattern>,
+    /// Rebuild btrfs options from FSROOT: `subvol=` always present,
+    /// `subvolid=` dropped, no `btrfs` lookups
+    pub prefer_fstab_options: bool,
+    /// For an overlay root, emit the filesystem backing its lower layer as
+    /// `/`
+    pub overlay_lower: bool,
+    /// Add commented template entries for btrfs subvolumes that are not
+    /// mounted
+    pub btrfs_subvol_templates: bool,
+    /// Keep NFS entries on the negotiated protocol version, with a matching
+    /// fstype (`nfs4` for 4.x)
+    pub pin_nfs_version: bool,
+    /// Credentials file that replaces inline CIFS login options
+    pub cifs_credentials: Option<PathBuf>,
+    /// Add `nofail` to devices on removable media so boot doesn't wait for them
+    pub nofail_removable: bool,
+    /// Add `_netdev` to network filesystems
+    pub netdev: bool,
+    /// Add `x-systemd.automount` to network filesystems
+    pub automount_network: bool,
+    /// Leave swap out of the output
+    pub no_swap: bool,
+    /// Write entries without comment lines
+    pub no_comments: bool,
+    /// Add `x-mount.mkdir` to non-root entries so systemd creates missing
+    /// mountpoints
+    pub mkdir: bool,
+    /// Mountpoints (relative to the root) whose entries get
+    /// `x-initrd.mount`, so the initramfs mounts them before switching root
+    pub initrd_mount: Vec<String>,
+    /// Add `x-initrd.mount` to a separate `/usr` entry, which systemd
+    /// expects the initramfs to mount
+    pub auto_initrd_usr: bool,
+    /// Naming schemes to try, in order, for the device in each entry's
+    /// comment; empty keeps the source as findmnt reports it
+    pub comment_source_priority: Vec<NamingScheme>,
+    /// Add blkid's TYPE/SEC_TYPE to each entry's source comment
+    pub blkid_comment: bool,
+    /// Note which driver an ambiguous fstype (ntfs, exfat, ...) assumes
+    pub annotate_fstype_driver: bool,
+    /// Letter case of generated mountpoints (swapfile paths are untouched)
+    pub mountpoint_case: MountpointCase,
+    /// Path the target system's `/` will be mounted at; mount and swapfile
+    /// targets are rebased under it
+    pub target_prefix: Option<String>,
+    /// Use the root path as given instead of resolving symlinks in it.
+    ///
+    /// Mount targets are canonical paths, so mounts reached through a
+    /// symlink in the root will not match.
+    pub no_canonicalize: bool,
+    /// Warn about generated mountpoints with no directory under this
+    /// target root (`--check-mountpoints`)
+    pub check_mountpoints: Option<PathBuf>,
+    /// Fail (E012, E016, E018, E019) instead of warning about missing
+    /// mountpoints, overlong lines, entries with no options left and shared
+    /// LABELs
+    pub strict: bool,
+    /// Warn about entries whose line is longer than this many characters
+    pub max_line_length: Option<usize>,
+    /// Write `rw` instead of `defaults` when filtering drops every option,
+    /// and warn about the entry
+    pub no_defaults_fallback: bool,
+    /// Warn when entries on different devices resolved to the same LABEL
+    pub label_collision_check: bool,
+    /// Don't warn about entries with an unrecognized fstype
+    pub skip_fstype_warnings: bool,
+    /// Warn on stderr about entries with `noauto`, which will not mount at boot
+    pub warn_noauto: bool,
+    /// End the output with a `# recfstab: ...` comment counting generated
+    /// and skipped entries (see [`crate::summary`])
+    pub summary_comment: bool,
+    /// Start the output with the header of Arch's stock fstab
+    pub template_header: bool,
+    /// How non-ASCII characters in paths are written
+    pub output_encoding: OutputEncoding,
+    /// Re-parse every generated entry before writing it (always on in
+    /// debug builds)
+    pub self_check: bool,
+    /// Read identifiers from the devices instead of blkid's cache file
+    /// (slower: every device is probed)
+    pub no_blkid_cache: bool,
+    /// Give up on a blkid lookup after this long and use the device path
+    pub blkid_timeout: Option<Duration>,
+    /// Drop entries whose data line repeats an earlier one, then sort by
+    /// mountpoint (on the final entry list, after merging)
+    pub sorted_unique: bool,
+    /// Gather entries under one comment header per device (see
+    /// [`crate::generate::group_by_device`]); ignored with `no_comments`
+    pub group_by: Option<GroupBy>,
+    /// Wrap comment lines at spaces to stay within this many columns
+    pub comment_width: Option<usize>,
+    /// File of per-mountpoint options that replace the filtered ones
+    /// (see [`crate::options_map`])
+    pub options_map: Option<PathBuf>,
+    /// File of per-mountpoint fsck pass numbers that replace the computed
+    /// ones (see [`crate::pass_map`])
+    pub pass_map: Option<PathBuf>,
+    /// Existing fstab to merge the generated entries into
+    pub merge: Option<PathBuf>,
+    /// Which entry wins when the merged fstab and generated set collide
+    pub merge_policy: MergePolicy,
+    /// Where sysfs is mounted (`/sys` when unset); tests point this at a fixture
+    pub sys_root: Option<PathBuf>,
+}
+
+impl Config {
+    /// Create a config using the given identifier type and default settings.
+    pub fn new(id_type: IdType) -> Self {
+        Self {
+            id_type,
+            ..Self::default()
+        }
+    }
+
+    /// Build a config from `RECFSTAB_*` environment variables.
+    ///
+    /// `RECFSTAB_ID_TYPE` takes an identifier type (`uuid`, `label`, ...);
+    /// `RECFSTAB_NO_SWAP`, `RECFSTAB_NO_COMMENTS`, `RECFSTAB_FAIL_ON_FALLBACK`,
+    /// `RECFSTAB_NOFAIL_REMOVABLE`, `RECFSTAB_NETDEV`, and
+    /// `RECFSTAB_AUTOMOUNT_NETWORK` set the matching flags.
+    ///
+    /// Unset variables keep their defaults. Boolean variables take
+    /// `1`/`true`/`yes`/`on` or `0`/`false`/`no`/`off`; an unrecognized value
+    /// is ignored with a warning.
+    pub fn from_env() -> Self {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// Like [`from_env`](Self::from_env), reading variables through `var`.
+    pub fn from_env_with<F>(var: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config = Self::default();
+        if let Some(value) = var("RECFSTAB_ID_TYPE") {
+            match <IdType as clap::ValueEnum>::from_str(value.trim(), true) {
+                Ok(id_type) => config.id_type = id_type,
+                Err(_) => warn_env("RECFSTAB_ID_TYPE", &value),
+            }
+        }
+        for (name, field) in [
+            ("RECFSTAB_NO_SWAP", &mut config.no_swap),
+            ("RECFSTAB_NO_COMMENTS", &mut config.no_comments),
+            ("RECFSTAB_FAIL_ON_FALLBACK", &mut config.fail_on_fallback),
+            ("RECFSTAB_NOFAIL_REMOVABLE", &mut config.nofail_removable),
+            ("RECFSTAB_NETDEV", &mut config.netdev),
+            ("RECFSTAB_AUTOMOUNT_NETWORK", &mut config.automount_network),
+        ] {
+            if let Some(value) = var(name) {
+                match parse_env_bool(&value) {
+                    Some(flag) => *field = flag,
+                    None => warn_env(name, &value),
+                }
+            }
+        }
+        config
+    }
+
+    /// Whether entries are written as mounts are read.
+    ///
+    /// `streaming` is ignored when a setting needs the full mount table
+    /// (`overlay_lower`, `btrfs_subvol_templates`, `resolve_swapfile`) or the
+    /// full entry list (`merge`, `options_map`, `pass_map`, `sorted_unique`,
+    /// `group_by`, `label_collision_check`, `check_mountpoints`).
+    pub fn streams(&self) -> bool {
+        self.streaming
+            && !self.overlay_lower
+            && !self.btrfs_subvol_templates
+            && !self.resolve_swapfile
+            && self.merge.is_none()
+            && self.options_map.is_none()
+            && self.pass_map.is_none()
+            && !self.sorted_unique
+            && self.group_by.is_none()
+            && !self.label_collision_check
+            && self.check_mountpoints.is_none()
+    }
+
+    /// How blkid is run for identifier lookups.
+    pub fn blkid(&self) -> BlkidOptions {
+        BlkidOptions {
+            no_cache: self.no_blkid_cache,
+            timeout: self.blkid_timeout,
+        }
+    }
+
+    /// Path sysfs lookups read from.
+    pub fn sys_root(&self) -> &Path {
+        self.sys_root
+            .as_deref()
+            .unwrap_or(Path::new(crate::sysfs::SYS_ROOT))
+    }
+}
+
+fn parse_env_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+fn warn_env(name: &str, value: &str) {
+    log::warning(
+        "env-ignored",
+        &format!("ignoring {}={:?}: unrecognized value", name, value),
+        &[("variable", name), ("value", value)],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_default() {
+        let config = Config::default();
+        assert_eq!(config.id_type, IdType::Uuid);
+        assert!(!config.streaming);
+    }
+
+    #[test]
+    fn test_profile_configs() {
+        let desktop = Profile::Desktop.config();
+        assert!(desktop.nofail_removable && desktop.automount_network);
+        assert!(!desktop.netdev && !desktop.fail_on_fallback);
+
+        let server = Profile::Server.config();
+        assert!(server.netdev && server.fail_on_fallback);
+        assert!(!server.nofail_removable && !server.automount_network);
+
+        let minimal = Profile::Minimal.config();
+        assert!(minimal.no_swap && minimal.no_comments);
+        assert_eq!(
+            Config {
+                no_swap: false,
+                no_comments: false,
+                ..minimal
+            },
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            Config::from_env_with(move |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        assert_eq!(env(&[]), Config::default());
+
+        let config = env(&[
+            ("RECFSTAB_ID_TYPE", "PARTUUID"),
+            ("RECFSTAB_NO_SWAP", "1"),
+            ("RECFSTAB_NETDEV", "yes"),
+            ("RECFSTAB_NO_COMMENTS", "off"),
+        ]);
+        assert_eq!(config.id_type, IdType::Partuuid);
+        assert!(config.no_swap && config.netdev && !config.no_comments);
+
+        // Unrecognized values keep the default
+        let config = env(&[
+            ("RECFSTAB_ID_TYPE", "serial"),
+            ("RECFSTAB_NO_SWAP", "maybe"),
+        ]);
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_profile_applies_over_env() {
+        let mut config =
+            Config::from_env_with(|name| (name == "RECFSTAB_NO_SWAP").then(|| "true".to_string()));
+        Profile::Desktop.apply(&mut config);
+        assert!(config.no_swap && config.nofail_removable && config.automount_network);
+    }
+
+    #[test]
+    fn test_config_new() {
+        let config = Config::new(IdType::Label);
+        assert_eq!(config.id_type, IdType::Label);
+        assert!(!config.streaming);
+    }
+
+    #[test]
+    fn test_streams_needs_no_full_mount_table() {
+        let streaming = Config {
+            streaming: true,
+            ..Config::default()
+        };
+        assert!(streaming.streams());
+        assert!(!Config {
+            overlay_lower: true,
+            ..streaming.clone()
+        }
+        .streams());
+        assert!(!Config {
+            resolve_swapfile: true,
+            ..streaming.clone()
+        }
+        .streams());
+        assert!(!Config {
+            sorted_unique: true,
+            ..streaming
+        }
+        .streams());
+    }
+}
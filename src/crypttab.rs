@@ -0,0 +1,208 @@
+//! crypttab generation for LUKS devices backing the target's mounts.
+//!
+//! A mount whose source is a dm-crypt mapping (`/dev/mapper/<name>` or
+//! `/dev/dm-N`) needs a crypttab line so the mapping exists at boot. The
+//! LUKS header UUID is recovered from the device-mapper UUID in sysfs
+//! (`CRYPT-LUKS2-<uuid>-<name>`), so no cryptsetup call is needed.
+
+use crate::config::Config;
+use crate::device::extract_device_path;
+use crate::generate::MountFilter;
+use crate::mount::MountInfo;
+use crate::swap::{is_swap_under_root, SwapInfo};
+use crate::sysfs::block_attr_in;
+use std::fs;
+use std::path::Path;
+
+/// A single crypttab line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrypttabEntry {
+    /// Mapping name under /dev/mapper
+    pub name: String,
+    /// Encrypted block device, as `UUID=<luks uuid>`
+    pub device: String,
+    /// Key file, or `none` to prompt at boot
+    pub password: String,
+    /// crypttab options
+    pub options: String,
+}
+
+impl CrypttabEntry {
+    /// Render the entry as a tab-separated crypttab line with newline.
+    pub fn render(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.name, self.device, self.password, self.options
+        )
+    }
+}
+
+/// Extract the LUKS UUID from a device-mapper UUID.
+///
+/// `CRYPT-LUKS2-1b4e28ba2fa1411f9b57d8a3c1e7e3c9-root` becomes
+/// `1b4e28ba-2fa1-411f-9b57-d8a3c1e7e3c9`. Returns `None` for non-LUKS
+/// mappings (plain dm-crypt, LVM, ...), which have no stable header UUID.
+pub fn luks_uuid_from_dm_uuid(dm_uuid: &str) -> Option<String> {
+    let rest = dm_uuid
+        .strip_prefix("CRYPT-LUKS2-")
+        .or_else(|| dm_uuid.strip_prefix("CRYPT-LUKS1-"))?;
+    let hex = rest.split('-').next()?;
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Find the sysfs name (`dm-N`) of a device-mapper device path.
+///
+/// `class/block` is scanned in name order, so the answer does not depend on
+/// directory iteration order.
+fn dm_kernel_name(sys_root: &Path, device: &str) -> Option<String> {
+    if let Some(name) = device.strip_prefix("/dev/") {
+        if name.starts_with("dm-") {
+            return Some(name.to_string());
+        }
+    }
+    let mapping = device.strip_prefix("/dev/mapper/")?;
+    let mut names: Vec<String> = fs::read_dir(sys_root.join("class/block"))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("dm-"))
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .find(|name| block_attr_in(sys_root, name, "dm/name").as_deref() == Some(mapping))
+}
+
+/// Build the crypttab entry for a mount or swap source, if it is on LUKS.
+pub fn crypttab_entry_in(sys_root: &Path, source: &str) -> Option<CrypttabEntry> {
+    let device = extract_device_path(source);
+    let kernel_name = dm_kernel_name(sys_root, device)?;
+    let name = block_attr_in(sys_root, &kernel_name, "dm/name")?;
+    let dm_uuid = block_attr_in(sys_root, &kernel_name, "dm/uuid")?;
+    let uuid = luks_uuid_from_dm_uuid(&dm_uuid)?;
+
+    Some(CrypttabEntry {
+        name,
+        device: format!("UUID={}", uuid),
+        password: "none".to_string(),
+        options: "luks".to_string(),
+    })
+}
+
+/// Build crypttab entries for every LUKS device behind an fstab entry.
+///
+/// Uses the same mount selection as fstab generation; each mapping is
+/// listed once even if several mounts (e.g. btrfs subvolumes) share it.
+pub fn crypttab_entries_in(
+    sys_root: &Path,
+    mounts: &[MountInfo],
+    swaps: &[SwapInfo],
+    root_str: &str,
+    config: &Config,
+) -> Vec<CrypttabEntry> {
+    let mut filter = MountFilter::for_config(root_str, config);
+    let sources = mounts
+        .iter()
+        .filter(|mount| filter.accept(mount))
+        .map(|mount| mount.source.as_str())
+        .chain(
+            swaps
+                .iter()
+                .filter(|swap| is_swap_under_root(swap, root_str))
+                .map(|swap| swap.filename.as_str()),
+        );
+
+    let mut entries: Vec<CrypttabEntry> = Vec::new();
+    for source in sources {
+        if let Some(entry) = crypttab_entry_in(sys_root, source) {
+            if !entries.iter().any(|e| e.name == entry.name) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// sysfs fixture with LUKS2 mapping `cryptroot` (dm-0) and LVM `vg-home` (dm-1).
+    pub(crate) fn sysfs_fixture(name: &str) -> PathBuf {
+        let sys = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&sys);
+        for (dev, dm_name, dm_uuid) in [
+            (
+                "dm-0",
+                "cryptroot",
+                "CRYPT-LUKS2-1b4e28ba2fa1411f9b57d8a3c1e7e3c9-cryptroot",
+            ),
+            ("dm-1", "vg-home", "LVM-abcdef"),
+        ] {
+            let dm = sys.join("class/block").join(dev).join("dm");
+            fs::create_dir_all(&dm).unwrap();
+            fs::write(dm.join("name"), format!("{}\n", dm_name)).unwrap();
+            fs::write(dm.join("uuid"), format!("{}\n", dm_uuid)).unwrap();
+        }
+        sys
+    }
+
+    #[test]
+    fn test_luks_uuid_from_dm_uuid() {
+        assert_eq!(
+            luks_uuid_from_dm_uuid("CRYPT-LUKS2-1b4e28ba2fa1411f9b57d8a3c1e7e3c9-root").as_deref(),
+            Some("1b4e28ba-2fa1-411f-9b57-d8a3c1e7e3c9")
+        );
+        assert!(luks_uuid_from_dm_uuid("CRYPT-PLAIN-root").is_none());
+        assert!(luks_uuid_from_dm_uuid("LVM-abcdef").is_none());
+        assert!(luks_uuid_from_dm_uuid("CRYPT-LUKS2-short-root").is_none());
+    }
+
+    #[test]
+    fn test_crypttab_entry_in() {
+        let sys = sysfs_fixture("recfstab_test_crypttab_entry");
+        let entry = crypttab_entry_in(&sys, "/dev/mapper/cryptroot[/@]").unwrap();
+        assert_eq!(
+            entry.render(),
+            "cryptroot\tUUID=1b4e28ba-2fa1-411f-9b57-d8a3c1e7e3c9\tnone\tluks\n"
+        );
+        assert_eq!(crypttab_entry_in(&sys, "/dev/dm-0"), Some(entry));
+        // LVM and plain partitions are not crypttab material
+        assert!(crypttab_entry_in(&sys, "/dev/mapper/vg-home").is_none());
+        assert!(crypttab_entry_in(&sys, "/dev/sda1").is_none());
+        let _ = fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_crypttab_entries_dedup_subvolumes() {
+        let sys = sysfs_fixture("recfstab_test_crypttab_entries");
+        let mount = |target: &str, source: &str| MountInfo {
+            target: target.to_string(),
+            source: source.to_string(),
+            fstype: "btrfs".to_string(),
+            options: "rw".to_string(),
+            ..MountInfo::default()
+        };
+        let mounts = vec![
+            mount("/mnt", "/dev/mapper/cryptroot[/@]"),
+            mount("/mnt/home", "/dev/mapper/cryptroot[/@home]"),
+            mount("/mnt/srv", "/dev/mapper/vg-home"),
+            mount("/other", "/dev/dm-0"),
+        ];
+        let entries = crypttab_entries_in(&sys, &mounts, &[], "/mnt", &Config::default());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "cryptroot");
+        let _ = fs::remove_dir_all(&sys);
+    }
+}
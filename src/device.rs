@@ -1,9 +1,17 @@
 //! Device identifier lookup (UUID/LABEL/PARTUUID/PARTLABEL).
 
-use std::process::Command;
+use crate::command::{CommandRunner, SystemRunner, TimeoutRunner};
+use crate::log;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Output;
+use std::time::Duration;
 
 /// Device identifier type for fstab entries.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
 pub enum IdType {
     /// Use filesystem UUID (default)
     #[default]
@@ -17,6 +25,30 @@ pub enum IdType {
 }
 
 impl IdType {
+    /// All identifier types, in CLI flag order.
+    pub const ALL: [IdType; 4] = [
+        IdType::Uuid,
+        IdType::Label,
+        IdType::Partuuid,
+        IdType::Partlabel,
+    ];
+
+    /// Short explanation of what the identifier refers to and when it changes.
+    pub fn description(&self) -> &'static str {
+        match self {
+            IdType::Uuid => "filesystem UUID; stable until the filesystem is recreated (mkfs)",
+            IdType::Label => {
+                "filesystem label; human-readable, but not guaranteed unique across disks"
+            }
+            IdType::Partuuid => {
+                "GPT partition UUID; survives reformatting the partition, changes if repartitioned"
+            }
+            IdType::Partlabel => {
+                "GPT partition name; human-readable, survives reformatting, not guaranteed unique"
+            }
+        }
+    }
+
     /// Get the blkid tag name for this identifier type.
     pub fn blkid_tag(&self) -> &'static str {
         match self {
@@ -38,6 +70,130 @@ impl IdType {
     }
 }
 
+/// How a resolved identifier is written in fs_spec (`--id-format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum IdFormat {
+    /// Tag syntax, e.g. `UUID=abcd-1234` (default)
+    #[default]
+    Tag,
+    /// udev symlink, e.g. `/dev/disk/by-uuid/abcd-1234`
+    Path,
+}
+
+impl IdFormat {
+    /// Write `spec` in this format; specs that are not `TAG=value`
+    /// identifiers (device paths, network sources) are returned unchanged.
+    pub fn apply(&self, spec: &str) -> String {
+        match self {
+            IdFormat::Tag => spec.to_string(),
+            IdFormat::Path => identifier_path(spec).unwrap_or_else(|| spec.to_string()),
+        }
+    }
+}
+
+/// The `/dev/disk/by-*` symlink for a `TAG=value` identifier.
+///
+/// `LABEL=root` becomes `/dev/disk/by-label/root`. The value is encoded as
+/// udev names the link (see [`encode_udev_name`]), so `LABEL=my disk` maps to
+/// `/dev/disk/by-label/my\x20disk`. Returns `None` for anything else.
+pub fn identifier_path(spec: &str) -> Option<String> {
+    let (tag, value) = spec.split_once('=')?;
+    let id_type = IdType::ALL
+        .into_iter()
+        .find(|id_type| id_type.fstab_prefix() == tag)?;
+    if value.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "/dev/disk/by-{}/{}",
+        id_type.fstab_prefix().to_ascii_lowercase(),
+        encode_udev_name(value)
+    ))
+}
+
+/// Encode a value the way udev does for `/dev/disk/by-*` link names.
+///
+/// ASCII letters, digits, `#+-.:=@_`, and non-ASCII UTF-8 characters are
+/// kept; everything else (spaces, `/`, `\`, ...) becomes `\xNN`.
+pub fn encode_udev_name(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || "#+-.:=@_".contains(c) || !c.is_ascii() {
+            out.push(c);
+        } else {
+            out.push_str(&format!("\\x{:02x}", c as u32));
+        }
+    }
+    out
+}
+
+/// Reverse [`encode_udev_name`]: decode `\xNN` escapes in a link name.
+pub fn decode_udev_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') {
+            let hex = bytes
+                .get(i + 2..i + 4)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = hex {
+                out.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Read the identifier straight from a `/dev/disk/by-*` source path.
+///
+/// `/dev/disk/by-uuid/abc` gives `UUID=abc` without running blkid, but only
+/// when `tag` asks for that identifier type; a by-label link with a UUID
+/// request returns `None` so the caller looks the UUID up instead.
+pub fn identifier_from_disk_path(device: &str, tag: &str) -> Option<String> {
+    let rest = device.strip_prefix("/dev/disk/by-")?;
+    let (kind, name) = rest.split_once('/')?;
+    if name.is_empty() || name.contains('/') || !kind.eq_ignore_ascii_case(tag) {
+        return None;
+    }
+    let id_type = IdType::ALL
+        .into_iter()
+        .find(|id_type| id_type.blkid_tag() == tag)?;
+    Some(format!(
+        "{}={}",
+        id_type.fstab_prefix(),
+        decode_udev_name(name)
+    ))
+}
+
+/// How a block device is written when no identifier can be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum FallbackFormat {
+    /// Kernel name, e.g. `/dev/sda1` (default)
+    #[default]
+    Dev,
+    /// Stable hardware name from `/dev/disk/by-id`
+    ById,
+    /// Bus topology name from `/dev/disk/by-path`
+    ByPath,
+}
+
+impl FallbackFormat {
+    /// The `/dev/disk` subdirectory holding this format's symlinks.
+    pub fn disk_dir(&self) -> Option<&'static str> {
+        match self {
+            FallbackFormat::Dev => None,
+            FallbackFormat::ById => Some("by-id"),
+            FallbackFormat::ByPath => Some("by-path"),
+        }
+    }
+}
+
 /// Extract the base device path from a source string.
 ///
 /// Handles btrfs subvolume notation like `/dev/sda1[/subvol]`.
@@ -57,6 +213,127 @@ pub fn extract_device_path(source: &str) -> &str {
     }
 }
 
+/// What a mount (or swap) source names, see [`classify_source`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    /// A device node, e.g. `/dev/sda1` or `/dev/disk/by-id/...`
+    Block,
+    /// A device node with a `[/path]` filesystem root, e.g.
+    /// `/dev/sda2[/@home]`: a btrfs subvolume, or a bind mount of a
+    /// directory on the device
+    BtrfsSubvol,
+    /// A remote share: `server:/export`, `//server/share` or
+    /// `\\server\share`
+    Network,
+    /// Any other absolute path: the directory behind a bind mount, or a
+    /// swap file
+    Bind,
+    /// Already an fstab identifier (`UUID=`, `LABEL=`, `PARTUUID=`,
+    /// `PARTLABEL=`)
+    Identifier,
+    /// No device: empty or `none`
+    None,
+    /// A pseudo or virtual source such as `tmpfs`, `proc` or `overlay`
+    Other,
+}
+
+impl SourceKind {
+    /// Check whether the source is a device node blkid can look up.
+    pub fn is_block(self) -> bool {
+        matches!(self, SourceKind::Block | SourceKind::BtrfsSubvol)
+    }
+}
+
+/// Identifier prefixes an fstab source can already carry.
+const IDENTIFIER_PREFIXES: &[&str] = &["UUID=", "LABEL=", "PARTUUID=", "PARTLABEL="];
+
+/// Classify a mount or swap source string (see [`SourceKind`]).
+pub fn classify_source(source: &str) -> SourceKind {
+    let source = source.trim();
+    if matches!(source, "" | "none") {
+        SourceKind::None
+    } else if IDENTIFIER_PREFIXES
+        .iter()
+        .any(|prefix| source.starts_with(prefix))
+    {
+        SourceKind::Identifier
+    } else if source.starts_with("/dev/") {
+        if subvol_from_source(source).is_some() {
+            SourceKind::BtrfsSubvol
+        } else {
+            SourceKind::Block
+        }
+    } else if source.starts_with("//")
+        || source.starts_with("\\\\")
+        || (!source.starts_with('/') && source.contains(':'))
+    {
+        SourceKind::Network
+    } else if source.starts_with('/') {
+        SourceKind::Bind
+    } else {
+        SourceKind::Other
+    }
+}
+
+/// The `/dev/...` block device behind a mount or swap source, if any.
+///
+/// Returns `/dev/sda1` for `/dev/sda1[/@home]` and None for `tmpfs`,
+/// `server:/share` or a swap file.
+pub fn block_device_path(source: &str) -> Option<&str> {
+    classify_source(source)
+        .is_block()
+        .then(|| extract_device_path(source))
+}
+
+/// Extract the subvolume (filesystem root) from a source string.
+///
+/// Returns `/@home` for `/dev/sda1[/@home]`, or `None` when the source has no
+/// bracket notation (the mount exposes the filesystem's top level).
+pub fn subvol_from_source(source: &str) -> Option<&str> {
+    let start = source.find('[')?;
+    let end = source.rfind(']')?;
+    if end <= start {
+        return None;
+    }
+    Some(&source[start + 1..end])
+}
+
+/// Maximum label length in bytes accepted by each filesystem's mkfs/label tool.
+pub const LABEL_MAX_LENGTHS: &[(&str, usize)] = &[
+    ("btrfs", 255),
+    ("exfat", 15),
+    ("ext2", 16),
+    ("ext3", 16),
+    ("ext4", 16),
+    ("f2fs", 512),
+    ("fat", 11),
+    ("msdos", 11),
+    ("swap", 16),
+    ("vfat", 11),
+    ("xfs", 12),
+];
+
+/// Warn when a LABEL is longer than its filesystem can store.
+///
+/// Such a label was truncated on disk (or set by another tool) and the
+/// `LABEL=` entry may not match at boot. `label` may include the `LABEL=`
+/// prefix. Returns `None` for acceptable labels and unknown filesystems.
+pub fn label_length_warning(label: &str, fstype: &str) -> Option<String> {
+    let label = label.strip_prefix("LABEL=").unwrap_or(label);
+    let (_, max) = LABEL_MAX_LENGTHS.iter().find(|(fs, _)| *fs == fstype)?;
+    if label.len() > *max {
+        Some(format!(
+            "LABEL '{}' is {} bytes, longer than the {}-byte limit for {}; it may not match at boot",
+            label,
+            label.len(),
+            max,
+            fstype
+        ))
+    } else {
+        None
+    }
+}
+
 /// Get the device identifier (UUID/LABEL/PARTUUID/PARTLABEL) for a source device.
 ///
 /// Falls back to the device path if identifier lookup fails.
@@ -66,40 +343,174 @@ pub fn extract_device_path(source: &str) -> &str {
 /// * `source` - The device source string (e.g., "/dev/sda1", "/dev/sda1[/subvol]")
 /// * `id_type` - The identifier type to use ("UUID", "LABEL", "PARTUUID", "PARTLABEL")
 pub fn get_device_identifier(source: &str, id_type: &str) -> String {
+    get_device_identifier_with(source, id_type, lookup_device_id)
+}
+
+/// Like [`get_device_identifier`], but resolves block devices through `lookup`.
+///
+/// `lookup` receives the device path and blkid tag and returns the
+/// `TAG=value` identifier, or `None` to fall back to the device path.
+pub fn get_device_identifier_with<F>(source: &str, id_type: &str, lookup: F) -> String
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
     // Handle empty source gracefully
     if source.is_empty() {
         return "none".to_string();
     }
 
-    // Already has an identifier - preserve it
-    if source.starts_with("UUID=")
-        || source.starts_with("LABEL=")
-        || source.starts_with("PARTUUID=")
-        || source.starts_with("PARTLABEL=")
-    {
-        return source.to_string();
-    }
-
-    let device = extract_device_path(source);
-
-    // Handle empty device after extraction
-    if device.is_empty() {
-        return source.to_string();
-    }
-
     // Look up identifier for block devices
-    if device.starts_with("/dev/") {
-        if let Some(id) = lookup_device_id(device, id_type) {
+    if let Some(device) = block_device_path(source) {
+        // A /dev/disk/by-* link already names the identifier
+        if let Some(id) = identifier_from_disk_path(device, id_type) {
+            return id;
+        }
+        if let Some(id) = lookup(device, id_type) {
             return id;
         }
         // Fall back to device path if no identifier found
         return device.to_string();
     }
 
-    // For other sources (bind mounts, network mounts), use as-is
+    // Identifiers, bind mounts and network mounts are used as-is
     source.to_string()
 }
 
+/// Wrap `lookup` to try each tag in `chain` in order (`--id-chain`).
+///
+/// The tag passed to the returned lookup is ignored, so it can stand in for
+/// a single-tag lookup anywhere one is accepted.
+pub fn chain_lookup<'a, F>(
+    chain: &'a [IdType],
+    lookup: F,
+) -> impl Fn(&str, &str) -> Option<String> + 'a
+where
+    F: Fn(&str, &str) -> Option<String> + 'a,
+{
+    move |device, _| {
+        chain
+            .iter()
+            .find_map(|id_type| lookup(device, id_type.blkid_tag()))
+    }
+}
+
+/// Like [`get_device_identifier_with`], but writes the fallback device path
+/// in the given `format`, also reporting whether the lookup failed and a
+/// device path was returned instead of `id_type`.
+pub fn resolve_device_identifier<F>(
+    source: &str,
+    id_type: &str,
+    lookup: F,
+    format: FallbackFormat,
+) -> (String, bool)
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
+    let identifier = get_device_identifier_with(source, id_type, lookup);
+    // Only the fallback branch returns the bare /dev path
+    match block_device_path(source) {
+        Some(device) if identifier == device => (fallback_device_path(device, format), true),
+        _ => (identifier, false),
+    }
+}
+
+/// Rewrite a `/dev/<name>` path in the given fallback format.
+///
+/// Keeps the kernel name if no `/dev/disk/by-*` link points at the device.
+pub fn fallback_device_path(device: &str, format: FallbackFormat) -> String {
+    fallback_device_path_in(Path::new("/dev"), device, format)
+}
+
+/// Like [`fallback_device_path`], with device nodes under `dev_root`.
+///
+/// The link is picked by [`disk_link_in`].
+pub fn fallback_device_path_in(dev_root: &Path, device: &str, format: FallbackFormat) -> String {
+    format
+        .disk_dir()
+        .and_then(|dir| disk_link_in(dev_root, device, dir))
+        .unwrap_or_else(|| device.to_string())
+}
+
+/// The `/dev/disk/<dir>` link pointing at `device`, with device nodes under
+/// `dev_root`.
+///
+/// When several links match, the alphabetically first is used so output is
+/// stable across runs. Returns `None` if no link points at the device.
+pub fn disk_link_in(dev_root: &Path, device: &str, dir: &str) -> Option<String> {
+    let name = device.strip_prefix("/dev/")?;
+    let node = fs::canonicalize(dev_root.join(name)).ok()?;
+    let links = fs::read_dir(dev_root.join("disk").join(dir)).ok()?;
+
+    let mut matches: Vec<String> = links
+        .flatten()
+        .filter(|link| fs::canonicalize(link.path()).is_ok_and(|target| target == node))
+        .map(|link| link.file_name().to_string_lossy().into_owned())
+        .collect();
+    matches.sort();
+
+    matches
+        .first()
+        .map(|link| format!("/dev/disk/{}/{}", dir, link))
+}
+
+/// A way of naming a block device, for the comment line
+/// (`--comment-source-priority`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NamingScheme {
+    /// Hardware name from `/dev/disk/by-id`
+    ById,
+    /// Filesystem UUID link from `/dev/disk/by-uuid`
+    ByUuid,
+    /// Bus topology name from `/dev/disk/by-path`
+    ByPath,
+    /// Filesystem label link from `/dev/disk/by-label`
+    ByLabel,
+    /// Partition UUID link from `/dev/disk/by-partuuid`
+    ByPartuuid,
+    /// Partition label link from `/dev/disk/by-partlabel`
+    ByPartlabel,
+    /// Kernel name, e.g. `/dev/sda1`
+    Dev,
+}
+
+impl NamingScheme {
+    /// The `/dev/disk` subdirectory holding this scheme's symlinks.
+    pub fn disk_dir(&self) -> Option<&'static str> {
+        match self {
+            NamingScheme::ById => Some("by-id"),
+            NamingScheme::ByUuid => Some("by-uuid"),
+            NamingScheme::ByPath => Some("by-path"),
+            NamingScheme::ByLabel => Some("by-label"),
+            NamingScheme::ByPartuuid => Some("by-partuuid"),
+            NamingScheme::ByPartlabel => Some("by-partlabel"),
+            NamingScheme::Dev => None,
+        }
+    }
+}
+
+/// Name `source` by the first scheme in `priority` that has a name for it.
+///
+/// Only the device part of a `/dev/...` source is renamed; a `[/subvol]`
+/// suffix is kept. Sources with no matching name are returned unchanged.
+pub fn comment_source(source: &str, priority: &[NamingScheme]) -> String {
+    comment_source_in(Path::new("/dev"), source, priority)
+}
+
+/// Like [`comment_source`], with device nodes under `dev_root`.
+pub fn comment_source_in(dev_root: &Path, source: &str, priority: &[NamingScheme]) -> String {
+    let Some(device) = block_device_path(source) else {
+        return source.to_string();
+    };
+    let named = priority.iter().find_map(|scheme| match scheme.disk_dir() {
+        Some(dir) => disk_link_in(dev_root, device, dir),
+        None => Some(device.to_string()),
+    });
+    match named {
+        Some(name) => format!("{}{}", name, &source[device.len()..]),
+        None => source.to_string(),
+    }
+}
+
 /// Look up an identifier for a device using blkid.
 ///
 /// # Arguments
@@ -107,26 +518,363 @@ pub fn get_device_identifier(source: &str, id_type: &str) -> String {
 /// * `tag` - The blkid tag to look up ("UUID", "LABEL", "PARTUUID", "PARTLABEL")
 ///
 /// Returns None silently on failure - Unix philosophy: avoid unnecessary output.
-/// The caller handles fallback to device path.
+/// The caller handles fallback to device path. Use [`lookup_device_id_result`]
+/// to tell the failure modes apart.
 pub fn lookup_device_id(device: &str, tag: &str) -> Option<String> {
-    let output = Command::new("blkid")
-        .args(["-s", tag, "-o", "value", device])
-        .output()
-        .ok()?; // Silent failure - falls back to device path
-
-    if output.status.success() {
-        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if !value.is_empty() {
-            return Some(format!("{}={}", tag, value));
+    // Silent failure - falls back to device path
+    lookup_device_id_result(device, tag).ok().flatten()
+}
+
+/// How blkid is run for lookups (`--no-blkid-cache`, `--blkid-timeout`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlkidOptions {
+    /// Probe devices instead of reading blkid's cache
+    pub no_cache: bool,
+    /// Kill blkid after this long and fall back to the device path
+    pub timeout: Option<Duration>,
+}
+
+impl BlkidOptions {
+    /// The runner blkid goes through: [`TimeoutRunner`] when a timeout is
+    /// set, [`SystemRunner`] otherwise.
+    pub fn runner(&self) -> Box<dyn CommandRunner> {
+        match self.timeout {
+            Some(timeout) => Box::new(TimeoutRunner { timeout }),
+            None => Box::new(SystemRunner),
+        }
+    }
+
+    /// Look up `tag` for `device`, like [`lookup_device_id`].
+    ///
+    /// A lookup that times out warns on stderr; other failures stay silent.
+    pub fn lookup(&self, device: &str, tag: &str) -> Option<String> {
+        self.lookup_through(self.runner().as_ref(), device, tag)
+    }
+
+    /// Like [`lookup`](Self::lookup), but runs blkid through `runner`.
+    pub fn lookup_through(
+        &self,
+        runner: &dyn CommandRunner,
+        device: &str,
+        tag: &str,
+    ) -> Option<String> {
+        match self.lookup_with(runner, device, tag) {
+            Ok(value) => value,
+            Err(LookupError::Timeout) => {
+                log::warning(
+                    "blkid-timeout",
+                    &format!(
+                        "blkid did not answer for {} within {:?}; using the device path",
+                        device,
+                        self.timeout.unwrap_or_default()
+                    ),
+                    &[("device", device)],
+                );
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Like [`lookup`](Self::lookup), but runs blkid through `runner` and
+    /// reports why no identifier was found.
+    pub fn lookup_with(
+        &self,
+        runner: &dyn CommandRunner,
+        device: &str,
+        tag: &str,
+    ) -> std::result::Result<Option<String>, LookupError> {
+        let output = runner.run("blkid", &blkid_lookup_args(device, tag, self.no_cache));
+        interpret_blkid_output(output, tag)
+    }
+
+    /// Run `blkid -o export` for a device, like [`blkid_export`].
+    pub fn export(&self, device: &str) -> Option<String> {
+        self.export_with(self.runner().as_ref(), device)
+    }
+
+    /// Like [`export`](Self::export), but runs blkid through `runner`.
+    pub fn export_with(&self, runner: &dyn CommandRunner, device: &str) -> Option<String> {
+        let mut args = Vec::with_capacity(5);
+        if self.no_cache {
+            args.extend(BLKID_NO_CACHE_ARGS);
+        }
+        args.extend(["-o", "export", device]);
+        let output = runner.run("blkid", &args).ok()?;
+
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            None
         }
     }
+}
+
+/// Extra blkid arguments that skip its cache file (`--no-blkid-cache`).
+///
+/// The cache (`/run/blkid/blkid.tab`) can hold stale values after
+/// repartitioning. `-c /dev/null` makes blkid probe each device while keeping
+/// the usual tag names; `-p` would bypass the cache too, but reports
+/// partition tags as `PART_ENTRY_*` instead of PARTUUID/PARTLABEL.
+pub const BLKID_NO_CACHE_ARGS: [&str; 2] = ["-c", "/dev/null"];
 
-    None
+/// Arguments for `blkid -s TAG -o value DEVICE`, optionally bypassing the cache.
+pub fn blkid_lookup_args<'a>(device: &'a str, tag: &'a str, no_cache: bool) -> Vec<&'a str> {
+    let mut args = Vec::with_capacity(7);
+    if no_cache {
+        args.extend(BLKID_NO_CACHE_ARGS);
+    }
+    args.extend(["-s", tag, "-o", "value", device]);
+    args
+}
+
+/// Why a blkid lookup produced no answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupError {
+    /// blkid could not be started (not installed, not executable)
+    Spawn(String),
+    /// blkid ran but exited non-zero (device absent or unreadable); `None`
+    /// when it was killed by a signal
+    Exit(Option<i32>),
+    /// blkid was killed for running past the timeout
+    Timeout,
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::Spawn(e) => write!(f, "cannot run blkid: {}", e),
+            LookupError::Exit(Some(code)) => write!(f, "blkid exited with status {}", code),
+            LookupError::Exit(None) => write!(f, "blkid was killed by a signal"),
+            LookupError::Timeout => write!(f, "blkid timed out"),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+/// Like [`lookup_device_id`], but reports why no identifier was found.
+///
+/// Returns `Ok(None)` when blkid succeeded but the device has no value for
+/// `tag`, and an error when blkid could not run or exited non-zero.
+pub fn lookup_device_id_result(
+    device: &str,
+    tag: &str,
+) -> std::result::Result<Option<String>, LookupError> {
+    lookup_device_id_result_with(&SystemRunner, device, tag)
+}
+
+/// Like [`lookup_device_id_result`], but runs blkid through `runner`.
+pub fn lookup_device_id_result_with(
+    runner: &dyn CommandRunner,
+    device: &str,
+    tag: &str,
+) -> std::result::Result<Option<String>, LookupError> {
+    BlkidOptions::default().lookup_with(runner, device, tag)
+}
+
+/// Classify the result of running `blkid -s TAG -o value DEVICE`.
+pub fn interpret_blkid_output(
+    output: io::Result<Output>,
+    tag: &str,
+) -> std::result::Result<Option<String>, LookupError> {
+    let output = output.map_err(|e| match e.kind() {
+        io::ErrorKind::TimedOut => LookupError::Timeout,
+        _ => LookupError::Spawn(e.to_string()),
+    })?;
+    if !output.status.success() {
+        return Err(LookupError::Exit(output.status.code()));
+    }
+
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if value.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("{}={}", tag, value)))
+    }
+}
+
+/// Attributes blkid reports for one device (`TYPE`, `UUID`, ...).
+pub type BlkidAttributes = HashMap<String, String>;
+
+/// Parse `blkid -o export` output, keyed by `DEVNAME`.
+///
+/// blkid prints one `KEY=value` block per device, separated by blank lines.
+/// Blocks without a `DEVNAME` (single-device queries on old blkid) are keyed
+/// by the empty string.
+pub fn parse_blkid_export(output: &str) -> HashMap<String, BlkidAttributes> {
+    let mut devices = HashMap::new();
+    let mut current = BlkidAttributes::new();
+
+    for line in output.lines().chain(std::iter::once("")) {
+        let line = line.trim();
+        if line.is_empty() {
+            if !current.is_empty() {
+                let devname = current.get("DEVNAME").cloned().unwrap_or_default();
+                devices.insert(devname, std::mem::take(&mut current));
+            }
+        } else if let Some((key, value)) = line.split_once('=') {
+            current.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    devices
+}
+
+/// Run `blkid -o export` for a device.
+///
+/// Returns None silently on failure, like [`lookup_device_id`].
+pub fn blkid_export(device: &str) -> Option<String> {
+    BlkidOptions::default().export(device)
+}
+
+/// Attributes added to the comment by `--blkid-comment`, in output order.
+pub const BLKID_COMMENT_TAGS: &[&str] = &["TYPE", "SEC_TYPE"];
+
+/// Build an entry comment with blkid's TYPE/SEC_TYPE, e.g. `/dev/sda2 TYPE=ext4`.
+pub fn blkid_comment(source: &str) -> String {
+    blkid_comment_with(source, blkid_export)
+}
+
+/// Like [`blkid_comment`], but reads `blkid -o export` output through `export`.
+///
+/// Non-block sources, and devices blkid knows nothing about, get the plain
+/// source as their comment.
+pub fn blkid_comment_with<F>(source: &str, export: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(device) = block_device_path(source) else {
+        return source.to_string();
+    };
+    let Some(output) = export(device) else {
+        return source.to_string();
+    };
+
+    let devices = parse_blkid_export(&output);
+    let Some(attrs) = devices.get(device).or_else(|| devices.get("")) else {
+        return source.to_string();
+    };
+
+    let mut comment = source.to_string();
+    for tag in BLKID_COMMENT_TAGS {
+        if let Some(value) = attrs.get(*tag) {
+            comment.push_str(&format!(" {}={}", tag, value));
+        }
+    }
+    comment
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::command::{MockRunner, TimeoutRunner};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::time::Instant;
+
+    fn blkid_output(code: i32, stdout: &str) -> io::Result<Output> {
+        Ok(Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_lookup_device_id_result_with_mock_runner() {
+        let runner = MockRunner::new(0, "ROOT\n");
+        assert_eq!(
+            lookup_device_id_result_with(&runner, "/dev/sda2", "LABEL"),
+            Ok(Some("LABEL=ROOT".to_string()))
+        );
+        assert_eq!(runner.calls(), ["blkid -s LABEL -o value /dev/sda2"]);
+
+        assert_eq!(
+            lookup_device_id_result_with(&MockRunner::new(2, ""), "/dev/sdz9", "UUID"),
+            Err(LookupError::Exit(Some(2)))
+        );
+        assert!(matches!(
+            lookup_device_id_result_with(&MockRunner::missing(), "/dev/sda2", "UUID"),
+            Err(LookupError::Spawn(_))
+        ));
+    }
+
+    #[test]
+    fn test_lookup_uncached_passes_cache_bypass_args() {
+        let runner = MockRunner::new(0, "abcd-1234\n");
+        assert_eq!(
+            BlkidOptions {
+                no_cache: true,
+                ..BlkidOptions::default()
+            }
+            .lookup_with(&runner, "/dev/sda2", "UUID"),
+            Ok(Some("UUID=abcd-1234".to_string()))
+        );
+        assert_eq!(
+            runner.calls(),
+            ["blkid -c /dev/null -s UUID -o value /dev/sda2"]
+        );
+        assert_eq!(
+            blkid_lookup_args("/dev/sda2", "LABEL", false),
+            ["-s", "LABEL", "-o", "value", "/dev/sda2"]
+        );
+    }
+
+    /// Stands in for a blkid that hangs on an unresponsive device.
+    struct HangingBlkid(TimeoutRunner);
+
+    impl CommandRunner for HangingBlkid {
+        fn run(&self, _cmd: &str, _args: &[&str]) -> io::Result<Output> {
+            self.0.run("sleep", &["5"])
+        }
+    }
+
+    #[test]
+    fn test_blkid_timeout_falls_back_to_device_path() {
+        let timeout = Duration::from_millis(100);
+        let runner = HangingBlkid(TimeoutRunner { timeout });
+        let blkid = BlkidOptions {
+            timeout: Some(timeout),
+            ..BlkidOptions::default()
+        };
+
+        let started = Instant::now();
+        assert_eq!(
+            blkid.lookup_with(&runner, "/dev/sda2", "UUID"),
+            Err(LookupError::Timeout)
+        );
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        let lookup =
+            |device: &str, tag: &str| blkid.lookup_with(&runner, device, tag).ok().flatten();
+        assert_eq!(
+            resolve_device_identifier("/dev/sda2", "UUID", lookup, FallbackFormat::Dev),
+            ("/dev/sda2".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_interpret_blkid_output_outcomes() {
+        assert_eq!(
+            interpret_blkid_output(blkid_output(0, "abcd-1234\n"), "UUID"),
+            Ok(Some("UUID=abcd-1234".to_string()))
+        );
+        assert_eq!(
+            interpret_blkid_output(blkid_output(0, "\n"), "LABEL"),
+            Ok(None)
+        );
+        assert_eq!(
+            interpret_blkid_output(blkid_output(2, ""), "UUID"),
+            Err(LookupError::Exit(Some(2)))
+        );
+
+        let missing = Err(io::Error::new(io::ErrorKind::NotFound, "no blkid"));
+        assert!(matches!(
+            interpret_blkid_output(missing, "UUID"),
+            Err(LookupError::Spawn(_))
+        ));
+    }
 
     #[test]
     fn test_extract_device_path() {
@@ -147,6 +895,46 @@ mod tests {
         assert_eq!(extract_device_path("server:/share"), "server:/share");
     }
 
+    #[test]
+    fn test_subvol_from_source() {
+        assert_eq!(subvol_from_source("/dev/sda1[/@home]"), Some("/@home"));
+        assert_eq!(subvol_from_source("/dev/sda1[/]"), Some("/"));
+        assert_eq!(
+            subvol_from_source("/dev/sda1[/[nested]]"),
+            Some("/[nested]")
+        );
+        assert_eq!(subvol_from_source("/dev/sda1"), None);
+        assert_eq!(subvol_from_source("/dev/sda1]["), None);
+    }
+
+    #[test]
+    fn test_classify_source() {
+        let cases = [
+            ("/dev/sda1", SourceKind::Block),
+            ("/dev/disk/by-id/nvme-Samsung_SSD-part2", SourceKind::Block),
+            ("/dev/sda2[/@home]", SourceKind::BtrfsSubvol),
+            ("nas:/export/home", SourceKind::Network),
+            ("[fd00::1]:/export", SourceKind::Network),
+            ("//nas/share", SourceKind::Network),
+            ("\\\\nas\\share", SourceKind::Network),
+            ("/srv/www", SourceKind::Bind),
+            ("/swapfile", SourceKind::Bind),
+            ("UUID=0f1e2d3c", SourceKind::Identifier),
+            ("PARTLABEL=root", SourceKind::Identifier),
+            ("none", SourceKind::None),
+            ("", SourceKind::None),
+            ("tmpfs", SourceKind::Other),
+            ("overlay", SourceKind::Other),
+        ];
+        for (source, kind) in cases {
+            assert_eq!(classify_source(source), kind, "{}", source);
+        }
+        assert!(SourceKind::BtrfsSubvol.is_block());
+        assert!(!SourceKind::Bind.is_block());
+        assert_eq!(block_device_path("/dev/sda2[/@home]"), Some("/dev/sda2"));
+        assert_eq!(block_device_path("nas:/export"), None);
+    }
+
     #[test]
     fn test_get_device_identifier_existing_uuid() {
         // Already has UUID - preserved regardless of id_type
@@ -244,6 +1032,26 @@ mod tests {
         assert_eq!(result, "/dev/nonexistent_xyz");
     }
 
+    #[test]
+    fn test_get_device_identifier_with_stub() {
+        let lookup = |device: &str, tag: &str| {
+            (device == "/dev/sda1").then(|| format!("{}=stub-{}", tag, tag.to_lowercase()))
+        };
+        assert_eq!(
+            get_device_identifier_with("/dev/sda1", "LABEL", lookup),
+            "LABEL=stub-label"
+        );
+        assert_eq!(
+            get_device_identifier_with("/dev/sda1[/@home]", "UUID", lookup),
+            "UUID=stub-uuid"
+        );
+        // Lookup miss falls back to the device path
+        assert_eq!(
+            get_device_identifier_with("/dev/sdb1", "UUID", lookup),
+            "/dev/sdb1"
+        );
+    }
+
     #[test]
     fn test_id_type_blkid_tag() {
         assert_eq!(IdType::Uuid.blkid_tag(), "UUID");
@@ -252,6 +1060,14 @@ mod tests {
         assert_eq!(IdType::Partlabel.blkid_tag(), "PARTLABEL");
     }
 
+    #[test]
+    fn test_id_type_all_descriptions() {
+        assert_eq!(IdType::ALL.len(), 4);
+        for id_type in IdType::ALL {
+            assert!(!id_type.description().is_empty());
+        }
+    }
+
     #[test]
     fn test_id_type_fstab_prefix() {
         assert_eq!(IdType::Uuid.fstab_prefix(), "UUID");
@@ -259,4 +1075,257 @@ mod tests {
         assert_eq!(IdType::Partuuid.fstab_prefix(), "PARTUUID");
         assert_eq!(IdType::Partlabel.fstab_prefix(), "PARTLABEL");
     }
+
+    #[test]
+    fn test_label_length_warning() {
+        let warning = label_length_warning("LABEL=EFI-SYSTEM-PART", "vfat").unwrap();
+        assert!(warning.contains("11-byte"), "warning was: {}", warning);
+        assert!(
+            warning.contains("EFI-SYSTEM-PART"),
+            "warning was: {}",
+            warning
+        );
+
+        assert_eq!(label_length_warning("LABEL=rootfs", "ext4"), None);
+        // Exactly at the limit is fine
+        assert_eq!(label_length_warning("ESP_PARTITI", "vfat"), None);
+        // Unknown filesystems are not checked
+        assert_eq!(label_length_warning(&"x".repeat(300), "nfs"), None);
+    }
+
+    const EXPORT: &str = "DEVNAME=/dev/sda1\nUUID=ABCD-1234\nSEC_TYPE=msdos\nTYPE=vfat\n\n\
+                          DEVNAME=/dev/sda2\nUUID=a1b2\nBLOCK_SIZE=4096\nTYPE=ext4\n";
+
+    #[test]
+    fn test_parse_blkid_export() {
+        let devices = parse_blkid_export(EXPORT);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices["/dev/sda1"]["TYPE"], "vfat");
+        assert_eq!(devices["/dev/sda2"]["UUID"], "a1b2");
+        assert!(parse_blkid_export("").is_empty());
+    }
+
+    #[test]
+    fn test_blkid_comment_with() {
+        let export = |_: &str| Some(EXPORT.to_string());
+        assert_eq!(
+            blkid_comment_with("/dev/sda2", export),
+            "/dev/sda2 TYPE=ext4"
+        );
+        assert_eq!(
+            blkid_comment_with("/dev/sda1", export),
+            "/dev/sda1 TYPE=vfat SEC_TYPE=msdos"
+        );
+        // Subvolume notation keeps the full source
+        let single = |_: &str| Some("UUID=f00\nTYPE=btrfs\n".to_string());
+        assert_eq!(
+            blkid_comment_with("/dev/sdb1[/@home]", single),
+            "/dev/sdb1[/@home] TYPE=btrfs"
+        );
+        // Fallbacks: blkid failure and non-block sources
+        assert_eq!(blkid_comment_with("/dev/sdc1", |_| None), "/dev/sdc1");
+        assert_eq!(blkid_comment_with("server:/share", export), "server:/share");
+    }
+
+    /// /dev fixture: sda1 with by-id and by-path links, sdb1 with none.
+    fn dev_fixture(name: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::symlink;
+
+        let dev = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dev);
+        fs::create_dir_all(dev.join("disk/by-id")).unwrap();
+        fs::create_dir_all(dev.join("disk/by-path")).unwrap();
+        fs::write(dev.join("sda1"), "").unwrap();
+        fs::write(dev.join("sdb1"), "").unwrap();
+        for (link, dest) in [
+            ("by-id/wwn-0x5000c500a1b2c3d4-part1", "sda1"),
+            ("by-id/ata-Samsung_SSD_860_S3Z9-part1", "sda1"),
+            ("by-path/pci-0000:00:17.0-ata-1-part1", "sda1"),
+        ] {
+            symlink(format!("../../{}", dest), dev.join("disk").join(link)).unwrap();
+        }
+        dev
+    }
+
+    #[test]
+    fn test_fallback_device_path_in() {
+        let dev = dev_fixture("recfstab_test_dev_fallback");
+        assert_eq!(
+            fallback_device_path_in(&dev, "/dev/sda1", FallbackFormat::Dev),
+            "/dev/sda1"
+        );
+        assert_eq!(
+            fallback_device_path_in(&dev, "/dev/sda1", FallbackFormat::ById),
+            "/dev/disk/by-id/ata-Samsung_SSD_860_S3Z9-part1"
+        );
+        assert_eq!(
+            fallback_device_path_in(&dev, "/dev/sda1", FallbackFormat::ByPath),
+            "/dev/disk/by-path/pci-0000:00:17.0-ata-1-part1"
+        );
+        // No link for the device: kernel name is kept
+        assert_eq!(
+            fallback_device_path_in(&dev, "/dev/sdb1", FallbackFormat::ById),
+            "/dev/sdb1"
+        );
+        let _ = fs::remove_dir_all(&dev);
+    }
+
+    #[test]
+    fn test_comment_source_priority() {
+        let dev = dev_fixture("recfstab_test_dev_comment_source");
+        fs::create_dir_all(dev.join("disk/by-uuid")).unwrap();
+        std::os::unix::fs::symlink("../../sda1", dev.join("disk/by-uuid/abcd-1234")).unwrap();
+
+        let priority = [NamingScheme::ById, NamingScheme::ByUuid, NamingScheme::Dev];
+        assert_eq!(
+            comment_source_in(&dev, "/dev/sda1", &priority),
+            "/dev/disk/by-id/ata-Samsung_SSD_860_S3Z9-part1"
+        );
+        assert_eq!(
+            comment_source_in(&dev, "/dev/sda1[/@home]", &priority[1..]),
+            "/dev/disk/by-uuid/abcd-1234[/@home]"
+        );
+        // sdb1 has no links, so the walk reaches dev
+        assert_eq!(comment_source_in(&dev, "/dev/sdb1", &priority), "/dev/sdb1");
+        // ...and without dev in the list the source is kept as-is
+        assert_eq!(
+            comment_source_in(&dev, "/dev/sdb1", &[NamingScheme::ByPath]),
+            "/dev/sdb1"
+        );
+        assert_eq!(
+            comment_source_in(&dev, "server:/srv", &priority),
+            "server:/srv"
+        );
+        let _ = fs::remove_dir_all(&dev);
+    }
+
+    #[test]
+    fn test_resolve_device_identifier_reports_fallback() {
+        let failing = |_: &str, _: &str| None;
+        assert_eq!(
+            resolve_device_identifier("/dev/sda1", "UUID", failing, FallbackFormat::Dev),
+            ("/dev/sda1".to_string(), true)
+        );
+        let found = |_: &str, tag: &str| Some(format!("{}=abc", tag));
+        assert_eq!(
+            resolve_device_identifier("/dev/sda1", "UUID", found, FallbackFormat::Dev),
+            ("UUID=abc".to_string(), false)
+        );
+        // Sources that never needed a lookup are not fallbacks
+        assert!(!resolve_device_identifier("server:/srv", "UUID", failing, FallbackFormat::Dev).1);
+        assert!(!resolve_device_identifier("LABEL=root", "UUID", failing, FallbackFormat::Dev).1);
+    }
+
+    #[test]
+    fn test_chain_lookup_skips_missing_tags() {
+        // The device has no filesystem UUID, but its partition has a PARTUUID
+        let lookup = |_: &str, tag: &str| match tag {
+            "PARTUUID" => Some("PARTUUID=1234-02".to_string()),
+            "LABEL" => Some("LABEL=data".to_string()),
+            _ => None,
+        };
+        let chain = [IdType::Uuid, IdType::Partuuid, IdType::Label];
+        assert_eq!(
+            get_device_identifier_with("/dev/sda2", "", chain_lookup(&chain, lookup)),
+            "PARTUUID=1234-02"
+        );
+        assert_eq!(
+            get_device_identifier_with("/dev/sda2", "", chain_lookup(&[IdType::Uuid], lookup)),
+            "/dev/sda2"
+        );
+
+        let (spec, fallback) = resolve_device_identifier(
+            "/dev/sda2",
+            "UUID",
+            chain_lookup(&[IdType::Uuid, IdType::Label], lookup),
+            FallbackFormat::Dev,
+        );
+        assert_eq!((spec.as_str(), fallback), ("LABEL=data", false));
+    }
+
+    #[test]
+    fn test_identifier_path() {
+        assert_eq!(
+            identifier_path("UUID=abc").as_deref(),
+            Some("/dev/disk/by-uuid/abc")
+        );
+        assert_eq!(
+            identifier_path("LABEL=x").as_deref(),
+            Some("/dev/disk/by-label/x")
+        );
+        assert_eq!(
+            identifier_path("PARTUUID=1234-02").as_deref(),
+            Some("/dev/disk/by-partuuid/1234-02")
+        );
+        assert_eq!(
+            identifier_path("PARTLABEL=EFI system").as_deref(),
+            Some("/dev/disk/by-partlabel/EFI\\x20system")
+        );
+        assert_eq!(
+            identifier_path("LABEL=a/b\\c").as_deref(),
+            Some("/dev/disk/by-label/a\\x2fb\\x5cc")
+        );
+        assert_eq!(identifier_path("/dev/sda1"), None);
+        assert_eq!(identifier_path("server:/srv"), None);
+        assert_eq!(identifier_path("SERIAL=abc"), None);
+
+        assert_eq!(IdFormat::Path.apply("/dev/sda1"), "/dev/sda1");
+        assert_eq!(IdFormat::Tag.apply("UUID=abc"), "UUID=abc");
+    }
+
+    #[test]
+    fn test_disk_path_sources_skip_blkid() {
+        let unreachable = |_: &str, _: &str| -> Option<String> {
+            panic!("blkid should not be needed");
+        };
+        for (source, tag, expected) in [
+            ("/dev/disk/by-uuid/abcd-1234", "UUID", "UUID=abcd-1234"),
+            ("/dev/disk/by-label/root", "LABEL", "LABEL=root"),
+            (
+                "/dev/disk/by-partuuid/1234-02",
+                "PARTUUID",
+                "PARTUUID=1234-02",
+            ),
+            (
+                "/dev/disk/by-partlabel/EFI\\x20system",
+                "PARTLABEL",
+                "PARTLABEL=EFI system",
+            ),
+        ] {
+            assert_eq!(
+                get_device_identifier_with(source, tag, unreachable),
+                expected
+            );
+        }
+
+        // A different identifier type still goes through blkid
+        let lookup = |device: &str, tag: &str| {
+            assert_eq!(device, "/dev/disk/by-label/root");
+            Some(format!("{}=abcd-1234", tag))
+        };
+        assert_eq!(
+            get_device_identifier_with("/dev/disk/by-label/root", "UUID", lookup),
+            "UUID=abcd-1234"
+        );
+        // by-id and by-path names are not identifiers
+        assert_eq!(
+            identifier_from_disk_path("/dev/disk/by-id/ata-disk", "UUID"),
+            None
+        );
+        assert_eq!(decode_udev_name("a\\x2fb\\xzz"), "a/b\\xzz");
+    }
+
+    #[test]
+    fn test_resolve_device_identifier_fallback_only_on_failure() {
+        let found = |_: &str, tag: &str| Some(format!("{}=abc", tag));
+        assert_eq!(
+            resolve_device_identifier("/dev/sda1", "UUID", found, FallbackFormat::ById),
+            ("UUID=abc".to_string(), false)
+        );
+        // Non-block sources are never rewritten
+        assert_eq!(
+            resolve_device_identifier("server:/x", "UUID", |_, _| None, FallbackFormat::ById),
+            ("server:/x".to_string(), false)
+        );
+    }
 }
@@ -0,0 +1,170 @@
+//! Entries for devices that are not mounted (`--devices-from`).
+//!
+//! The list has one `DEVICE MOUNTPOINT FSTYPE [OPTIONS]` line per device,
+//! e.g. `/dev/sdb1 /data ext4 nodev`. Blank lines and `#` comments are
+//! ignored. The mountpoint uses fstab escaping (`\040` for a space); swap
+//! lines give `none` (or anything) as the mountpoint. Each line is treated
+//! like a mount findmnt reported, so identifiers are resolved through blkid
+//! and options go through the usual filtering (`noatime` is dropped as
+//! runtime-only; use `--options-map` for exact options), but findmnt itself
+//! is never run.
+
+use crate::error::{RecfstabError, Result};
+use crate::fstab::unescape_fstab;
+use crate::log;
+use crate::mount::{MountInfo, MountProvider};
+use crate::swap::{is_swap_file, SwapInfo};
+use std::path::Path;
+
+/// Devices from a list, presented as if mounted under `/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceList {
+    /// Filesystems, in list order, with the mountpoint as target
+    pub mounts: Vec<MountInfo>,
+    /// Lines with fstype `swap`
+    pub swaps: Vec<SwapInfo>,
+}
+
+impl DeviceList {
+    /// Read and parse the list at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| RecfstabError::read_failed(path, e))?;
+        Ok(parse_device_list(&content))
+    }
+}
+
+impl MountProvider for DeviceList {
+    fn mounts(&self) -> Result<Vec<MountInfo>> {
+        Ok(self.mounts.clone())
+    }
+
+    fn swaps(&self) -> Result<Vec<SwapInfo>> {
+        Ok(self.swaps.clone())
+    }
+}
+
+/// Parse a device list (see the module docs).
+///
+/// Lines with fewer than three fields, or a relative mountpoint, are
+/// skipped with a warning. Generate with root `/` so each mountpoint is
+/// used as given.
+pub fn parse_device_list(content: &str) -> DeviceList {
+    let mut list = DeviceList::default();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            log::warning(
+                "device-list-line-skipped",
+                &format!(
+                    "device list line {}: expected DEVICE MOUNTPOINT FSTYPE [OPTIONS], \
+                     skipping: {}",
+                    index + 1,
+                    line
+                ),
+                &[("line", &(index + 1).to_string())],
+            );
+            continue;
+        }
+        let device = unescape_fstab(fields[0]);
+        if fields[2] == "swap" {
+            list.swaps.push(SwapInfo {
+                swap_type: if is_swap_file(&device) {
+                    "file"
+                } else {
+                    "partition"
+                }
+                .to_string(),
+                filename: device,
+            });
+            continue;
+        }
+        let target = unescape_fstab(fields[1]);
+        if !target.starts_with('/') {
+            log::warning(
+                "device-list-line-skipped",
+                &format!(
+                    "device list line {}: mountpoint {} is not absolute, skipping",
+                    index + 1,
+                    target
+                ),
+                &[("line", &(index + 1).to_string())],
+            );
+            continue;
+        }
+        list.mounts.push(
+            MountInfo {
+                target,
+                source: device,
+                fstype: fields[2].to_string(),
+                options: fields.get(3).copied().unwrap_or_default().to_string(),
+                ..MountInfo::default()
+            }
+            .with_fuse_subtype(),
+        );
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::MockRunner;
+    use crate::config::Config;
+    use crate::generate::entry_from_mount_with;
+
+    const LIST: &str = "# provisioning plan\n\
+                        /dev/sdb1 / ext4 errors=remount-ro\n\
+                        \n\
+                        /dev/sdb2 /srv/my\\040data xfs\n\
+                        /dev/sdb3 none swap\n\
+                        /dev/sdb4 relative ext4\n\
+                        /dev/sdb5 /short\n";
+
+    #[test]
+    fn test_parse_device_list() {
+        let list = parse_device_list(LIST);
+        assert_eq!(list.mounts.len(), 2);
+        assert_eq!(list.mounts[0].source, "/dev/sdb1");
+        assert_eq!(list.mounts[0].options, "errors=remount-ro");
+        assert_eq!(list.mounts[1].target, "/srv/my data");
+        assert_eq!(list.mounts[1].options, "");
+        assert_eq!(
+            list.swaps,
+            [SwapInfo {
+                filename: "/dev/sdb3".to_string(),
+                swap_type: "partition".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_device_list_entries_resolve_through_blkid() {
+        let list = parse_device_list(LIST);
+        let runner = MockRunner::new(0, "0f1e2d3c-aaaa-bbbb-cccc-1234567890ab\n");
+        let entries: Vec<String> = list
+            .mounts
+            .iter()
+            .map(|mount| entry_from_mount_with(mount, "/", &Config::default(), &runner).data_line())
+            .collect();
+
+        assert_eq!(
+            entries,
+            [
+                "UUID=0f1e2d3c-aaaa-bbbb-cccc-1234567890ab\t/\text4\terrors=remount-ro\t0\t1",
+                "UUID=0f1e2d3c-aaaa-bbbb-cccc-1234567890ab\t/srv/my\\040data\txfs\tdefaults\t0\t2",
+            ]
+        );
+        assert_eq!(
+            runner.calls(),
+            [
+                "blkid -s UUID -o value /dev/sdb1",
+                "blkid -s UUID -o value /dev/sdb2"
+            ]
+        );
+    }
+}
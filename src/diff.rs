@@ -0,0 +1,177 @@
+//! Comparing a generated fstab against an existing one (`--diff`,
+//! `--dry-run-diff`).
+
+use crate::fstab::FstabEntry;
+use crate::merge::merge_key;
+use std::collections::{HashMap, HashSet};
+
+/// Differences between an existing fstab and the generated entries.
+///
+/// Entries are matched by mountpoint (swaps by device, as in
+/// [`merge_entries`](crate::merge::merge_entries)); comments are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FstabDiff {
+    /// Generated entries with no counterpart in the old fstab
+    pub added: Vec<FstabEntry>,
+    /// Old entries with no generated counterpart
+    pub removed: Vec<FstabEntry>,
+    /// (old, new) pairs for the same mountpoint whose data lines differ
+    pub changed: Vec<(FstabEntry, FstabEntry)>,
+}
+
+impl FstabDiff {
+    /// Check whether the two fstabs have the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Render as `+`/`-` lines for added/removed entries and a `~` line per
+    /// changed field, e.g. `~ /home options: defaults -> noatime`.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.removed {
+            out.push_str(&format!("- {}\n", entry.data_line()));
+        }
+        for entry in &self.added {
+            out.push_str(&format!("+ {}\n", entry.data_line()));
+        }
+        for (old, new) in &self.changed {
+            let fields = [
+                ("source", old.fs_spec.clone(), new.fs_spec.clone()),
+                ("mountpoint", old.mountpoint.clone(), new.mountpoint.clone()),
+                ("fstype", old.fstype.clone(), new.fstype.clone()),
+                ("options", old.options.clone(), new.options.clone()),
+                ("dump", old.dump.to_string(), new.dump.to_string()),
+                ("pass", old.pass.to_string(), new.pass.to_string()),
+            ];
+            for (name, before, after) in fields {
+                if before != after {
+                    out.push_str(&format!(
+                        "~ {} {}: {} -> {}\n",
+                        merge_key(new),
+                        name,
+                        before,
+                        after
+                    ));
+                }
+            }
+        }
+        out
+    }
+
+    /// Render as unified-style lines: `-` for each removed entry, `-` then
+    /// `+` for each changed one, and `+` for each added one
+    /// (`--dry-run-diff`).
+    pub fn render_unified(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.removed {
+            out.push_str(&format!("-{}\n", entry.data_line()));
+        }
+        for (old, new) in &self.changed {
+            out.push_str(&format!("-{}\n+{}\n", old.data_line(), new.data_line()));
+        }
+        for entry in &self.added {
+            out.push_str(&format!("+{}\n", entry.data_line()));
+        }
+        out
+    }
+
+    /// Count the differences, e.g. `1 added, 0 removed, 2 changed`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// Compare `old` (e.g. from [`parse_fstab`](crate::fstab::parse_fstab))
+/// with `new`.
+///
+/// `added` and `changed` follow the order of `new`, `removed` the order of
+/// `old`. When a key appears more than once, the first entry counts.
+pub fn diff_fstab(old: &[FstabEntry], new: &[FstabEntry]) -> FstabDiff {
+    let mut old_by_key: HashMap<&str, &FstabEntry> = HashMap::new();
+    for entry in old {
+        old_by_key.entry(merge_key(entry)).or_insert(entry);
+    }
+
+    let mut diff = FstabDiff::default();
+    let mut matched = HashSet::new();
+    for entry in new {
+        let key = merge_key(entry);
+        if !matched.insert(key) {
+            continue;
+        }
+        match old_by_key.get(key) {
+            Some(previous) if previous.data_line() != entry.data_line() => {
+                diff.changed.push(((*previous).clone(), entry.clone()));
+            }
+            Some(_) => {}
+            None => diff.added.push(entry.clone()),
+        }
+    }
+    let mut reported = HashSet::new();
+    for entry in old {
+        let key = merge_key(entry);
+        if !matched.contains(key) && reported.insert(key) {
+            diff.removed.push(entry.clone());
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fstab::parse_fstab;
+
+    #[test]
+    fn test_diff_added_removed_changed() {
+        let old = parse_fstab(
+            "UUID=root\t/\text4\tdefaults\t0\t1\n\
+             UUID=home\t/home\text4\tdefaults\t0\t2\n\
+             UUID=old\t/srv\text4\tdefaults\t0\t2\n",
+        );
+        let new = parse_fstab(
+            "# comments are ignored\n\
+             UUID=root\t/\text4\tdefaults\t0\t1\n\
+             UUID=home\t/home\text4\tnoatime\t0\t2\n\
+             UUID=ABCD-1234\t/boot\tvfat\tumask=0077\t0\t0\n",
+        );
+
+        let diff = diff_fstab(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].mountpoint, "/boot");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].mountpoint, "/srv");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.options, "noatime");
+
+        assert_eq!(
+            diff.render(),
+            "- UUID=old\t/srv\text4\tdefaults\t0\t2\n\
+             + UUID=ABCD-1234\t/boot\tvfat\tumask=0077\t0\t0\n\
+             ~ /home options: defaults -> noatime\n"
+        );
+        assert_eq!(
+            diff.render_unified(),
+            "-UUID=old\t/srv\text4\tdefaults\t0\t2\n\
+             -UUID=home\t/home\text4\tdefaults\t0\t2\n\
+             +UUID=home\t/home\text4\tnoatime\t0\t2\n\
+             +UUID=ABCD-1234\t/boot\tvfat\tumask=0077\t0\t0\n"
+        );
+        assert_eq!(diff.summary(), "1 added, 1 removed, 1 changed");
+    }
+
+    #[test]
+    fn test_diff_identical_is_empty() {
+        let fstab =
+            parse_fstab("UUID=root / ext4 defaults 0 1\n/swapfile none swap defaults 0 0\n");
+        let diff = diff_fstab(&fstab, &fstab);
+        assert!(diff.is_empty());
+        assert_eq!(diff.render(), "");
+    }
+}
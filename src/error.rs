@@ -12,9 +12,22 @@
 //! | E001 | Root directory does not exist |
 //! | E002 | Path is not a directory |
 //! | E003 | Failed to determine current directory |
-//! | E004 | findmnt command not found (util-linux not installed) |
+//! | E004 | Retired: a missing findmnt now falls back to `/proc/self/mountinfo` (see E014) |
 //! | E005 | findmnt command failed |
 //! | E006 | No filesystems found under specified root |
+//! | E007 | Failed to write output |
+//! | E008 | Failed to read input file |
+//! | E009 | Identifier lookup fell back to a device path (`--fail-on-fallback`) |
+//! | E010 | Generated entry failed the output self-check |
+//! | E011 | No root (`/`) entry was generated (`--require-root`) |
+//! | E012 | Mountpoint directory missing (`--check-mountpoints --strict`) |
+//! | E013 | No mount generates the entry asked for (`--explain-entry`) |
+//! | E014 | No mount table available (no findmnt, unreadable `/proc/self/mountinfo`) |
+//! | E015 | Entry options differ from the running mount (`--verify-options`) |
+//! | E016 | Generated line longer than `--max-line-length` (with `--strict`) |
+//! | E017 | Invalid `--pass-map` file |
+//! | E018 | Entry left with no options by `--no-defaults-fallback` (with `--strict`) |
+//! | E019 | LABEL shared by several devices (`--label-collision-check --strict`) |
 
 use std::fmt;
 
@@ -27,12 +40,40 @@ pub enum ErrorCode {
     NotADirectory,
     /// E003: Failed to determine current directory
     CurrentDirFailed,
-    /// E004: findmnt command not found
+    /// E004: findmnt command not found (retired; no longer returned since a
+    /// missing findmnt falls back to `/proc/self/mountinfo`, see
+    /// [`ErrorCode::NoMountSource`])
     FindmntNotFound,
     /// E005: findmnt command failed
     FindmntFailed,
     /// E006: No filesystems found under root
     NoFilesystems,
+    /// E007: Failed to write output
+    WriteFailed,
+    /// E008: Failed to read input file
+    ReadFailed,
+    /// E009: Identifier lookup fell back to a device path
+    IdentifierFallback,
+    /// E010: Generated entry failed the output self-check
+    SelfCheckFailed,
+    /// E011: No root entry was generated
+    RootEntryMissing,
+    /// E012: A mountpoint directory is missing from the target root
+    MountpointMissing,
+    /// E013: No mount under the root generates the requested entry
+    EntryNotFound,
+    /// E014: Neither findmnt nor the kernel mount table is available
+    NoMountSource,
+    /// E015: Generated options would not reproduce the running mounts
+    OptionsMismatch,
+    /// E016: A generated line is longer than the configured maximum
+    LineTooLong,
+    /// E017: A pass map line is malformed or has a pass other than 0-2
+    InvalidPassMap,
+    /// E018: Filtering left an entry with no options (`--no-defaults-fallback`)
+    EmptyOptions,
+    /// E019: Several devices resolved to the same LABEL
+    DuplicateLabel,
 }
 
 impl ErrorCode {
@@ -45,6 +86,19 @@ impl ErrorCode {
             ErrorCode::FindmntNotFound => "E004",
             ErrorCode::FindmntFailed => "E005",
             ErrorCode::NoFilesystems => "E006",
+            ErrorCode::WriteFailed => "E007",
+            ErrorCode::ReadFailed => "E008",
+            ErrorCode::IdentifierFallback => "E009",
+            ErrorCode::SelfCheckFailed => "E010",
+            ErrorCode::RootEntryMissing => "E011",
+            ErrorCode::MountpointMissing => "E012",
+            ErrorCode::EntryNotFound => "E013",
+            ErrorCode::NoMountSource => "E014",
+            ErrorCode::OptionsMismatch => "E015",
+            ErrorCode::LineTooLong => "E016",
+            ErrorCode::InvalidPassMap => "E017",
+            ErrorCode::EmptyOptions => "E018",
+            ErrorCode::DuplicateLabel => "E019",
         }
     }
 
@@ -54,9 +108,22 @@ impl ErrorCode {
             ErrorCode::RootNotFound => "root directory does not exist",
             ErrorCode::NotADirectory => "path is not a directory",
             ErrorCode::CurrentDirFailed => "failed to determine current directory",
-            ErrorCode::FindmntNotFound => "findmnt command not found",
+            ErrorCode::FindmntNotFound => "findmnt command not found (retired)",
             ErrorCode::FindmntFailed => "findmnt command failed",
             ErrorCode::NoFilesystems => "no filesystems found",
+            ErrorCode::WriteFailed => "failed to write output",
+            ErrorCode::ReadFailed => "failed to read input file",
+            ErrorCode::IdentifierFallback => "identifier lookup fell back to device path",
+            ErrorCode::SelfCheckFailed => "generated entry failed self-check",
+            ErrorCode::RootEntryMissing => "no root entry generated",
+            ErrorCode::MountpointMissing => "mountpoint directory missing",
+            ErrorCode::EntryNotFound => "no such entry",
+            ErrorCode::NoMountSource => "no mount table available",
+            ErrorCode::OptionsMismatch => "options differ from running mounts",
+            ErrorCode::LineTooLong => "fstab line too long",
+            ErrorCode::InvalidPassMap => "invalid pass map",
+            ErrorCode::EmptyOptions => "no mount options left",
+            ErrorCode::DuplicateLabel => "duplicate label",
         }
     }
 }
@@ -108,6 +175,7 @@ impl RecfstabError {
     }
 
     /// findmnt command not found.
+    #[deprecated(note = "E004 is retired")]
     pub fn findmnt_not_found(source: std::io::Error) -> Self {
         Self::new(
             ErrorCode::FindmntNotFound,
@@ -118,6 +186,24 @@ impl RecfstabError {
         )
     }
 
+    /// findmnt could not be run and the kernel mount table could not be read.
+    pub fn no_mount_source(
+        findmnt: &std::io::Error,
+        mountinfo: &std::path::Path,
+        source: &std::io::Error,
+    ) -> Self {
+        Self::new(
+            ErrorCode::NoMountSource,
+            format!(
+                "cannot read the mount table: findmnt could not be run ({}) and '{}' \
+                 is unreadable ({}); install util-linux or make /proc available",
+                findmnt,
+                mountinfo.display(),
+                source
+            ),
+        )
+    }
+
     /// findmnt command failed.
     pub fn findmnt_failed(stderr: &str) -> Self {
         let detail = if stderr.is_empty() {
@@ -141,6 +227,135 @@ impl RecfstabError {
             ),
         )
     }
+
+    /// Writing generated output failed.
+    pub fn write_failed(source: std::io::Error) -> Self {
+        Self::new(
+            ErrorCode::WriteFailed,
+            format!("failed to write output: {}", source),
+        )
+    }
+
+    /// Writing the --output file failed.
+    pub fn output_failed(path: &std::path::Path, source: std::io::Error) -> Self {
+        Self::new(
+            ErrorCode::WriteFailed,
+            format!("failed to write '{}': {}", path.display(), source),
+        )
+    }
+
+    /// Reading an input file (e.g. the --merge fstab) failed.
+    pub fn read_failed(path: &std::path::Path, source: std::io::Error) -> Self {
+        Self::new(
+            ErrorCode::ReadFailed,
+            format!("failed to read '{}': {}", path.display(), source),
+        )
+    }
+
+    /// A generated entry did not re-parse as a valid fstab line.
+    pub fn self_check_failed(mountpoint: &str, problem: &str) -> Self {
+        Self::new(
+            ErrorCode::SelfCheckFailed,
+            format!(
+                "self-check failed for the {} entry: {} (this is a recfstab bug)",
+                mountpoint, problem
+            ),
+        )
+    }
+
+    /// No `/` entry was generated under `--require-root`.
+    pub fn root_entry_missing(root: &str) -> Self {
+        Self::new(
+            ErrorCode::RootEntryMissing,
+            format!(
+                "no / entry generated for '{}' (is the root filesystem mounted there?)",
+                root
+            ),
+        )
+    }
+
+    /// Mountpoint directories are missing under `--check-mountpoints --strict`.
+    pub fn mountpoint_missing(target_root: &std::path::Path, mountpoints: &[String]) -> Self {
+        Self::new(
+            ErrorCode::MountpointMissing,
+            format!(
+                "no directory under '{}' for {}",
+                target_root.display(),
+                mountpoints.join(", ")
+            ),
+        )
+    }
+
+    /// No accepted mount under `root` has fstab mountpoint `mountpoint`.
+    pub fn entry_not_found(mountpoint: &str, root: &str) -> Self {
+        Self::new(
+            ErrorCode::EntryNotFound,
+            format!(
+                "no entry for '{}' under '{}' (not mounted, or skipped)",
+                mountpoint, root
+            ),
+        )
+    }
+
+    /// Entry options differ from the running mounts under `--verify-options`.
+    pub fn options_mismatch(mountpoints: &[String]) -> Self {
+        Self::new(
+            ErrorCode::OptionsMismatch,
+            format!(
+                "options would not reproduce the running mount for {}",
+                mountpoints.join(", ")
+            ),
+        )
+    }
+
+    /// Lines for `entries` exceed `--max-line-length` under `--strict`.
+    pub fn line_too_long(entries: &[String], max: usize) -> Self {
+        Self::new(
+            ErrorCode::LineTooLong,
+            format!(
+                "fstab line longer than {} characters for {}",
+                max,
+                entries.join(", ")
+            ),
+        )
+    }
+
+    /// Filtering left `entries` with no options under `--no-defaults-fallback`
+    /// and `--strict`.
+    pub fn empty_options(entries: &[String]) -> Self {
+        Self::new(
+            ErrorCode::EmptyOptions,
+            format!("no mount options left for {}", entries.join(", ")),
+        )
+    }
+
+    /// `labels` each name more than one device under
+    /// `--label-collision-check --strict`.
+    pub fn duplicate_labels(labels: &[String]) -> Self {
+        Self::new(
+            ErrorCode::DuplicateLabel,
+            format!("shared by more than one device: {}", labels.join(", ")),
+        )
+    }
+
+    /// The `--pass-map` file at `path` has a bad line.
+    pub fn invalid_pass_map(path: &std::path::Path, problem: &str) -> Self {
+        Self::new(
+            ErrorCode::InvalidPassMap,
+            format!("invalid pass map '{}': {}", path.display(), problem),
+        )
+    }
+
+    /// Entries fell back to device paths under `--fail-on-fallback`.
+    pub fn identifier_fallback(devices: &[String]) -> Self {
+        Self::new(
+            ErrorCode::IdentifierFallback,
+            format!(
+                "no identifier found for {} (device path used instead)",
+                devices.join(", ")
+            ),
+        )
+    }
 }
 
 impl fmt::Display for RecfstabError {
@@ -166,6 +381,19 @@ mod tests {
         assert_eq!(ErrorCode::FindmntNotFound.code(), "E004");
         assert_eq!(ErrorCode::FindmntFailed.code(), "E005");
         assert_eq!(ErrorCode::NoFilesystems.code(), "E006");
+        assert_eq!(ErrorCode::WriteFailed.code(), "E007");
+        assert_eq!(ErrorCode::ReadFailed.code(), "E008");
+        assert_eq!(ErrorCode::IdentifierFallback.code(), "E009");
+        assert_eq!(ErrorCode::SelfCheckFailed.code(), "E010");
+        assert_eq!(ErrorCode::RootEntryMissing.code(), "E011");
+        assert_eq!(ErrorCode::MountpointMissing.code(), "E012");
+        assert_eq!(ErrorCode::EntryNotFound.code(), "E013");
+        assert_eq!(ErrorCode::NoMountSource.code(), "E014");
+        assert_eq!(ErrorCode::OptionsMismatch.code(), "E015");
+        assert_eq!(ErrorCode::LineTooLong.code(), "E016");
+        assert_eq!(ErrorCode::InvalidPassMap.code(), "E017");
+        assert_eq!(ErrorCode::EmptyOptions.code(), "E018");
+        assert_eq!(ErrorCode::DuplicateLabel.code(), "E019");
     }
 
     #[test]
@@ -206,6 +434,17 @@ mod tests {
         assert!(msg.contains("permission denied"), "Error was: {}", msg);
     }
 
+    #[test]
+    fn test_error_output_failed() {
+        let err = RecfstabError::output_failed(
+            std::path::Path::new("/mnt/etc/fstab"),
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        );
+        let msg = err.to_string();
+        assert!(msg.starts_with("E007:"), "Error was: {}", msg);
+        assert!(msg.contains("/mnt/etc/fstab"), "Error was: {}", msg);
+    }
+
     #[test]
     fn test_all_error_codes_unique() {
         let codes = [
@@ -215,6 +454,19 @@ mod tests {
             ErrorCode::FindmntNotFound,
             ErrorCode::FindmntFailed,
             ErrorCode::NoFilesystems,
+            ErrorCode::WriteFailed,
+            ErrorCode::ReadFailed,
+            ErrorCode::IdentifierFallback,
+            ErrorCode::SelfCheckFailed,
+            ErrorCode::RootEntryMissing,
+            ErrorCode::MountpointMissing,
+            ErrorCode::EntryNotFound,
+            ErrorCode::NoMountSource,
+            ErrorCode::OptionsMismatch,
+            ErrorCode::LineTooLong,
+            ErrorCode::InvalidPassMap,
+            ErrorCode::EmptyOptions,
+            ErrorCode::DuplicateLabel,
         ];
 
         let mut seen = std::collections::HashSet::new();
@@ -34,37 +34,460 @@ pub const PSEUDO_FILESYSTEMS: &[&str] = &[
 /// Mount options that are runtime-only and should not appear in fstab.
 pub const RUNTIME_OPTIONS: &[&str] = &["lazytime", "noatime", "relatime", "ro", "rw", "seclabel"];
 
+/// Filesystem types that are mounted over the network.
+pub const NETWORK_FILESYSTEMS: &[&str] = &[
+    "9p",
+    "ceph",
+    "cifs",
+    "fuse.sshfs",
+    "glusterfs",
+    "nfs",
+    "nfs4",
+    "smb3",
+    "smbfs",
+];
+
+/// Shared-disk cluster filesystem types.
+///
+/// Mounting them needs the cluster stack (and so the network) up, so their
+/// entries always get `_netdev`; they are never checked at boot, since fsck
+/// must only run with the filesystem unmounted on every node.
+pub const CLUSTER_FILESYSTEMS: &[&str] = &["gfs2", "ocfs2"];
+
+/// Check if a filesystem type is a shared-disk cluster filesystem.
+pub fn is_cluster_filesystem(fstype: &str) -> bool {
+    CLUSTER_FILESYSTEMS.contains(&fstype)
+}
+
+/// Filesystem types that can only be mounted read-only (compressed images).
+///
+/// These are real filesystems, not pseudo ones: their entries keep `ro` and
+/// never get an fsck pass.
+pub const READ_ONLY_FILESYSTEMS: &[&str] = &["erofs", "squashfs"];
+
+/// Check if a filesystem type is a read-only image format.
+pub fn is_read_only_filesystem(fstype: &str) -> bool {
+    READ_ONLY_FILESYSTEMS.contains(&fstype)
+}
+
+/// Notes for filesystem types that more than one driver can provide.
+///
+/// The fstype in fstab picks the driver at mount time, so an entry copied
+/// between systems can silently switch from the kernel driver to FUSE (or
+/// back).
+pub const FSTYPE_DRIVER_NOTES: &[(&str, &str)] = &[
+    ("exfat", "uses the exfat kernel driver (not exfat-fuse)"),
+    (
+        "fuse.exfat",
+        "uses exfat-fuse (FUSE), not the exfat kernel driver",
+    ),
+    (
+        "fuseblk",
+        "uses a FUSE block driver, usually ntfs-3g; not the ntfs3 kernel driver",
+    ),
+    (
+        "ntfs",
+        "ntfs may map to the ntfs3 kernel driver or ntfs-3g depending on the system",
+    ),
+    ("ntfs3", "uses the ntfs3 kernel driver (not ntfs-3g)"),
+];
+
+/// Driver note for an ambiguous filesystem type (`--annotate-fstype-driver`).
+pub fn fstype_driver_note(fstype: &str) -> Option<&'static str> {
+    FSTYPE_DRIVER_NOTES
+        .iter()
+        .find(|(name, _)| *name == fstype)
+        .map(|(_, note)| *note)
+}
+
+/// Local filesystem types known to need no special handling beyond the
+/// defaults (most get pass 0 on purpose: no fsck at boot, or none exists;
+/// nilfs2 is log-structured and recovers on mount, its `fsck0.nilfs2` is a
+/// manual tool).
+pub const KNOWN_FILESYSTEMS: &[&str] = &[
+    "bcachefs",
+    "btrfs",
+    "ext2",
+    "ext3",
+    "ext4",
+    "exfat",
+    "f2fs",
+    "fat",
+    "fuse.exfat",
+    "fuseblk",
+    "hfsplus",
+    "iso9660",
+    "jfs",
+    "msdos",
+    "nilfs2",
+    "none",
+    "ntfs",
+    "ntfs3",
+    "overlay",
+    "reiserfs",
+    "swap",
+    "udf",
+    "vfat",
+    "xfs",
+    "zfs",
+];
+
+/// Whether recfstab recognizes a filesystem type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FstypeClass {
+    /// Pseudo, network, cluster, read-only image, or a known local filesystem
+    Known,
+    /// Anything else; the generated entry may need checking by hand
+    Unknown,
+}
+
+/// Classify `fstype` as [`FstypeClass::Known`] or [`FstypeClass::Unknown`].
+pub fn classify_fstype(fstype: &str) -> FstypeClass {
+    if KNOWN_FILESYSTEMS.contains(&fstype)
+        || is_pseudo_filesystem(fstype)
+        || is_network_filesystem(fstype)
+        || is_cluster_filesystem(fstype)
+        || is_read_only_filesystem(fstype)
+        || crate::fstab::needs_fsck(fstype)
+    {
+        FstypeClass::Known
+    } else {
+        FstypeClass::Unknown
+    }
+}
+
+/// Warning for an entry whose fstype recfstab does not recognize.
+///
+/// Such an entry gets pass 0 and generic option filtering, which may not
+/// suit it. Suppressed with `--skip-fstype-warnings`.
+pub fn unknown_fstype_warning(mountpoint: &str, fstype: &str) -> Option<String> {
+    (classify_fstype(fstype) == FstypeClass::Unknown).then(|| {
+        format!(
+            "{} has unrecognized fstype {}; check its options and pass number",
+            mountpoint, fstype
+        )
+    })
+}
+
+/// Check if a filesystem type is mounted over the network.
+pub fn is_network_filesystem(fstype: &str) -> bool {
+    NETWORK_FILESYSTEMS.contains(&fstype)
+}
+
+/// Check if a filesystem type is served by a FUSE daemon (`fuse`,
+/// `fuseblk`, `fuse.<subtype>`).
+pub fn is_fuse_filesystem(fstype: &str) -> bool {
+    fstype == "fuse" || fstype == "fuseblk" || fstype.starts_with("fuse.")
+}
+
 /// Check if a filesystem type is a pseudo-filesystem that should be excluded.
 pub fn is_pseudo_filesystem(fstype: &str) -> bool {
     PSEUDO_FILESYSTEMS.contains(&fstype)
 }
 
+/// Optional transformations applied by [`filter_options_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionFilter {
+    /// Sort the kept options alphabetically, for reproducible diffs.
+    ///
+    /// Safe because the kernel and mount(8) don't depend on option order.
+    pub sort: bool,
+    /// Keep `ro` instead of dropping it with the other runtime options, so a
+    /// read-only mount stays read-only.
+    pub keep_ro: bool,
+    /// Keep whichever of `ro`/`rw` is in effect (the last one given), so the
+    /// entry mounts the way the filesystem is mounted now.
+    pub preserve_mount_state: bool,
+    /// Put generic VFS options ([`GENERIC_OPTIONS`]) before fs-specific ones,
+    /// keeping the relative order within each group.
+    pub normalize_order: bool,
+    /// Leave an explicit `rw` instead of `defaults` when no option is kept
+    /// (`--no-defaults-fallback`).
+    pub no_defaults: bool,
+    /// Drop every option this pattern matches (`--strip-options-regex`)
+    #[cfg(feature = "regex")]
+    pub strip: Option<OptionPattern>,
+}
+
+/// A compiled `--strip-options-regex` pattern, matched against each option
+/// (`x-systemd.automount`, `uhelper=udisks2`, ...).
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct OptionPattern(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl OptionPattern {
+    /// Compile `pattern`.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Self)
+    }
+
+    /// Check whether `option` matches anywhere (anchor with `^`/`$`).
+    pub fn is_match(&self, option: &str) -> bool {
+        self.0.is_match(option)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl std::str::FromStr for OptionPattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::new(pattern)
+    }
+}
+
+// Compiled regexes have no equality; the same pattern is the same filter
+#[cfg(feature = "regex")]
+impl PartialEq for OptionPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for OptionPattern {}
+
+/// Mount options that apply to any filesystem (mount(8) "FILESYSTEM-
+/// INDEPENDENT MOUNT OPTIONS"), plus the `x-*` userspace options.
+pub const GENERIC_OPTIONS: &[&str] = &[
+    "_netdev",
+    "async",
+    "atime",
+    "auto",
+    "bind",
+    "defaults",
+    "dev",
+    "diratime",
+    "dirsync",
+    "exec",
+    "group",
+    "iversion",
+    "lazytime",
+    "mand",
+    "noatime",
+    "noauto",
+    "nodev",
+    "nodiratime",
+    "noexec",
+    "nofail",
+    "noiversion",
+    "nomand",
+    "norelatime",
+    "nostrictatime",
+    "nosuid",
+    "nosymfollow",
+    "nouser",
+    "owner",
+    "rbind",
+    "relatime",
+    "ro",
+    "rw",
+    "strictatime",
+    "suid",
+    "sync",
+    "user",
+    "users",
+];
+
+/// Check whether a mount option is filesystem-independent (see
+/// [`GENERIC_OPTIONS`]).
+pub fn is_generic_option(option: &str) -> bool {
+    GENERIC_OPTIONS.contains(&option) || option.starts_with("x-") || option.starts_with("comment=")
+}
+
+/// Check if a mount is a tmpfs with an explicit `size=` option.
+///
+/// A sized tmpfs was most likely configured deliberately, unlike the
+/// default-sized ones systemd and the initramfs create.
+pub fn is_sized_tmpfs(fstype: &str, options: &str) -> bool {
+    fstype == "tmpfs"
+        && options
+            .split(',')
+            .any(|opt| opt.trim().starts_with("size="))
+}
+
 /// Filter out runtime-only mount options that shouldn't be in fstab.
 ///
 /// Handles edge cases like leading/trailing commas, empty options, and whitespace.
 pub fn filter_options(options: &str) -> String {
-    let filtered: Vec<&str> = options
+    filter_options_with(options, &OptionFilter::default())
+}
+
+/// Check whether an options string describes a bind mount (`bind`/`rbind`).
+///
+/// findmnt never reports these flags for live mounts (see
+/// [`MountInfo::is_bind`](crate::mount::MountInfo::is_bind) for those); they
+/// appear in options supplied by a
+/// [`MountProvider`](crate::mount::MountProvider) or an existing fstab.
+pub fn is_bind_options(options: &str) -> bool {
+    options
         .split(',')
-        .map(|opt| opt.trim()) // Handle whitespace around options
-        .filter(|opt| {
-            !opt.is_empty() && !RUNTIME_OPTIONS.contains(opt) && !opt.starts_with("subvolid=")
+        .any(|opt| matches!(opt.trim(), "bind" | "rbind"))
+}
+
+/// Like [`filter_options`], with the extra transformations in `filter`.
+///
+/// On a bind mount `ro` is always kept: a read-only bind is a deliberate
+/// view of a writable tree, not runtime state.
+pub fn filter_options_with(options: &str, filter: &OptionFilter) -> String {
+    let keep_ro = filter.keep_ro || is_bind_options(options);
+    let opts: Vec<&str> = options.split(',').map(|opt| opt.trim()).collect(); // Handle whitespace around options
+
+    // The mount state in effect is the last ro/rw; any earlier one is dropped
+    let state = opts
+        .iter()
+        .rposition(|opt| matches!(*opt, "ro" | "rw"))
+        .filter(|_| filter.preserve_mount_state);
+    let mut filtered: Vec<&str> = opts
+        .iter()
+        .enumerate()
+        .filter(|&(index, opt)| {
+            !opt.is_empty()
+                && (!RUNTIME_OPTIONS.contains(opt)
+                    || Some(index) == state
+                    || (keep_ro && state.is_none() && *opt == "ro"))
+                && !opt.starts_with("subvolid=")
         })
+        .map(|(_, opt)| *opt)
         .collect();
+    #[cfg(feature = "regex")]
+    if let Some(pattern) = &filter.strip {
+        filtered.retain(|opt| !pattern.is_match(opt));
+    }
+
+    if filter.sort {
+        filtered.sort_unstable();
+    }
+    if filter.normalize_order {
+        // Stable, so each group keeps its (possibly sorted) order
+        filtered.sort_by_key(|opt| !is_generic_option(opt));
+    }
 
     if filtered.is_empty() {
-        "defaults".to_string()
+        if filter.no_defaults { "rw" } else { "defaults" }.to_string()
     } else {
         filtered.join(",")
     }
 }
 
+/// GPT partition type GUID of an EFI System Partition.
+pub const ESP_PARTTYPE: &str = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+
+/// Mountpoints (relative to the target root) where an ESP is conventionally mounted.
+pub const ESP_MOUNTPOINTS: &[&str] = &["/boot/efi", "/efi", "/boot"];
+
+/// Check whether a mount is an EFI System Partition.
+///
+/// An ESP is a FAT filesystem that is either at a conventional ESP mountpoint
+/// or on a partition typed as an ESP. `parttype` returns the device's GPT
+/// PARTTYPE (bare GUID or `PARTTYPE=` form) and is only called when needed.
+pub fn is_esp_with<F>(mountpoint: &str, fstype: &str, device: &str, parttype: F) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !matches!(fstype, "vfat" | "fat" | "msdos") {
+        return false;
+    }
+    if ESP_MOUNTPOINTS.contains(&mountpoint) {
+        return true;
+    }
+    parttype(device).is_some_and(|value| {
+        value
+            .strip_prefix("PARTTYPE=")
+            .unwrap_or(&value)
+            .eq_ignore_ascii_case(ESP_PARTTYPE)
+    })
+}
+
+/// Check whether an options string mounts read-only (`ro` is present).
+pub fn is_read_only(options: &str) -> bool {
+    options.split(',').any(|opt| opt.trim() == "ro")
+}
+
+/// Make an options string read-only: drop `rw` and `defaults`, lead with `ro`.
+pub fn force_readonly(options: &str) -> String {
+    let rest: Vec<&str> = options
+        .split(',')
+        .map(|opt| opt.trim())
+        .filter(|opt| !opt.is_empty() && !matches!(*opt, "ro" | "rw" | "defaults"))
+        .collect();
+
+    if rest.is_empty() {
+        "ro".to_string()
+    } else {
+        format!("ro,{}", rest.join(","))
+    }
+}
+
+/// Append `option` to an options string unless it is already present.
+pub fn append_option(options: &str, option: &str) -> String {
+    if options.split(',').any(|opt| opt.trim() == option) {
+        options.to_string()
+    } else if options.trim().is_empty() {
+        option.to_string()
+    } else {
+        format!("{},{}", options, option)
+    }
+}
+
 /// Check if a filesystem type is empty or whitespace.
 pub fn is_valid_fstype(fstype: &str) -> bool {
     !fstype.trim().is_empty()
 }
 
+/// Give a bare `fuse` fstype the subtype its `source` implies.
+///
+/// findmnt usually reports FUSE mounts as `fuse.<subtype>`, but a helper
+/// that does not set the subtype leaves plain `fuse`. The subtype is taken
+/// from the legacy `type#spec` source (`sshfs#user@host:/` -> `fuse.sshfs`),
+/// an sshfs-style `[user@]host:path` source (`fuse.sshfs`), or a source
+/// that is just a program name (`portal` -> `fuse.portal`). Any other
+/// fstype, or a source that says nothing, is returned unchanged.
+pub fn normalize_fuse_fstype(fstype: &str, source: &str) -> String {
+    if fstype != "fuse" {
+        return fstype.to_string();
+    }
+    let source = source.trim();
+    let subtype = match source.split_once('#') {
+        Some((program, _)) => Some(program),
+        None => match source.split_once(':') {
+            Some((host, _)) if !host.is_empty() && !host.contains('/') => Some("sshfs"),
+            Some(_) => None,
+            None => Some(source).filter(|name| {
+                !matches!(*name, "" | "none" | "fuse")
+                    && !name.contains('/')
+                    && !name.contains(char::is_whitespace)
+            }),
+        },
+    };
+    match subtype {
+        Some(subtype) if !subtype.is_empty() => format!("fuse.{}", subtype),
+        _ => fstype.to_string(),
+    }
+}
+
+/// Drop the legacy `type#` prefix from a FUSE source (`sshfs#user@host:/`
+/// -> `user@host:/`), which a `fuse.<type>` entry must not repeat.
+pub fn strip_fuse_subtype(source: &str) -> &str {
+    source.split_once('#').map_or(source, |(_, spec)| spec)
+}
+
+/// Strip trailing slashes from a root path, keeping "/" for the filesystem root.
+pub fn normalize_root(root_str: &str) -> &str {
+    let trimmed = root_str.trim_end_matches('/');
+    if trimmed.is_empty() && !root_str.is_empty() {
+        "/"
+    } else {
+        trimmed
+    }
+}
+
 /// Check if a mount target is under the given root path.
+///
+/// A trailing slash on `root_str` is ignored, so "/mnt/" behaves like "/mnt".
 pub fn is_under_root(target: &str, root_str: &str) -> bool {
+    let root_str = normalize_root(root_str);
     if root_str == "/" {
         true // Everything is under "/"
     } else {
@@ -289,4 +712,234 @@ mod tests {
         // fuse.ntfs-3g is also a real filesystem (FUSE-based NTFS)
         assert!(!is_pseudo_filesystem("fuse.ntfs-3g"));
     }
+
+    #[test]
+    fn test_normalize_root() {
+        assert_eq!(normalize_root("/mnt/"), "/mnt");
+        assert_eq!(normalize_root("/mnt//"), "/mnt");
+        assert_eq!(normalize_root("/mnt"), "/mnt");
+        assert_eq!(normalize_root("/"), "/");
+        assert_eq!(normalize_root("//"), "/");
+    }
+
+    #[test]
+    fn test_is_under_root_trailing_slash() {
+        // Regression guard: library callers may pass an un-normalized root
+        assert!(is_under_root("/mnt/boot", "/mnt/"));
+        assert!(is_under_root("/mnt", "/mnt/"));
+        assert!(!is_under_root("/mntextra", "/mnt/"));
+        assert!(is_under_root("/anything", "//"));
+    }
+
+    #[test]
+    fn test_force_readonly() {
+        assert_eq!(force_readonly("defaults"), "ro");
+        assert_eq!(force_readonly("rw,compress=zstd"), "ro,compress=zstd");
+        assert_eq!(force_readonly("ro,subvol=/@"), "ro,subvol=/@");
+        assert_eq!(force_readonly(""), "ro");
+    }
+
+    #[test]
+    fn test_unknown_fstype_warning() {
+        assert_eq!(classify_fstype("ext4"), FstypeClass::Known);
+        assert_eq!(classify_fstype("nfs4"), FstypeClass::Known);
+        assert_eq!(classify_fstype("proc"), FstypeClass::Known);
+        assert_eq!(classify_fstype("myfs"), FstypeClass::Unknown);
+
+        assert_eq!(
+            unknown_fstype_warning("/data", "myfs").as_deref(),
+            Some("/data has unrecognized fstype myfs; check its options and pass number")
+        );
+        assert_eq!(unknown_fstype_warning("/", "ext4"), None);
+    }
+
+    #[test]
+    fn test_append_option() {
+        assert_eq!(
+            append_option("defaults", "x-mount.mkdir"),
+            "defaults,x-mount.mkdir"
+        );
+        assert_eq!(
+            append_option("noatime,x-mount.mkdir", "x-mount.mkdir"),
+            "noatime,x-mount.mkdir"
+        );
+        assert_eq!(append_option("", "x-mount.mkdir"), "x-mount.mkdir");
+    }
+
+    #[test]
+    fn test_filter_options_with_normalize_order() {
+        let normalize = OptionFilter {
+            normalize_order: true,
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with("compress=zstd,nodev,subvol=/@", &normalize),
+            "nodev,compress=zstd,subvol=/@"
+        );
+        assert_eq!(
+            filter_options_with("errors=remount-ro,x-systemd.automount,nosuid", &normalize),
+            "x-systemd.automount,nosuid,errors=remount-ro"
+        );
+        // Combined with sorting, each group is sorted on its own
+        let both = OptionFilter {
+            sort: true,
+            ..normalize
+        };
+        assert_eq!(
+            filter_options_with("ssd,nosuid,compress=zstd,nodev", &both),
+            "nodev,nosuid,compress=zstd,ssd"
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_filter_options_with_strip_pattern() {
+        let strip = OptionFilter {
+            strip: Some(OptionPattern::new(r"^x-systemd\.").unwrap()),
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with(
+                "rw,nofail,x-systemd.automount,x-systemd.idle-timeout=60,x-mount.mkdir",
+                &strip
+            ),
+            "nofail,x-mount.mkdir"
+        );
+        assert_eq!(
+            filter_options_with("x-systemd.automount", &strip),
+            "defaults"
+        );
+        assert!("(".parse::<OptionPattern>().is_err());
+    }
+
+    #[test]
+    fn test_filter_options_with_sort() {
+        let sort = OptionFilter {
+            sort: true,
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with("ssd,compress=zstd,space_cache=v2", &sort),
+            "compress=zstd,space_cache=v2,ssd"
+        );
+        // Runtime options and subvolid are still dropped; subvol= is kept
+        assert_eq!(
+            filter_options_with("rw,subvolid=256,subvol=/@,noatime,compress=zstd", &sort),
+            "compress=zstd,subvol=/@"
+        );
+        assert_eq!(filter_options_with("rw,relatime", &sort), "defaults");
+        // Without the toggle, order is preserved
+        assert_eq!(
+            filter_options_with("ssd,compress=zstd", &OptionFilter::default()),
+            "ssd,compress=zstd"
+        );
+    }
+
+    #[test]
+    fn test_filter_options_with_keep_ro() {
+        let keep_ro = OptionFilter {
+            keep_ro: true,
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with("ro,relatime,errors=remount-ro", &keep_ro),
+            "ro,errors=remount-ro"
+        );
+        assert_eq!(filter_options_with("rw,relatime", &keep_ro), "defaults");
+        assert!(is_read_only("ro,errors=remount-ro"));
+        assert!(!is_read_only("rw,errors=remount-ro"));
+    }
+
+    #[test]
+    fn test_filter_options_with_preserve_mount_state() {
+        let preserve = OptionFilter {
+            preserve_mount_state: true,
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with("ro,relatime,errors=remount-ro", &preserve),
+            "ro,errors=remount-ro"
+        );
+        assert_eq!(filter_options_with("rw,relatime", &preserve), "rw");
+        assert_eq!(filter_options_with("rw,nodev", &preserve), "rw,nodev");
+        // Only the state in effect is kept, never both
+        assert_eq!(filter_options_with("ro,nosuid,rw", &preserve), "nosuid,rw");
+        assert_eq!(filter_options_with("relatime", &preserve), "defaults");
+        // Without the flag both are runtime-only, as before
+        assert_eq!(filter_options("ro,relatime"), "defaults");
+    }
+
+    #[test]
+    fn test_filter_options_with_no_defaults() {
+        let no_defaults = OptionFilter {
+            no_defaults: true,
+            ..OptionFilter::default()
+        };
+        assert_eq!(
+            filter_options_with("rw,relatime,seclabel", &no_defaults),
+            "rw"
+        );
+        assert_eq!(filter_options_with("rw,nodev", &no_defaults), "nodev");
+    }
+
+    #[test]
+    fn test_normalize_fuse_fstype() {
+        assert_eq!(
+            normalize_fuse_fstype("fuse", "sshfs#user@host:"),
+            "fuse.sshfs"
+        );
+        assert_eq!(
+            normalize_fuse_fstype("fuse", "user@host:/srv/data"),
+            "fuse.sshfs"
+        );
+        assert_eq!(normalize_fuse_fstype("fuse", "portal"), "fuse.portal");
+        assert_eq!(strip_fuse_subtype("sshfs#user@host:"), "user@host:");
+        // Nothing to derive from
+        assert_eq!(normalize_fuse_fstype("fuse", "/dev/sdb1"), "fuse");
+        assert_eq!(normalize_fuse_fstype("fuse", "none"), "fuse");
+        assert_eq!(normalize_fuse_fstype("fuse", "#x"), "fuse");
+        // Only a bare fuse fstype is touched
+        assert_eq!(
+            normalize_fuse_fstype("fuse.rclone", "remote:bucket"),
+            "fuse.rclone"
+        );
+        assert_eq!(normalize_fuse_fstype("nfs", "host:/export"), "nfs");
+    }
+
+    #[test]
+    fn test_filter_options_keeps_ro_on_bind() {
+        assert_eq!(filter_options("bind,ro,relatime"), "bind,ro");
+        assert_eq!(filter_options("ro,rbind"), "ro,rbind");
+        // A plain read-only mount still loses ro unless keep_ro is set
+        assert_eq!(
+            filter_options("ro,relatime,errors=remount-ro"),
+            "errors=remount-ro"
+        );
+        assert!(is_bind_options("rw,bind"));
+        assert!(!is_bind_options("rw,binder"));
+    }
+
+    #[test]
+    fn test_is_esp_with() {
+        let no_parttype = |_: &str| None;
+        assert!(is_esp_with("/boot/efi", "vfat", "/dev/sda1", no_parttype));
+        assert!(is_esp_with("/efi", "vfat", "/dev/sda1", no_parttype));
+        // A data vfat partition is not an ESP
+        assert!(!is_esp_with("/data", "vfat", "/dev/sdb1", no_parttype));
+        // Non-FAT filesystems at ESP mountpoints are not ESPs
+        assert!(!is_esp_with("/boot", "ext4", "/dev/sda2", no_parttype));
+
+        // ESP at an unusual mountpoint, detected by partition type
+        let esp_type = |_: &str| Some("PARTTYPE=C12A7328-F81F-11D2-BA4B-00A0C93EC93B".to_string());
+        assert!(is_esp_with("/mnt/esp", "vfat", "/dev/sda1", esp_type));
+    }
+
+    #[test]
+    fn test_is_sized_tmpfs() {
+        assert!(is_sized_tmpfs("tmpfs", "rw,nosuid,size=2G,mode=1777"));
+        assert!(!is_sized_tmpfs("tmpfs", "rw,nosuid,nodev"));
+        assert!(!is_sized_tmpfs("ramfs", "size=2G"));
+        // nr_inodes is not a size
+        assert!(!is_sized_tmpfs("tmpfs", "rw,nr_inodes=1024"));
+    }
 }
@@ -1,5 +1,118 @@
 //! Fstab entry formatting and output.
 
+use crate::filter::{is_cluster_filesystem, is_read_only, is_read_only_filesystem, normalize_root};
+
+/// A single generated fstab entry.
+///
+/// Fields hold unescaped values; escaping is applied when rendering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEntry {
+    /// Comment lines printed above the entry (without the leading `# `)
+    pub comments: Vec<String>,
+    /// First field: device identifier (UUID=..., /dev/..., server:/share)
+    pub fs_spec: String,
+    /// Second field: mount point relative to the target root
+    pub mountpoint: String,
+    /// Third field: filesystem type
+    pub fstype: String,
+    /// Fourth field: mount options
+    pub options: String,
+    /// Fifth field: dump frequency
+    pub dump: u8,
+    /// Sixth field: fsck pass number
+    pub pass: u8,
+    /// Exact mount point bytes when the path is not valid UTF-8; rendered
+    /// with [`escape_fstab_bytes`] in place of `mountpoint`
+    pub mountpoint_bytes: Option<Vec<u8>>,
+    /// The requested identifier was not found and `fs_spec` fell back to the
+    /// device path (not rendered; checked by `--fail-on-fallback`)
+    pub id_fallback: bool,
+    /// Block device the entry was generated from (not rendered; groups
+    /// entries for `--group-by device`)
+    pub device: Option<String>,
+}
+
+impl FstabEntry {
+    /// Format the tab-separated data line (without trailing newline).
+    pub fn data_line(&self) -> String {
+        self.data_line_with(OutputEncoding::Utf8)
+    }
+
+    /// Like [`FstabEntry::data_line`], escaping paths for `encoding`.
+    pub fn data_line_with(&self, encoding: OutputEncoding) -> String {
+        let mountpoint = match &self.mountpoint_bytes {
+            // Every non-ASCII byte is escaped, so the conversion is lossless
+            Some(bytes) => String::from_utf8_lossy(&escape_fstab_bytes(bytes)).into_owned(),
+            None => escape_fstab_with(&self.mountpoint, encoding),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape_fstab_with(&self.fs_spec, encoding),
+            mountpoint,
+            self.fstype,
+            self.options,
+            self.dump,
+            self.pass
+        )
+    }
+
+    /// Render the entry as comment lines, the data line, and a blank separator.
+    pub fn render(&self) -> String {
+        self.render_with(OutputEncoding::Utf8)
+    }
+
+    /// Like [`FstabEntry::render`], escaping paths for `encoding`.
+    ///
+    /// Comments are written as they are.
+    pub fn render_with(&self, encoding: OutputEncoding) -> String {
+        let mut out = String::new();
+        for comment in &self.comments {
+            out.push_str("# ");
+            out.push_str(comment);
+            out.push('\n');
+        }
+        out.push_str(&self.data_line_with(encoding));
+        out.push_str("\n\n");
+        out
+    }
+
+    /// Re-wrap every comment with [`wrap_comment`] to fit `width` columns.
+    pub fn wrap_comments(&mut self, width: usize) {
+        self.comments = self
+            .comments
+            .iter()
+            .flat_map(|comment| wrap_comment(comment, width))
+            .collect();
+    }
+
+    /// Re-parse the rendered entry and check it reads back as one 6-field line.
+    ///
+    /// Catches escaping regressions (e.g. an unescaped space splitting a
+    /// field, or a newline in a comment) before they reach a real fstab.
+    /// Returns a description of the problem on failure.
+    pub fn self_check(&self) -> Result<(), String> {
+        let rendered = self.render();
+        let data: Vec<&str> = rendered
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        let [line] = data[..] else {
+            return Err(format!("expected 1 data line, got {}", data.len()));
+        };
+
+        let fields = line.split_whitespace().count();
+        if fields != 6 {
+            return Err(format!("expected 6 fields, got {} in '{}'", fields, line));
+        }
+        let parsed = parse_fstab_line(line).ok_or_else(|| format!("unparseable '{}'", line))?;
+        let mountpoint_ok = self.mountpoint_bytes.is_some() || parsed.mountpoint == self.mountpoint;
+        if parsed.fs_spec != self.fs_spec || !mountpoint_ok {
+            return Err(format!("'{}' does not read back as written", line));
+        }
+        Ok(())
+    }
+}
+
 /// Escape special characters for fstab output.
 ///
 /// fstab(5) requires special characters to be octal-escaped because the line
@@ -8,9 +121,31 @@
 /// - Backslash (\134) - escape character itself
 /// - Hash (\043) - comment character at start of field
 pub fn escape_fstab(s: &str) -> String {
+    escape_fstab_with(s, OutputEncoding::Utf8)
+}
+
+/// How non-ASCII characters in paths are written (`--output-encoding`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputEncoding {
+    /// Write UTF-8 as is (default)
+    #[default]
+    Utf8,
+    /// Octal-escape every non-ASCII byte, so the fstab is plain ASCII
+    EscapeAll,
+}
+
+/// [`escape_fstab`] for `encoding`: with [`OutputEncoding::EscapeAll`] each
+/// UTF-8 byte of a non-ASCII character is escaped too (`é` -> `\303\251`).
+pub fn escape_fstab_with(s: &str, encoding: OutputEncoding) -> String {
     let mut result = String::with_capacity(s.len() * 2); // Worst case: all chars escaped
     for c in s.chars() {
         match c {
+            c if !c.is_ascii() && encoding == OutputEncoding::EscapeAll => {
+                let mut utf8 = [0; 4];
+                for b in c.encode_utf8(&mut utf8).bytes() {
+                    result.push_str(&format!("\\{:03o}", b));
+                }
+            }
             '\\' => result.push_str("\\134"),
             ' ' => result.push_str("\\040"),
             '\t' => result.push_str("\\011"),
@@ -23,16 +158,259 @@ pub fn escape_fstab(s: &str) -> String {
     result
 }
 
+/// Byte-oriented [`escape_fstab`] for paths that are not valid UTF-8.
+///
+/// Besides the characters `escape_fstab` handles, every other control byte
+/// and every non-ASCII byte is octal-escaped, so the result is plain ASCII
+/// that libmount decodes back to the original bytes.
+pub fn escape_fstab_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len() * 4); // Worst case: all bytes escaped
+    for &b in bytes {
+        if b.is_ascii_graphic() && b != b'\\' && b != b'#' {
+            result.push(b);
+        } else {
+            result.extend_from_slice(format!("\\{:03o}", b).as_bytes());
+        }
+    }
+    result
+}
+
+/// Reverse [`escape_fstab`]: decode `\NNN` octal escapes in an fstab field.
+///
+/// Backslashes not followed by three octal digits are kept literally, as
+/// libmount does.
+pub fn unescape_fstab(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4]
+                .iter()
+                .all(|b| (b'0'..=b'7').contains(b))
+        {
+            let value = (bytes[i + 1] - b'0') as u32 * 64
+                + (bytes[i + 2] - b'0') as u32 * 8
+                + (bytes[i + 3] - b'0') as u32;
+            if let Ok(byte) = u8::try_from(value) {
+                out.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Word-wrap comment `text` so each `# ` line is at most `width` columns
+/// (`--comment-width`).
+///
+/// Breaks only at spaces; a word longer than a line is kept whole on a line
+/// of its own.
+pub fn wrap_comment(text: &str, width: usize) -> Vec<String> {
+    let limit = width.saturating_sub("# ".len()).max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ').filter(|word| !word.is_empty()) {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > limit {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Parse one data line of an existing fstab.
+///
+/// Returns `None` for blank lines, comments, and lines with fewer than the
+/// two required fields. Missing trailing fields take fstab(5) defaults
+/// (`auto`, `defaults`, 0, 0); unparseable dump/pass values become 0.
+pub fn parse_fstab_line(line: &str) -> Option<FstabEntry> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split_whitespace();
+    let fs_spec = unescape_fstab(fields.next()?);
+    let mountpoint = unescape_fstab(fields.next()?);
+    let fstype = fields.next().unwrap_or("auto").to_string();
+    let options = fields.next().unwrap_or("defaults").to_string();
+    let dump = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+    let pass = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+
+    Some(FstabEntry {
+        comments: Vec::new(),
+        fs_spec,
+        mountpoint,
+        fstype,
+        options,
+        dump,
+        pass,
+        mountpoint_bytes: None,
+        id_fallback: false,
+        device: None,
+    })
+}
+
+/// Parse the contents of an existing fstab into entries.
+///
+/// Comment lines directly above an entry are attached to it (without the
+/// leading `#`); a blank line detaches them.
+pub fn parse_fstab(content: &str) -> Vec<FstabEntry> {
+    let mut entries = Vec::new();
+    let mut comments = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comments.clear();
+        } else if let Some(comment) = trimmed.strip_prefix('#') {
+            comments.push(comment.strip_prefix(' ').unwrap_or(comment).to_string());
+        } else if let Some(mut entry) = parse_fstab_line(trimmed) {
+            entry.comments = std::mem::take(&mut comments);
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+/// Byte-oriented [`make_fstab_target`] for targets that are not valid UTF-8.
+pub fn make_fstab_target_bytes(target: &[u8], root_str: &str) -> Vec<u8> {
+    let root = normalize_root(root_str).as_bytes();
+    let stripped = target.strip_prefix(root).unwrap_or(target);
+    if stripped.is_empty() {
+        b"/".to_vec()
+    } else if stripped.starts_with(b"/") {
+        stripped.to_vec()
+    } else {
+        [b"/", stripped].concat()
+    }
+}
+
+/// Rebase a root-relative fstab target under `prefix` (`--target-prefix`).
+///
+/// `"/"` becomes the prefix itself; with no prefix the target is unchanged.
+pub fn prefix_target(prefix: Option<&str>, target: &str) -> String {
+    let Some(prefix) = prefix.map(normalize_root).filter(|p| *p != "/") else {
+        return target.to_string();
+    };
+    if target == "/" {
+        prefix.to_string()
+    } else {
+        format!("{}{}", prefix, target)
+    }
+}
+
+/// Byte-oriented [`prefix_target`].
+pub fn prefix_target_bytes(prefix: Option<&str>, target: &[u8]) -> Vec<u8> {
+    let Some(prefix) = prefix.map(normalize_root).filter(|p| *p != "/") else {
+        return target.to_vec();
+    };
+    if target == b"/" {
+        prefix.as_bytes().to_vec()
+    } else {
+        [prefix.as_bytes(), target].concat()
+    }
+}
+
+/// How mountpoint letter case is written (`--normalize-mountpoint-case`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MountpointCase {
+    /// Keep the mount target's case (default)
+    #[default]
+    Preserve,
+    /// Lowercase the whole mountpoint, e.g. `/mnt/Windows` -> `/mnt/windows`
+    Lower,
+}
+
+impl MountpointCase {
+    /// Apply to a root-relative mountpoint.
+    pub fn apply(&self, target: &str) -> String {
+        match self {
+            MountpointCase::Preserve => target.to_string(),
+            MountpointCase::Lower => target.to_lowercase(),
+        }
+    }
+
+    /// Byte-oriented [`MountpointCase::apply`]; bytes that are not UTF-8
+    /// only have ASCII letters lowercased.
+    pub fn apply_bytes(&self, target: &[u8]) -> Vec<u8> {
+        match (self, std::str::from_utf8(target)) {
+            (MountpointCase::Preserve, _) => target.to_vec(),
+            (MountpointCase::Lower, Ok(target)) => target.to_lowercase().into_bytes(),
+            (MountpointCase::Lower, Err(_)) => target.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Header of Arch Linux's stock `/etc/fstab` (`--template-header`).
+///
+/// genfstab output is usually appended right after it, so it ends with the
+/// column legend and no blank line.
+pub const TEMPLATE_HEADER: &str = "# Static information about the filesystems.\n\
+                                   # See fstab(5) for details.\n\
+                                   \n\
+                                   # <file system> <dir> <type> <options> <dump> <pass>\n";
+
+/// Lexically normalize a path: collapse repeated slashes, drop `.` segments
+/// and a trailing slash, and resolve `..` against the preceding segment.
+///
+/// Nothing is looked up on disk, so symlinks are not followed; `..` at the
+/// root of an absolute path stays at the root.
+pub fn normalize_path(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if matches!(segments.last(), Some(last) if *last != "..") {
+                    segments.pop();
+                } else if !absolute {
+                    segments.push("..");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let joined = segments.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else if joined.is_empty() {
+        ".".to_string()
+    } else {
+        joined
+    }
+}
+
 /// Convert an absolute mount target to a path relative to the root.
 ///
 /// Returns "/" for the root mount, or the relative path for submounts.
-/// Handles edge cases like empty strings gracefully.
+/// Handles edge cases like empty strings and a trailing slash on the root.
 pub fn make_fstab_target(target: &str, root_str: &str) -> String {
     // Handle empty input
     if target.is_empty() {
         return "/".to_string();
     }
 
+    // Compare lexically clean paths so `//` and `.` segments can't defeat
+    // the prefix match
+    let root = normalize_path(root_str);
+    let root_str = normalize_root(&root);
+    let target = &normalize_path(target);
+
     if target == root_str {
         "/".to_string()
     } else {
@@ -64,14 +442,80 @@ pub fn determine_pass_number(fstab_target: &str, fstype: &str) -> u8 {
     }
 }
 
-/// Check if a filesystem type supports/needs fsck at boot.
+/// Like [`determine_pass_number`] (or the genfstab rules), but also looks at
+/// the entry's final options.
+///
+/// With `genfstab_compat` the genfstab pass is returned unchanged. Otherwise
+/// a read-only (`ro`) mount other than root gets pass 0 (see
+/// [`read_only_pass`]). Root keeps its pass either way, unless it is a
+/// read-only image (squashfs, erofs), which has no checker at all, or a
+/// cluster filesystem (gfs2, ocfs2), which other nodes may have mounted.
+pub fn determine_pass_number_with(
+    fstab_target: &str,
+    fstype: &str,
+    options: &str,
+    genfstab_compat: bool,
+) -> u8 {
+    if genfstab_compat {
+        determine_pass_number_genfstab(fstab_target, fstype)
+    } else if is_read_only_filesystem(fstype) || is_cluster_filesystem(fstype) {
+        0
+    } else {
+        read_only_pass(determine_pass_number(fstab_target, fstype), options)
+    }
+}
+
+/// Drop a non-root pass (2) to 0 when `options` mount read-only.
+///
+/// Nothing writes to a read-only mount, so there is nothing for a boot-time
+/// check to repair.
+pub fn read_only_pass(pass: u8, options: &str) -> u8 {
+    if pass == 2 && is_read_only(options) {
+        0
+    } else {
+        pass
+    }
+}
+
+/// Filesystem types that genfstab gives a non-zero pass number.
+///
+/// genfstab probes `$PATH` for `fsck.<fstype>`; this is the set of types whose
+/// checker ships with the standard userspace tools (e2fsprogs, dosfstools,
+/// exfatprogs, xfsprogs, f2fs-tools, ...). genfstab special-cases btrfs and
+/// swap to pass 0 even though `fsck.btrfs` exists.
+pub const GENFSTAB_FSCK_FILESYSTEMS: &[&str] = &[
+    "cramfs", "exfat", "ext2", "ext3", "ext4", "f2fs", "fat", "jfs", "minix", "msdos", "reiserfs",
+    "vfat", "xfs",
+];
+
+/// Determine the fsck pass number the way Arch's `genfstab` does.
+///
+/// Differences from [`determine_pass_number`]:
+/// - vfat/exfat (and other FAT types) get pass 2, so the ESP is checked
+/// - Root only gets pass 1 if its fstype has a checker; a btrfs root gets 0
+pub fn determine_pass_number_genfstab(fstab_target: &str, fstype: &str) -> u8 {
+    if !GENFSTAB_FSCK_FILESYSTEMS.contains(&fstype) {
+        0
+    } else if fstab_target == "/" {
+        1
+    } else {
+        2
+    }
+}
+
+/// Filesystem types that get checked at boot (pass 2 when not root).
 ///
 /// Note: vfat is excluded because EFI system partitions don't need fsck
 /// (they're rarely written to after initial setup, and dosfsck has issues).
 /// Note: btrfs is excluded because it doesn't use traditional fsck - it uses
 /// `btrfs check` which should only be run manually, not at boot.
+pub const FSCK_FILESYSTEMS: &[&str] = &["ext2", "ext3", "ext4", "xfs", "f2fs"];
+
+/// Check if a filesystem type supports/needs fsck at boot.
+///
+/// See [`FSCK_FILESYSTEMS`] for the list and why vfat/btrfs are excluded.
 pub fn needs_fsck(fstype: &str) -> bool {
-    matches!(fstype, "ext2" | "ext3" | "ext4" | "xfs" | "f2fs")
+    FSCK_FILESYSTEMS.contains(&fstype)
 }
 
 #[cfg(test)]
@@ -136,6 +580,87 @@ mod tests {
         assert!(!needs_fsck("zfs"));
     }
 
+    #[test]
+    fn test_make_fstab_target_trailing_slash_root() {
+        assert_eq!(make_fstab_target("/mnt/boot", "/mnt/"), "/boot");
+        assert_eq!(make_fstab_target("/mnt", "/mnt/"), "/");
+        assert_eq!(make_fstab_target("/boot", "/"), "/boot");
+    }
+
+    #[test]
+    fn test_make_fstab_target_normalizes_paths() {
+        assert_eq!(make_fstab_target("/mnt//boot", "/mnt"), "/boot");
+        assert_eq!(make_fstab_target("/mnt/./home", "/mnt"), "/home");
+        assert_eq!(make_fstab_target("/mnt/var/../var/log", "/mnt"), "/var/log");
+        assert_eq!(make_fstab_target("/mnt/boot/.", "//mnt/"), "/boot");
+        assert_eq!(make_fstab_target("/mnt/.", "/mnt"), "/");
+    }
+
+    #[test]
+    fn test_prefix_target() {
+        assert_eq!(prefix_target(Some("/srv/guest"), "/"), "/srv/guest");
+        assert_eq!(
+            prefix_target(Some("/srv/guest/"), "/home"),
+            "/srv/guest/home"
+        );
+        assert_eq!(prefix_target(Some("/"), "/home"), "/home");
+        assert_eq!(prefix_target(None, "/home"), "/home");
+        assert_eq!(prefix_target_bytes(Some("/srv"), b"/d\xff"), b"/srv/d\xff");
+    }
+
+    #[test]
+    fn test_mountpoint_case() {
+        assert_eq!(MountpointCase::Lower.apply("/mnt/Windows"), "/mnt/windows");
+        assert_eq!(MountpointCase::Lower.apply("/Daten/Ärger"), "/daten/ärger");
+        assert_eq!(
+            MountpointCase::Preserve.apply("/mnt/Windows"),
+            "/mnt/Windows"
+        );
+        assert_eq!(MountpointCase::Lower.apply_bytes(b"/D\xff"), b"/d\xff");
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("//a///b/"), "/a/b");
+        assert_eq!(normalize_path("/a/./b/."), "/a/b");
+        assert_eq!(normalize_path("/a/b/../c"), "/a/c");
+        assert_eq!(normalize_path("/.."), "/");
+        assert_eq!(normalize_path("a/../../b"), "../b");
+    }
+
+    fn check_entry(fstype: &str, options: &str) -> FstabEntry {
+        FstabEntry {
+            comments: vec!["/dev/sda2".to_string()],
+            fs_spec: "UUID=a b".to_string(),
+            mountpoint: "/my data".to_string(),
+            fstype: fstype.to_string(),
+            options: options.to_string(),
+            dump: 0,
+            pass: 2,
+            mountpoint_bytes: None,
+            id_fallback: false,
+            device: None,
+        }
+    }
+
+    #[test]
+    fn test_self_check() {
+        // Escaped fields pass
+        assert_eq!(check_entry("ext4", "defaults").self_check(), Ok(()));
+
+        // Unescaped space in an unescaped field splits the line
+        let err = check_entry("ext4", "noatime, nodev")
+            .self_check()
+            .unwrap_err();
+        assert!(err.contains("expected 6 fields, got 7"), "{}", err);
+
+        // A newline in a comment produces a stray data line
+        let mut entry = check_entry("ext4", "defaults");
+        entry.comments = vec!["line\nUUID=x / ext4 defaults 0 1".to_string()];
+        assert!(entry.self_check().is_err());
+    }
+
     #[test]
     fn test_make_fstab_target_non_matching_prefix() {
         // When target doesn't start with root, strip_prefix returns None
@@ -150,6 +675,40 @@ mod tests {
         assert!(!needs_fsck("Ext4"));
     }
 
+    #[test]
+    fn test_determine_pass_number_with_read_only() {
+        assert_eq!(
+            determine_pass_number_with("/data", "ext4", "ro,noexec", false),
+            0
+        );
+        assert_eq!(
+            determine_pass_number_with("/data", "ext4", "noexec", false),
+            2
+        );
+        assert_eq!(
+            determine_pass_number_with("/data", "ext4", "errors=remount-ro", false),
+            2
+        );
+        assert_eq!(determine_pass_number_with("/", "ext4", "ro", false), 1);
+        // genfstab compat keeps the genfstab pass
+        assert_eq!(determine_pass_number_with("/boot", "vfat", "ro", true), 2);
+        assert_eq!(
+            determine_pass_number_with("/boot", "vfat", "defaults", true),
+            2
+        );
+        assert_eq!(determine_pass_number_with("/", "squashfs", "ro", false), 0);
+        assert_eq!(determine_pass_number_with("/", "erofs", "ro", true), 0);
+    }
+
+    #[test]
+    fn test_determine_pass_number_with_compat_skips_overrides() {
+        assert_eq!(determine_pass_number_with("/", "gfs2", "rw", false), 0);
+        assert_eq!(determine_pass_number_with("/", "gfs2", "rw", true), 0);
+        assert_eq!(determine_pass_number_with("/data", "ext4", "ro", false), 0);
+        assert_eq!(determine_pass_number_with("/data", "ext4", "ro", true), 2);
+        assert_eq!(determine_pass_number_with("/", "ext4", "ro", true), 1);
+    }
+
     #[test]
     fn test_determine_pass_root_any_fstype() {
         // Root always gets pass 1 regardless of fstype
@@ -189,6 +748,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_wrap_comment() {
+        let comment = "/dev/nvme0n1p2 UUID=0f1e2d3c-aaaa-bbbb-cccc-1234567890ab \
+                       LABEL=system TYPE=btrfs";
+        let lines = wrap_comment(comment, 40);
+        assert_eq!(
+            lines,
+            [
+                "/dev/nvme0n1p2",
+                "UUID=0f1e2d3c-aaaa-bbbb-cccc-1234567890ab",
+                "LABEL=system TYPE=btrfs"
+            ]
+        );
+        // Only the over-long word exceeds the width
+        assert!(lines
+            .iter()
+            .all(|line| line.len() + 2 <= 40 || !line.contains(' ')));
+        assert_eq!(wrap_comment("UUID=abc", 40), ["UUID=abc"]);
+        assert_eq!(wrap_comment("", 40), [""]);
+
+        let mut entry = parse_fstab_line("UUID=abc / ext4 defaults 0 1").unwrap();
+        entry.comments = vec!["one two three".to_string(), "four".to_string()];
+        entry.wrap_comments(11);
+        assert_eq!(entry.comments, ["one two", "three", "four"]);
+    }
+
+    #[test]
+    fn test_escape_fstab_with_escape_all() {
+        assert_eq!(
+            escape_fstab_with("/mnt/café", OutputEncoding::EscapeAll),
+            "/mnt/caf\\303\\251"
+        );
+        assert_eq!(
+            escape_fstab_with("/mnt/日 x", OutputEncoding::EscapeAll),
+            "/mnt/\\346\\227\\245\\040x"
+        );
+        assert_eq!(
+            escape_fstab_with("/mnt/café", OutputEncoding::Utf8),
+            "/mnt/café"
+        );
+        let escaped = escape_fstab_with("/mnt/émoji🎉", OutputEncoding::EscapeAll);
+        assert!(escaped.is_ascii());
+        assert_eq!(unescape_fstab(&escaped), "/mnt/émoji🎉");
+    }
+
     #[test]
     fn test_escape_fstab() {
         // Spaces become \040
@@ -236,4 +840,142 @@ mod tests {
         // CR + LF (Windows line ending in path)
         assert_eq!(escape_fstab("/mnt/crlf\r\nhere"), "/mnt/crlf\\015\\012here");
     }
+
+    #[test]
+    fn test_fstab_entry_render() {
+        let entry = FstabEntry {
+            comments: vec!["/dev/sda1".to_string()],
+            fs_spec: "UUID=abc".to_string(),
+            mountpoint: "/my disk".to_string(),
+            fstype: "ext4".to_string(),
+            options: "defaults".to_string(),
+            dump: 0,
+            pass: 2,
+            mountpoint_bytes: None,
+            id_fallback: false,
+            device: None,
+        };
+        assert_eq!(
+            entry.data_line(),
+            "UUID=abc\t/my\\040disk\text4\tdefaults\t0\t2"
+        );
+        assert_eq!(
+            entry.render(),
+            "# /dev/sda1\nUUID=abc\t/my\\040disk\text4\tdefaults\t0\t2\n\n"
+        );
+    }
+
+    #[test]
+    fn test_determine_pass_number_genfstab() {
+        // Known genfstab output: ext4 root 0 1, vfat /boot 0 2
+        assert_eq!(determine_pass_number_genfstab("/", "ext4"), 1);
+        assert_eq!(determine_pass_number_genfstab("/boot", "vfat"), 2);
+        assert_eq!(determine_pass_number_genfstab("/efi", "vfat"), 2);
+        assert_eq!(determine_pass_number_genfstab("/home", "xfs"), 2);
+
+        // btrfs root and subvolumes are all 0 0 in genfstab output
+        assert_eq!(determine_pass_number_genfstab("/", "btrfs"), 0);
+        assert_eq!(determine_pass_number_genfstab("/home", "btrfs"), 0);
+
+        // No checker: network and swap
+        assert_eq!(determine_pass_number_genfstab("/nfs", "nfs4"), 0);
+        assert_eq!(determine_pass_number_genfstab("none", "swap"), 0);
+    }
+
+    #[test]
+    fn test_genfstab_pass_differs_from_default() {
+        assert_ne!(
+            determine_pass_number("/boot", "vfat"),
+            determine_pass_number_genfstab("/boot", "vfat")
+        );
+        assert_ne!(
+            determine_pass_number("/", "btrfs"),
+            determine_pass_number_genfstab("/", "btrfs")
+        );
+    }
+
+    #[test]
+    fn test_unescape_fstab_roundtrip() {
+        for original in [
+            "/mnt/my disk",
+            "/mnt/tab\there",
+            "/mnt/back\\slash",
+            "/mnt/#hash",
+        ] {
+            assert_eq!(unescape_fstab(&escape_fstab(original)), original);
+        }
+        // Not an octal escape: kept literally
+        assert_eq!(unescape_fstab("/mnt/\\9x"), "/mnt/\\9x");
+        assert_eq!(unescape_fstab("trailing\\04"), "trailing\\04");
+    }
+
+    #[test]
+    fn test_parse_fstab_line() {
+        let entry = parse_fstab_line("UUID=abc\t/my\\040disk\text4\tnoatime\t0\t2").unwrap();
+        assert_eq!(entry.fs_spec, "UUID=abc");
+        assert_eq!(entry.mountpoint, "/my disk");
+        assert_eq!(entry.fstype, "ext4");
+        assert_eq!(entry.options, "noatime");
+        assert_eq!((entry.dump, entry.pass), (0, 2));
+
+        // Optional trailing fields
+        let entry = parse_fstab_line("/dev/sdb1 /data").unwrap();
+        assert_eq!(entry.fstype, "auto");
+        assert_eq!(entry.options, "defaults");
+        assert_eq!((entry.dump, entry.pass), (0, 0));
+
+        assert!(parse_fstab_line("").is_none());
+        assert!(parse_fstab_line("   # comment").is_none());
+        assert!(parse_fstab_line("lonely").is_none());
+    }
+
+    #[test]
+    fn test_parse_fstab_comments_and_roundtrip() {
+        let generated = FstabEntry {
+            comments: vec!["/dev/vda2".to_string()],
+            fs_spec: "UUID=abc".to_string(),
+            mountpoint: "/".to_string(),
+            fstype: "ext4".to_string(),
+            options: "defaults".to_string(),
+            dump: 0,
+            pass: 1,
+            mountpoint_bytes: None,
+            id_fallback: false,
+            device: None,
+        };
+        let content = format!("# Static information\n\n{}", generated.render());
+        assert_eq!(parse_fstab(&content), vec![generated]);
+    }
+
+    #[test]
+    fn test_escape_fstab_bytes_invalid_utf8() {
+        assert_eq!(escape_fstab_bytes(b"/mnt/caf\xe9"), b"/mnt/caf\\351");
+        assert_eq!(escape_fstab_bytes(b"/a b\xff\x01"), b"/a\\040b\\377\\001");
+        assert_eq!(escape_fstab_bytes(b"/x\\#"), b"/x\\134\\043");
+        assert_eq!(escape_fstab_bytes(b"/plain"), b"/plain");
+    }
+
+    #[test]
+    fn test_make_fstab_target_bytes() {
+        assert_eq!(make_fstab_target_bytes(b"/mnt/d\xff", "/mnt"), b"/d\xff");
+        assert_eq!(make_fstab_target_bytes(b"/mnt", "/mnt/"), b"/");
+        assert_eq!(make_fstab_target_bytes(b"/d\xff", "/"), b"/d\xff");
+    }
+
+    #[test]
+    fn test_data_line_uses_mountpoint_bytes() {
+        let entry = FstabEntry {
+            comments: Vec::new(),
+            fs_spec: "UUID=abc".to_string(),
+            mountpoint: "/d\u{fffd}".to_string(),
+            fstype: "ext4".to_string(),
+            options: "defaults".to_string(),
+            dump: 0,
+            pass: 2,
+            mountpoint_bytes: Some(b"/d\xff".to_vec()),
+            id_fallback: false,
+            device: None,
+        };
+        assert_eq!(entry.data_line(), "UUID=abc\t/d\\377\text4\tdefaults\t0\t2");
+    }
 }
@@ -0,0 +1,2390 @@
+//! Entry generation: mount selection, entry building, and output.
+
+use crate::btrfs::{
+    btrfs_fstab_options, list_subvolumes, normalize_subvol_option, resolve_subvolid,
+    restore_subvol_option_with, subvolume_templates,
+};
+use crate::cifs::{is_cifs, normalize_cifs_source, rewrite_cifs_credentials};
+use crate::command::{CommandRunner, TracingRunner};
+use crate::config::Config;
+use crate::device::{
+    blkid_comment_with, block_device_path, chain_lookup, classify_source, comment_source,
+    extract_device_path, label_length_warning, resolve_device_identifier, subvol_from_source,
+    BlkidOptions, IdType, SourceKind,
+};
+use crate::error::{RecfstabError, Result};
+use crate::filter::{
+    append_option, filter_options, filter_options_with, force_readonly, fstype_driver_note,
+    is_cluster_filesystem, is_esp_with, is_fuse_filesystem, is_network_filesystem,
+    is_pseudo_filesystem, is_read_only_filesystem, is_sized_tmpfs, is_under_root, normalize_root,
+    unknown_fstype_warning, OptionFilter,
+};
+use crate::fstab::{
+    determine_pass_number_with, make_fstab_target, make_fstab_target_bytes, prefix_target,
+    prefix_target_bytes, FstabEntry, OutputEncoding,
+};
+use crate::log;
+use crate::mount::{MountInfo, MountProvider};
+use crate::nfs::{is_nfs, pin_nfs_version};
+use crate::overlay::resolve_overlay_lower;
+use crate::summary::SkipCounts;
+use crate::swap::{
+    is_swap_under_root, resolve_swapfile_target, swap_entry_with, SwapIdType, SwapInfo,
+};
+use crate::sysfs::{is_removable_in, loop_backing_file_in};
+use crate::zfs::{strip_zfsutil, zfs_is_legacy_with};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// What makes two mounts duplicates of each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DedupeBy {
+    /// Same target; the first mount at a target wins (default)
+    #[default]
+    Target,
+    /// Same source, fstype, target, and filtered options; different mounts
+    /// stacked on one target are all kept
+    Full,
+}
+
+/// How entries are grouped in the output (`--group-by`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// One `# Device: /dev/X (UUID=...)` header above all entries on a block
+    /// device, e.g. btrfs subvolumes or bind mounts
+    Device,
+}
+
+/// Decides which mounts under a root produce fstab entries.
+///
+/// The dedup state (emitted targets, keyed by filesystem) is the only state
+/// kept across mounts, which is what bounds memory use in streaming mode.
+#[derive(Debug)]
+pub struct MountFilter {
+    root: String,
+    seen_targets: HashSet<String>,
+    dedupe_by: DedupeBy,
+    /// (source, fstype, target, filtered options) of accepted mounts, for
+    /// [`DedupeBy::Full`]
+    seen_full: HashSet<(String, String, String, String)>,
+    /// fstab targets already emitted for each (device, fsroot) pair
+    seen_filesystems: HashMap<(String, String), Vec<String>>,
+    /// Reads a ZFS dataset's mountpoint property
+    zfs_query: fn(&str) -> Option<String>,
+    /// How blkid looks up PARTTYPE for the ESP check
+    blkid: BlkidOptions,
+    /// Skip EFI System Partitions (`--no-esp`)
+    skip_esp: bool,
+    /// Keep tmpfs mounts that set `size=` (`--keep-sized-tmpfs`)
+    keep_sized_tmpfs: bool,
+    /// Keep mounts whose target is a regular file (`--keep-file-binds`)
+    keep_file_binds: bool,
+    /// Mounts come from `--devices-from`; targets are never stat'ed
+    device_list: bool,
+    /// Rejected mounts under the root, by reason
+    skipped: SkipCounts,
+}
+
+impl MountFilter {
+    /// Create a filter for mounts under `root_str`.
+    pub fn new(root_str: &str) -> Self {
+        Self {
+            root: root_str.to_string(),
+            seen_targets: HashSet::new(),
+            dedupe_by: DedupeBy::Target,
+            seen_full: HashSet::new(),
+            seen_filesystems: HashMap::new(),
+            zfs_query: crate::zfs::query_mountpoint,
+            blkid: BlkidOptions::default(),
+            skip_esp: false,
+            keep_sized_tmpfs: false,
+            keep_file_binds: false,
+            device_list: false,
+            skipped: SkipCounts::new(),
+        }
+    }
+
+    /// Create a filter for mounts under `root_str` with the settings in `config`.
+    pub fn for_config(root_str: &str, config: &Config) -> Self {
+        Self {
+            skip_esp: config.no_esp,
+            dedupe_by: config.dedupe_by,
+            keep_sized_tmpfs: config.keep_sized_tmpfs,
+            keep_file_binds: config.keep_file_binds,
+            device_list: config.device_list,
+            blkid: config.blkid(),
+            ..Self::new(root_str)
+        }
+    }
+
+    /// Use `query` instead of `zfs get` to read ZFS mountpoint properties.
+    pub fn with_zfs_query(mut self, query: fn(&str) -> Option<String>) -> Self {
+        self.zfs_query = query;
+        self
+    }
+
+    /// Check whether a mount should be emitted, recording it if so.
+    ///
+    /// Skips mounts outside the root, pseudo-filesystems (except sized tmpfs
+    /// when keeping those), duplicates of an
+    /// accepted mount (by target, or by every field with [`DedupeBy::Full`];
+    /// first mount wins), ZFS datasets whose mountpoint is
+    /// managed by zfs rather than fstab, the ESP when skipping it,
+    /// propagated copies of an accepted mount (see [`is_propagated_copy`]),
+    /// and single-file bind mounts unless keeping those.
+    pub fn accept(&mut self, mount: &MountInfo) -> bool {
+        if !is_under_root(&mount.target, &self.root) {
+            return false;
+        }
+        if is_pseudo_filesystem(&mount.fstype)
+            && !(self.keep_sized_tmpfs && is_sized_tmpfs(&mount.fstype, &mount.options))
+        {
+            return self.skip(mount, "pseudo");
+        }
+        // Container runtimes bind files like /etc/resolv.conf into place
+        if !self.keep_file_binds && !self.device_list && is_file_bind(mount) {
+            return self.skip(mount, "file-bind");
+        }
+        let full_key = match self.dedupe_by {
+            DedupeBy::Target if self.seen_targets.contains(&mount.target) => {
+                return self.skip(mount, "duplicate")
+            }
+            DedupeBy::Target => None,
+            DedupeBy::Full => {
+                let key = (
+                    mount.source.clone(),
+                    mount.fstype.clone(),
+                    mount.target.clone(),
+                    filter_options(&mount.options),
+                );
+                if self.seen_full.contains(&key) {
+                    return self.skip(mount, "duplicate");
+                }
+                Some(key)
+            }
+        };
+        // Unknown (no zfs command) keeps the dataset, as before ZFS awareness
+        if mount.fstype == "zfs" && zfs_is_legacy_with(&mount.source, self.zfs_query) == Some(false)
+        {
+            log::note(
+                "zfs-dataset-skipped",
+                &format!(
+                    "skipping ZFS dataset '{}' (mounted by zfs, not fstab)",
+                    mount.source
+                ),
+                &[("source", &mount.source)],
+            );
+            return self.skip(mount, "zfs");
+        }
+
+        let key = (
+            extract_device_path(&mount.source).to_string(),
+            mount.fs_root().to_string(),
+        );
+        let fstab_target = make_fstab_target(&mount.target, &self.root);
+        if self.skip_esp
+            && is_esp_with(&fstab_target, &mount.fstype, &key.0, |device| {
+                block_device_path(device).and_then(|device| self.blkid.lookup(device, "PARTTYPE"))
+            })
+        {
+            return self.skip(mount, "esp");
+        }
+        if mount.is_propagated() && classify_source(&mount.source).is_block() {
+            if let Some(targets) = self.seen_filesystems.get(&key) {
+                if targets.iter().any(|t| is_propagated_copy(&fstab_target, t)) {
+                    return self.skip(mount, "propagated");
+                }
+            }
+        }
+
+        self.seen_targets.insert(mount.target.clone());
+        if let Some(key) = full_key {
+            self.seen_full.insert(key);
+        }
+        self.seen_filesystems
+            .entry(key)
+            .or_default()
+            .push(fstab_target);
+        true
+    }
+
+    /// Number of distinct targets accepted so far.
+    pub fn seen(&self) -> usize {
+        self.seen_targets.len()
+    }
+
+    /// Mounts under the root rejected so far, by reason.
+    pub fn skipped(&self) -> &SkipCounts {
+        &self.skipped
+    }
+
+    fn skip(&mut self, mount: &MountInfo, reason: &'static str) -> bool {
+        log::debug(
+            "mount-skipped",
+            &format!("skipping {} ({})", mount.target, reason),
+            &[("target", &mount.target), ("reason", reason)],
+        );
+        *self.skipped.entry(reason).or_insert(0) += 1;
+        false
+    }
+}
+
+/// Check whether `target` is a propagated copy of an emitted `original`.
+///
+/// With shared/slave propagation, a mount made at `/boot` also appears under
+/// every peer of its parent (e.g. `/var/lib/peer/boot`). Such copies share
+/// the original's device and fsroot and end with the original's path
+/// components. A second mount of the same filesystem at an unrelated path
+/// (`/srv` vs `/data`) is a deliberate mount and is not a copy, and neither
+/// is any re-mount of a filesystem emitted at `/`.
+pub fn is_propagated_copy(target: &str, original: &str) -> bool {
+    let target: Vec<_> = Path::new(target).components().skip(1).collect();
+    let original: Vec<_> = Path::new(original).components().skip(1).collect();
+    !original.is_empty() && target.len() > original.len() && target.ends_with(&original)
+}
+
+/// Whether `mount` binds a single regular file into place.
+///
+/// Only bind mounts ([`MountInfo::is_bind`]) are stat'ed, and never network
+/// or FUSE mounts, where a stat can hang on an unreachable server.
+fn is_file_bind(mount: &MountInfo) -> bool {
+    mount.is_bind()
+        && !is_network_filesystem(&mount.fstype)
+        && !is_fuse_filesystem(&mount.fstype)
+        && Path::new(&mount.target).is_file()
+}
+
+/// Pick the identifier type for a mount source.
+///
+/// Devices on removable media use `config.removable_id` when set, so an
+/// equivalent replacement stick still matches; everything else uses
+/// `config.id_type`.
+pub fn id_type_for<F>(source: &str, config: &Config, is_removable: F) -> IdType
+where
+    F: Fn(&str) -> bool,
+{
+    match config.removable_id {
+        Some(id_type) if is_removable(extract_device_path(source)) => id_type,
+        _ => config.id_type,
+    }
+}
+
+/// How runtime options are filtered for `mount`.
+///
+/// A read-only bind mount keeps `ro`: it is a deliberate view of a writable
+/// tree, not runtime state.
+fn option_filter_for(config: &Config, mount: &MountInfo) -> OptionFilter {
+    OptionFilter {
+        sort: config.sort_options,
+        keep_ro: config.keep_ro || is_read_only_filesystem(&mount.fstype) || mount.is_bind(),
+        preserve_mount_state: config.preserve_mount_state,
+        normalize_order: config.normalize_option_order,
+        no_defaults: config.no_defaults_fallback,
+        #[cfg(feature = "regex")]
+        strip: config.strip_options.clone(),
+    }
+}
+
+/// Build the fstab entry for a mount under `root_str`.
+pub fn entry_from_mount(mount: &MountInfo, root_str: &str, config: &Config) -> FstabEntry {
+    entry_from_mount_with(mount, root_str, config, config.blkid().runner().as_ref())
+}
+
+/// Like [`entry_from_mount`], but runs blkid through `runner`.
+pub fn entry_from_mount_with(
+    mount: &MountInfo,
+    root_str: &str,
+    config: &Config,
+    runner: &dyn CommandRunner,
+) -> FstabEntry {
+    // Convert absolute target path to path relative to root
+    let mountpoint = config
+        .mountpoint_case
+        .apply(&make_fstab_target(&mount.target, root_str));
+
+    // Get UUID/LABEL/PARTUUID/PARTLABEL for the device
+    let id_type = id_type_for(&mount.source, config, |device| {
+        is_removable_in(config.sys_root(), device)
+    });
+    let blkid = config.blkid();
+    let chain = if config.id_chain.is_empty() || id_type != config.id_type {
+        std::slice::from_ref(&id_type)
+    } else {
+        &config.id_chain[..]
+    };
+    let (fs_spec, id_fallback) = resolve_device_identifier(
+        &mount.source,
+        id_type.blkid_tag(),
+        chain_lookup(chain, |device, tag| {
+            blkid.lookup_through(runner, device, tag)
+        }),
+        config.fallback_format,
+    );
+    let mut fs_spec = config.id_format.apply(&fs_spec);
+    // Only the fs_spec is normalized; the comment below shows mount.source
+    if is_cifs(&mount.fstype) {
+        fs_spec = normalize_cifs_source(&fs_spec);
+    }
+
+    // Filter runtime-only mount options
+    let read_only_image = is_read_only_filesystem(&mount.fstype);
+    let option_filter = option_filter_for(config, mount);
+    let mut options = filter_options_with(&mount.options, &option_filter);
+    if read_only_image {
+        options = force_readonly(&options);
+    }
+    if mount.fstype == "zfs" {
+        options = strip_zfsutil(&options);
+    }
+    if mount.fstype == "btrfs" {
+        let fsroot = if mount.fsroot.is_empty() {
+            subvol_from_source(&mount.source)
+        } else {
+            Some(mount.fsroot.as_str())
+        };
+        match fsroot {
+            Some(fsroot) if config.prefer_fstab_options => {
+                options = btrfs_fstab_options(&options, fsroot);
+            }
+            _ => {
+                options = normalize_subvol_option(&options, &mount.source);
+                options = restore_subvol_option_with(
+                    &mount.options,
+                    &options,
+                    fsroot,
+                    &mount.target,
+                    resolve_subvolid,
+                );
+            }
+        }
+    }
+    let mut fstype = mount.fstype.clone();
+    if config.pin_nfs_version && is_nfs(&fstype) {
+        match pin_nfs_version(&fstype, &options) {
+            (pinned_fstype, Some(pinned)) => {
+                fstype = pinned_fstype;
+                options = pinned;
+            }
+            (_, None) => log::warning(
+                "nfs-no-version",
+                &format!(
+                    "NFS mount {} has no vers= option; the version will be negotiated \
+                     at mount time",
+                    mount.target
+                ),
+                &[("mountpoint", &mount.target)],
+            ),
+        }
+    }
+
+    // A sourceless mount's `# none` comment would only be noise
+    let mut comments = Vec::new();
+    if classify_source(&mount.source) != SourceKind::None {
+        let source = comment_source(&mount.source, &config.comment_source_priority);
+        comments.push(if config.blkid_comment {
+            blkid_comment_with(&source, |device| blkid.export_with(runner, device))
+        } else {
+            source
+        });
+    }
+    if !config.skip_fstype_warnings {
+        if let Some(warning) = unknown_fstype_warning(&mountpoint, &fstype) {
+            log::warning(
+                "unknown-fstype",
+                &warning,
+                &[("mountpoint", &mountpoint), ("fstype", &fstype)],
+            );
+        }
+    }
+    if config.annotate_fstype_driver {
+        if let Some(note) = fstype_driver_note(&fstype) {
+            comments.push(note.to_string());
+        }
+    }
+    if let Some(file) = loop_backing_file_in(config.sys_root(), extract_device_path(&mount.source))
+    {
+        comments.push(format!("loop device backed by {}", file));
+    }
+    if config.root_ro && mountpoint == "/" {
+        options = force_readonly(&options);
+        comments.push("root is read-only (--root-ro); mount writable paths separately".to_string());
+    }
+    if is_cifs(&fstype) {
+        let (rewritten, had_password) =
+            rewrite_cifs_credentials(&options, config.cifs_credentials.as_deref());
+        if had_password && config.cifs_credentials.is_none() {
+            log::warning(
+                "cifs-password-removed",
+                &format!(
+                    "removed the inline password from CIFS mount {}; use \
+                     --cifs-credentials to reference a credentials file",
+                    mount.target
+                ),
+                &[("mountpoint", &mount.target)],
+            );
+        }
+        options = rewritten;
+    }
+
+    let mut extra = Vec::new();
+    if config.mkdir && mountpoint != "/" {
+        extra.push("x-mount.mkdir");
+    }
+    if needs_initrd_mount(&mountpoint, config) {
+        extra.push("x-initrd.mount");
+    }
+    if config.nofail_removable
+        && is_removable_in(config.sys_root(), extract_device_path(&mount.source))
+    {
+        extra.push("nofail");
+    }
+    if is_cluster_filesystem(&fstype) {
+        extra.push("_netdev");
+    }
+    if is_network_filesystem(&fstype) {
+        if config.netdev {
+            extra.push("_netdev");
+        }
+        if config.automount_network {
+            extra.push("x-systemd.automount");
+        }
+    }
+    for option in &extra {
+        options = append_option(&options, option);
+    }
+    if (config.sort_options || config.normalize_option_order) && !extra.is_empty() {
+        // Only to reorder: options added by flags are not stripped
+        let reorder = OptionFilter {
+            #[cfg(feature = "regex")]
+            strip: None,
+            ..option_filter
+        };
+        options = filter_options_with(&options, &reorder);
+    }
+
+    if mountpoint == "/" {
+        if let Some(root_options) = &config.root_options {
+            options = root_options.clone();
+        }
+    }
+
+    // Determine fsck pass number from the final fstype and options (ro skips fsck)
+    let pass = determine_pass_number_with(&mountpoint, &fstype, &options, config.genfstab_compat);
+
+    let prefix = config.target_prefix.as_deref();
+    let entry = FstabEntry {
+        comments,
+        fs_spec,
+        mountpoint: prefix_target(prefix, &mountpoint),
+        fstype,
+        options,
+        dump: 0,
+        pass,
+        mountpoint_bytes: mount.target_bytes.as_deref().map(|target| {
+            let target = make_fstab_target_bytes(target, root_str);
+            prefix_target_bytes(prefix, &config.mountpoint_case.apply_bytes(&target))
+        }),
+        id_fallback,
+        device: block_device_path(&mount.source).map(String::from),
+    };
+    warn_label_length(&entry);
+    entry
+}
+
+/// Trace how the entry for `mount` is built (`--explain-entry`): source,
+/// each blkid command and its result, raw and filtered options, pass, and
+/// the final line. blkid runs through `runner`.
+pub fn explain_entry_with(
+    mount: &MountInfo,
+    root_str: &str,
+    config: &Config,
+    runner: &dyn CommandRunner,
+) -> String {
+    let tracing = TracingRunner::new(runner);
+    let entry = entry_from_mount_with(mount, root_str, config, &tracing);
+    let filtered = filter_options_with(&mount.options, &option_filter_for(config, mount));
+
+    let mut text = format!("mountpoint:  {}\n", entry.mountpoint);
+    text.push_str(&format!("target:      {}\n", mount.target));
+    text.push_str(&format!("source:      {}\n", mount.source));
+    text.push_str(&format!("fstype:      {}\n", mount.fstype));
+    let transcript = tracing.transcript();
+    if transcript.is_empty() {
+        text.push_str("blkid:       not run (source needs no lookup)\n");
+    }
+    for command in transcript {
+        text.push_str(&format!("blkid:       {}\n", command));
+    }
+    text.push_str(&format!("fs_spec:     {}", entry.fs_spec));
+    if entry.id_fallback {
+        text.push_str(" (no identifier found; device path used)");
+    }
+    text.push('\n');
+    text.push_str(&format!("raw options: {}\n", mount.options));
+    text.push_str(&format!("filtered:    {}\n", filtered));
+    if entry.options != filtered {
+        text.push_str(&format!("final:       {}\n", entry.options));
+    }
+    text.push_str(&format!("pass:        {}\n", entry.pass));
+    text.push_str(&format!("entry:       {}\n", entry.data_line()));
+    text
+}
+
+/// Print a stderr warning if the entry's LABEL is too long for its fstype.
+fn warn_label_length(entry: &FstabEntry) {
+    if entry.fs_spec.starts_with("LABEL=") {
+        if let Some(warning) = label_length_warning(&entry.fs_spec, &entry.fstype) {
+            log::warning(
+                "label-too-long",
+                &warning,
+                &[("fs_spec", &entry.fs_spec), ("fstype", &entry.fstype)],
+            );
+        }
+    }
+}
+
+/// Collect fstab entries for every mount under `root_str`, in mount order.
+pub fn collect_entries(
+    provider: &dyn MountProvider,
+    root_str: &str,
+    config: &Config,
+) -> Result<Vec<FstabEntry>> {
+    Ok(entries_from_mounts(&provider.mounts()?, root_str, config))
+}
+
+/// Build fstab entries from an already-read mount table.
+///
+/// With `config.overlay_lower`, an overlay root is replaced by an entry for
+/// the filesystem backing its lower layer (see [`overlay_root_entry`]).
+pub fn entries_from_mounts(
+    mounts: &[MountInfo],
+    root_str: &str,
+    config: &Config,
+) -> Vec<FstabEntry> {
+    entries_from_mounts_counted(mounts, root_str, config).0
+}
+
+/// Like [`entries_from_mounts`], also returning why other mounts under the
+/// root were skipped.
+pub fn entries_from_mounts_counted(
+    mounts: &[MountInfo],
+    root_str: &str,
+    config: &Config,
+) -> (Vec<FstabEntry>, SkipCounts) {
+    let mut filter = MountFilter::for_config(root_str, config);
+    let accepted: Vec<&MountInfo> = mounts.iter().filter(|mount| filter.accept(mount)).collect();
+    let mut entries: Vec<FstabEntry> = accepted
+        .iter()
+        .map(|mount| entry_from_mount(mount, root_str, config))
+        .collect();
+
+    if config.btrfs_subvol_templates {
+        add_subvolume_templates(&accepted, &mut entries, mounts, list_subvolumes);
+    }
+
+    if let Some(containing) = containing_mount(mounts, root_str) {
+        entries.insert(0, containing_root_entry(containing, root_str, config));
+    }
+
+    if config.overlay_lower && !entries.iter().any(|entry| is_root_entry(entry, config)) {
+        if let Some(entry) = overlay_root_entry(mounts, root_str, config) {
+            entries.insert(0, entry);
+        }
+    }
+    (entries, filter.skipped)
+}
+
+/// Attach commented templates for unmounted btrfs subvolumes.
+///
+/// Templates go on the first entry of each btrfs filesystem (`accepted` and
+/// `entries` are parallel). A subvolume counts as mounted if any mount in
+/// `mounts` exposes it, even outside the root.
+pub fn add_subvolume_templates<F>(
+    accepted: &[&MountInfo],
+    entries: &mut [FstabEntry],
+    mounts: &[MountInfo],
+    list: F,
+) where
+    F: Fn(&str) -> Option<Vec<String>>,
+{
+    let mut done: HashSet<&str> = HashSet::new();
+    for (mount, entry) in accepted.iter().zip(entries.iter_mut()) {
+        let device = extract_device_path(&mount.source);
+        if mount.fstype != "btrfs" || !done.insert(device) {
+            continue;
+        }
+        let Some(subvolumes) = list(&mount.target) else {
+            continue;
+        };
+        let mounted: Vec<&str> = mounts
+            .iter()
+            .filter(|m| m.fstype == "btrfs" && extract_device_path(&m.source) == device)
+            .map(|m| m.fs_root())
+            .collect();
+        entry
+            .comments
+            .extend(subvolume_templates(entry, &subvolumes, &mounted));
+    }
+}
+
+/// Check whether `mount` is a real filesystem strictly above `root_str`,
+/// other than the host's `/`.
+fn contains_root(mount: &MountInfo, root_str: &str) -> bool {
+    mount.target != "/"
+        && mount.target != normalize_root(root_str)
+        && is_under_root(root_str, &mount.target)
+        && !is_pseudo_filesystem(&mount.fstype)
+}
+
+/// Find the mount holding `root_str` when the root is not itself a mountpoint.
+///
+/// Running against a plain directory (e.g. `/mnt/subdir` on the filesystem
+/// mounted at `/mnt`) leaves nothing that maps to `/`. The mount with the
+/// longest target containing the root stands in for it. Returns `None` when
+/// any mount (even a skipped one) sits exactly on the root, or when only the
+/// host's `/` contains it: a directory that nothing is mounted under is not
+/// a target system.
+pub fn containing_mount<'a>(mounts: &'a [MountInfo], root_str: &str) -> Option<&'a MountInfo> {
+    let root = normalize_root(root_str);
+    if mounts.iter().any(|mount| mount.target == root) {
+        return None;
+    }
+    mounts
+        .iter()
+        .filter(|mount| contains_root(mount, root))
+        .max_by_key(|mount| mount.target.len())
+}
+
+/// Check whether the entry at `mountpoint` (relative to the root) gets
+/// `x-initrd.mount` from `--initrd-mount` or `--auto-initrd-usr`.
+fn needs_initrd_mount(mountpoint: &str, config: &Config) -> bool {
+    (config.auto_initrd_usr && mountpoint == "/usr")
+        || config.initrd_mount.iter().any(|wanted| {
+            let wanted = wanted.trim_end_matches('/');
+            mountpoint == if wanted.is_empty() { "/" } else { wanted }
+        })
+}
+
+/// Build the `/` entry for a root that is a directory inside `containing`.
+fn containing_root_entry(containing: &MountInfo, root_str: &str, config: &Config) -> FstabEntry {
+    let root = normalize_root(root_str);
+    log::warning(
+        "root-not-mountpoint",
+        &format!(
+            "{} is not a mountpoint; using the filesystem mounted at {} ({}) as /",
+            root, containing.target, containing.source
+        ),
+        &[("root", root), ("containing", &containing.target)],
+    );
+    let as_root = MountInfo {
+        target: root.to_string(),
+        target_bytes: None,
+        ..containing.clone()
+    };
+    entry_from_mount(&as_root, root_str, config)
+}
+
+/// Build the root entry for an overlay root from its backing lower filesystem.
+pub fn overlay_root_entry(
+    mounts: &[MountInfo],
+    root_str: &str,
+    config: &Config,
+) -> Option<FstabEntry> {
+    let root = normalize_root(root_str);
+    let overlay = mounts
+        .iter()
+        .find(|mount| mount.target == root && mount.fstype == "overlay")?;
+    let (lowerdir, backing) = resolve_overlay_lower(overlay, mounts)?;
+
+    let as_root = MountInfo {
+        target: root.to_string(),
+        ..backing.clone()
+    };
+    let mut entry = entry_from_mount(&as_root, root_str, config);
+    entry.comments.push(format!(
+        "root is an overlay; lower layer {} is on this filesystem",
+        lowerdir
+    ));
+    Some(entry)
+}
+
+/// Build swap entries for swaps that belong under `root_str`.
+///
+/// With `config.resolve_swapfile`, swapfiles on btrfs are mapped through the
+/// mount table (`mounts`) so their target matches where the subvolume is
+/// mounted under the root.
+pub fn swap_entries(
+    swaps: &[SwapInfo],
+    root_str: &str,
+    config: &Config,
+    mounts: &[MountInfo],
+) -> Vec<FstabEntry> {
+    let id_type = config
+        .swap_id_type
+        .unwrap_or_else(|| SwapIdType::from(config.id_type));
+    // Without --swap-id-type, swaps follow --id-chain like other entries
+    let chain = match config.swap_id_type {
+        None if !config.id_chain.is_empty() => Some(&config.id_chain[..]),
+        _ => None,
+    };
+    let blkid = config.blkid();
+    let lookup = |device: &str, tag: &str| match chain {
+        Some(chain) => chain_lookup(chain, |d, t| blkid.lookup(d, t))(device, tag),
+        None => blkid.lookup(device, tag),
+    };
+    let entries: Vec<FstabEntry> = swaps
+        .iter()
+        .filter_map(|swap_info| {
+            let resolved = if config.resolve_swapfile {
+                resolve_swapfile_target(swap_info, root_str, mounts)
+            } else {
+                None
+            };
+            if resolved.is_none() && !is_swap_under_root(swap_info, root_str) {
+                return None;
+            }
+            let prefix = config.target_prefix.as_deref();
+            let mut entry = swap_entry_with(swap_info, root_str, id_type, prefix, lookup);
+            entry.fs_spec = config.id_format.apply(&entry.fs_spec);
+            if let Some(target) = resolved {
+                entry.mountpoint = prefix_target(prefix, &target);
+            }
+            if let Some(style) = config.swap_mountpoint {
+                entry.mountpoint = style.apply(swap_info, &entry.mountpoint);
+            }
+            warn_label_length(&entry);
+            Some(entry)
+        })
+        .collect();
+    if let Some(max) = config.max_swaps {
+        if let Some(warning) = swap_count_warning(&entries, max) {
+            log::warning("too-many-swaps", &warning, &[]);
+        }
+    }
+    entries
+}
+
+/// Warning for more than `max` swap entries (`--max-swaps`), listing them.
+pub fn swap_count_warning(swaps: &[FstabEntry], max: usize) -> Option<String> {
+    (swaps.len() > max).then(|| {
+        let specs: Vec<&str> = swaps.iter().map(|entry| entry.fs_spec.as_str()).collect();
+        format!(
+            "{} swap entries (more than --max-swaps {}): {}",
+            swaps.len(),
+            max,
+            specs.join(", ")
+        )
+    })
+}
+
+/// Write a single rendered entry, escaping paths for `encoding`.
+pub fn write_entry<W: Write>(
+    out: &mut W,
+    entry: &FstabEntry,
+    encoding: OutputEncoding,
+) -> Result<()> {
+    out.write_all(entry.render_with(encoding).as_bytes())
+        .map_err(RecfstabError::write_failed)
+}
+
+/// Check whether `entry` is the root (`/`, or the `--target-prefix`) entry.
+pub fn is_root_entry(entry: &FstabEntry, config: &Config) -> bool {
+    entry.mountpoint == prefix_target(config.target_prefix.as_deref(), "/")
+}
+
+/// Keep the first entry for each distinct data line, sorted by mountpoint.
+///
+/// Comments are ignored when comparing, so two entries that render the same
+/// line are duplicates even if annotated differently. Sorting by mountpoint
+/// keeps every parent ahead of the mounts below it; the sort is stable, so
+/// entries sharing a mountpoint keep their order.
+pub fn sorted_unique_entries(entries: Vec<FstabEntry>) -> Vec<FstabEntry> {
+    let mut seen = HashSet::new();
+    let mut unique: Vec<FstabEntry> = entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.data_line()))
+        .collect();
+    unique.sort_by(|a, b| a.mountpoint.cmp(&b.mountpoint));
+    unique
+}
+
+/// Gather entries on the same block device under one `Device:` header
+/// (`--group-by device`).
+///
+/// Each group takes the place of its first entry and keeps the entries'
+/// relative order; entries without a block device (network, pseudo, swap
+/// files, merged lines) stay where they are. The header replaces the
+/// per-entry comments naming the device. `mount -a` follows file order, so
+/// a group can move a mount ahead of its parent; systemd orders by path.
+pub fn group_by_device(entries: Vec<FstabEntry>) -> Vec<FstabEntry> {
+    let mut groups: Vec<Vec<FstabEntry>> = Vec::new();
+    let mut by_device: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let Some(device) = entry.device.clone() else {
+            groups.push(vec![entry]);
+            continue;
+        };
+        match by_device.get(&device) {
+            Some(&index) => groups[index].push(entry),
+            None => {
+                by_device.insert(device, groups.len());
+                groups.push(vec![entry]);
+            }
+        }
+    }
+
+    for group in &mut groups {
+        let Some(device) = group[0].device.clone() else {
+            continue;
+        };
+        for entry in group.iter_mut() {
+            entry.comments.retain(|comment| {
+                let first = comment.split_whitespace().next().unwrap_or_default();
+                extract_device_path(first) != device
+            });
+        }
+        let header = if group[0].fs_spec == device {
+            format!("Device: {}", device)
+        } else {
+            format!("Device: {} ({})", device, group[0].fs_spec)
+        };
+        group[0].comments.insert(0, header);
+    }
+    groups.into_iter().flatten().collect()
+}
+
+/// Write rendered entries in order.
+pub fn write_entries<W: Write>(
+    out: &mut W,
+    entries: &[FstabEntry],
+    encoding: OutputEncoding,
+) -> Result<()> {
+    for entry in entries {
+        write_entry(out, entry, encoding)?;
+    }
+    Ok(())
+}
+
+/// Mountpoints of `entries` with no directory under `target_root`.
+///
+/// Such an entry fails at boot. Swap entries and those with
+/// `x-mount.mkdir` (which creates the directory) are not checked.
+pub fn missing_mountpoints(entries: &[FstabEntry], target_root: &Path) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|entry| entry.fstype != "swap" && entry.mountpoint.starts_with('/'))
+        .filter(|entry| !entry.options.split(',').any(|opt| opt == "x-mount.mkdir"))
+        .filter(|entry| {
+            let mountpoint: &OsStr = match &entry.mountpoint_bytes {
+                Some(bytes) => OsStr::from_bytes(bytes),
+                None => OsStr::new(&entry.mountpoint),
+            };
+            let relative = mountpoint.as_bytes().strip_prefix(b"/").unwrap_or_default();
+            !target_root.join(OsStr::from_bytes(relative)).is_dir()
+        })
+        .map(|entry| entry.mountpoint.clone())
+        .collect()
+}
+
+/// Warning for an entry that will not mount at boot because it has `noauto`.
+pub fn noauto_warning(entry: &FstabEntry) -> Option<String> {
+    entry
+        .options
+        .split(',')
+        .any(|opt| opt.trim() == "noauto")
+        .then(|| {
+            format!(
+                "{} has noauto and will not be mounted at boot",
+                entry.mountpoint
+            )
+        })
+}
+
+/// Print [`noauto_warning`]s for `entries` if `config` asks for them
+/// (`--warn-noauto`).
+pub fn warn_noauto_entries(entries: &[FstabEntry], config: &Config) {
+    if !config.warn_noauto {
+        return;
+    }
+    for entry in entries {
+        if let Some(warning) = noauto_warning(entry) {
+            log::warning("noauto", &warning, &[("mountpoint", &entry.mountpoint)]);
+        }
+    }
+}
+
+/// Warning for an entry whose data line, as escaped for `encoding`, is
+/// longer than `max` characters (`--max-line-length`).
+pub fn line_length_warning(
+    entry: &FstabEntry,
+    max: usize,
+    encoding: OutputEncoding,
+) -> Option<String> {
+    let length = entry.data_line_with(encoding).chars().count();
+    (length > max).then(|| {
+        format!(
+            "the line for {} is {} characters long (more than --max-line-length {})",
+            entry_name(entry),
+            length,
+            max
+        )
+    })
+}
+
+/// Mountpoint naming an entry in messages, or the device for a swap.
+fn entry_name(entry: &FstabEntry) -> &str {
+    if entry.mountpoint == "none" {
+        &entry.fs_spec
+    } else {
+        &entry.mountpoint
+    }
+}
+
+/// Check `entries` against `--max-line-length`: warn about each long line,
+/// or fail (E016) listing them with `--strict`.
+pub fn check_line_lengths(entries: &[FstabEntry], config: &Config) -> Result<()> {
+    let Some(max) = config.max_line_length else {
+        return Ok(());
+    };
+    let mut long = Vec::new();
+    for entry in entries {
+        if let Some(warning) = line_length_warning(entry, max, config.output_encoding) {
+            if config.strict {
+                long.push(entry_name(entry).to_string());
+            } else {
+                log::warning(
+                    "line-too-long",
+                    &warning,
+                    &[("mountpoint", entry_name(entry))],
+                );
+            }
+        }
+    }
+    if long.is_empty() {
+        Ok(())
+    } else {
+        Err(RecfstabError::line_too_long(&long, max))
+    }
+}
+
+/// Warning for a filesystem entry left with no options but `rw`
+/// (`--no-defaults-fallback`): every option of the mount was runtime-only
+/// or stripped.
+pub fn empty_options_warning(entry: &FstabEntry) -> Option<String> {
+    (entry.fstype != "swap" && entry.options == "rw").then(|| {
+        format!(
+            "the options for {} were all dropped; writing rw (--no-defaults-fallback)",
+            entry_name(entry)
+        )
+    })
+}
+
+/// Check `entries` for `--no-defaults-fallback`: warn about each entry with
+/// no options left, or fail (E018) listing them with `--strict`.
+pub fn check_empty_options(entries: &[FstabEntry], config: &Config) -> Result<()> {
+    if !config.no_defaults_fallback {
+        return Ok(());
+    }
+    let mut empty = Vec::new();
+    for entry in entries {
+        if let Some(warning) = empty_options_warning(entry) {
+            if config.strict {
+                empty.push(entry_name(entry).to_string());
+            } else {
+                log::warning(
+                    "empty-options",
+                    &warning,
+                    &[("mountpoint", entry_name(entry))],
+                );
+            }
+        }
+    }
+    if empty.is_empty() {
+        Ok(())
+    } else {
+        Err(RecfstabError::empty_options(&empty))
+    }
+}
+
+/// Identifiers starting with `prefix` (e.g. `LABEL=`) that entries
+/// resolved from more than one distinct block device, each with those
+/// devices, in first-seen order.
+///
+/// Entries on one device (btrfs subvolumes, bind mounts) share an
+/// identifier legitimately and are not reported; entries with no known
+/// device are ignored.
+pub fn find_duplicate_identifiers(
+    entries: &[FstabEntry],
+    prefix: &str,
+) -> Vec<(String, Vec<String>)> {
+    let mut found: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries.iter().filter(|e| e.fs_spec.starts_with(prefix)) {
+        let Some(device) = &entry.device else {
+            continue;
+        };
+        match found.iter_mut().find(|(id, _)| *id == entry.fs_spec) {
+            Some((_, devices)) if !devices.contains(device) => devices.push(device.clone()),
+            Some(_) => {}
+            None => found.push((entry.fs_spec.clone(), vec![device.clone()])),
+        }
+    }
+    found.retain(|(_, devices)| devices.len() > 1);
+    found
+}
+
+/// Warning for a LABEL shared by several devices: the entries using it may
+/// mount the wrong filesystem.
+pub fn label_collision_warning(label: &str, devices: &[String]) -> String {
+    format!(
+        "{} is shared by {}; entries using it may mount the wrong device",
+        label,
+        devices.join(", ")
+    )
+}
+
+/// Check `entries` for `--label-collision-check`: warn about each LABEL
+/// resolved from more than one device, or fail (E019) listing them with
+/// `--strict`.
+pub fn check_label_collisions(entries: &[FstabEntry], config: &Config) -> Result<()> {
+    if !config.label_collision_check {
+        return Ok(());
+    }
+    let collisions = find_duplicate_identifiers(entries, "LABEL=");
+    if config.strict && !collisions.is_empty() {
+        let labels: Vec<String> = collisions.into_iter().map(|(label, _)| label).collect();
+        return Err(RecfstabError::duplicate_labels(&labels));
+    }
+    for (label, devices) in &collisions {
+        log::warning(
+            "label-collision",
+            &label_collision_warning(label, devices),
+            &[("label", label)],
+        );
+    }
+    Ok(())
+}
+
+/// Run [`FstabEntry::self_check`] on `entries` if `config` asks for it.
+///
+/// Always enabled in debug builds.
+pub fn self_check_entries(entries: &[FstabEntry], config: &Config) -> Result<()> {
+    if !(config.self_check || cfg!(debug_assertions)) {
+        return Ok(());
+    }
+    for entry in entries {
+        entry
+            .self_check()
+            .map_err(|problem| RecfstabError::self_check_failed(&entry.mountpoint, &problem))?;
+    }
+    Ok(())
+}
+
+/// Writes each accepted mount as soon as it is seen (streaming mode).
+///
+/// No entry list is kept; only the [`MountFilter`] dedup set grows.
+pub struct StreamWriter<'a, W: Write> {
+    filter: MountFilter,
+    root: String,
+    config: &'a Config,
+    out: &'a mut W,
+    written: usize,
+    fallbacks: Vec<String>,
+    /// A `/` entry has been written
+    wrote_root: bool,
+    /// A mount sits exactly on the root
+    root_mounted: bool,
+    /// Longest mount seen so far that contains a non-mountpoint root
+    containing: Option<MountInfo>,
+}
+
+impl<'a, W: Write> StreamWriter<'a, W> {
+    /// Create a streaming writer for mounts under `root_str`.
+    pub fn new(root_str: &str, config: &'a Config, out: &'a mut W) -> Self {
+        Self {
+            filter: MountFilter::for_config(root_str, config),
+            root: root_str.to_string(),
+            config,
+            out,
+            written: 0,
+            fallbacks: Vec::new(),
+            wrote_root: false,
+            root_mounted: false,
+            containing: None,
+        }
+    }
+
+    /// Process one mount, writing its entry immediately if accepted.
+    ///
+    /// findmnt lists parents before children, so by the first mount under
+    /// the root it is known whether the root is a mountpoint; if not, the
+    /// containing mount (see [`containing_mount`]) is written first as `/`.
+    pub fn push(&mut self, mount: MountInfo) -> Result<()> {
+        if mount.target == normalize_root(&self.root) {
+            self.root_mounted = true;
+        } else if contains_root(&mount, &self.root)
+            && self
+                .containing
+                .as_ref()
+                .is_none_or(|c| mount.target.len() > c.target.len())
+        {
+            self.containing = Some(mount.clone());
+        }
+        if self.filter.accept(&mount) {
+            self.write_containing_root()?;
+            let entry = entry_from_mount(&mount, &self.root, self.config);
+            self.write(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Write the containing mount as `/` if the root was never a mountpoint.
+    ///
+    /// Call after the last mount so a root with nothing mounted below it
+    /// still gets its entry.
+    pub fn finish(&mut self) -> Result<()> {
+        self.write_containing_root()
+    }
+
+    fn write_containing_root(&mut self) -> Result<()> {
+        if self.root_mounted {
+            return Ok(());
+        }
+        if let Some(containing) = self.containing.take() {
+            self.root_mounted = true;
+            let entry = containing_root_entry(&containing, &self.root, self.config);
+            self.write(entry)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, mut entry: FstabEntry) -> Result<()> {
+        if entry.id_fallback {
+            self.fallbacks.push(entry.fs_spec.clone());
+        }
+        if self.config.no_comments {
+            entry.comments.clear();
+        }
+        if let Some(width) = self.config.comment_width {
+            entry.wrap_comments(width);
+        }
+        self_check_entries(std::slice::from_ref(&entry), self.config)?;
+        check_line_lengths(std::slice::from_ref(&entry), self.config)?;
+        check_empty_options(std::slice::from_ref(&entry), self.config)?;
+        warn_noauto_entries(std::slice::from_ref(&entry), self.config);
+        write_entry(self.out, &entry, self.config.output_encoding)?;
+        self.written += 1;
+        self.wrote_root |= is_root_entry(&entry, self.config);
+        Ok(())
+    }
+
+    /// Number of entries written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Mounts under the root skipped so far, by reason.
+    pub fn skipped(&self) -> &SkipCounts {
+        self.filter.skipped()
+    }
+
+    /// Whether a `/` entry has been written.
+    pub fn wrote_root(&self) -> bool {
+        self.wrote_root
+    }
+
+    /// Device paths written because their identifier was not found.
+    pub fn fallbacks(&self) -> &[String] {
+        &self.fallbacks
+    }
+
+    /// Number of targets held in the dedup set.
+    pub fn tracked_targets(&self) -> usize {
+        self.filter.seen()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mount::StaticProvider;
+
+    fn mount(target: &str, source: &str, fstype: &str, options: &str) -> MountInfo {
+        MountInfo {
+            target: target.to_string(),
+            source: source.to_string(),
+            fstype: fstype.to_string(),
+            options: options.to_string(),
+            ..MountInfo::default()
+        }
+    }
+
+    /// Synthetic mount table resembling a busy container host.
+    fn large_fixture(count: usize) -> Vec<MountInfo> {
+        let mut mounts = vec![mount("/mnt", "UUID=root", "ext4", "rw,relatime")];
+        for i in 0..count {
+            mounts.push(mount(
+                &format!("/mnt/srv/vol{}", i),
+                &format!("UUID=vol-{}", i),
+                "xfs",
+                "rw,noatime",
+            ));
+            // Pseudo and out-of-root mounts that must be skipped
+            mounts.push(mount(&format!("/run/netns/ns{}", i), "nsfs", "nsfs", "rw"));
+            mounts.push(mount(
+                &format!("/mnt/srv/vol{}/tmp", i),
+                "tmpfs",
+                "tmpfs",
+                "rw",
+            ));
+        }
+        // Duplicate target: first mount wins
+        mounts.push(mount("/mnt/srv/vol0", "UUID=shadow", "ext4", "rw"));
+        mounts
+    }
+
+    #[test]
+    fn test_mount_filter_skips_pseudo_and_duplicates() {
+        let mut filter = MountFilter::new("/mnt");
+        assert!(filter.accept(&mount("/mnt", "/dev/sda1", "ext4", "rw")));
+        assert!(!filter.accept(&mount("/mnt/proc", "proc", "proc", "rw")));
+        assert!(!filter.accept(&mount("/other", "/dev/sdb1", "ext4", "rw")));
+        assert!(!filter.accept(&mount("/mnt", "/dev/sdc1", "ext4", "rw")));
+        assert_eq!(filter.seen(), 1);
+    }
+
+    #[test]
+    fn test_mount_filter_dedupe_full() {
+        let mut filter = MountFilter::for_config(
+            "/mnt",
+            &Config {
+                dedupe_by: DedupeBy::Full,
+                ..Config::default()
+            },
+        );
+        assert!(filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "rw,nodev")));
+        // Exact duplicate line
+        assert!(!filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "rw,nodev")));
+        // Differs only in runtime options, which are filtered: still a duplicate
+        assert!(!filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "ro,nodev")));
+        // Near duplicate with different kept options is distinct
+        assert!(filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "rw,noexec")));
+        assert_eq!(filter.seen(), 1);
+
+        // Default target dedup drops the near duplicate
+        let mut filter = MountFilter::new("/mnt");
+        assert!(filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "rw,nodev")));
+        assert!(!filter.accept(&mount("/mnt/data", "/dev/sdb1", "ext4", "rw,noexec")));
+    }
+
+    #[test]
+    fn test_mount_filter_skips_file_binds() {
+        let temp_dir = std::env::temp_dir().join("recfstab_test_mount_filter_file_binds");
+        let _ = std::fs::create_dir_all(temp_dir.join("etc/conf.d"));
+        std::fs::write(temp_dir.join("etc/hostname"), "box\n").unwrap();
+        std::fs::write(temp_dir.join("etc/motd"), "hi\n").unwrap();
+        let root = temp_dir.to_str().unwrap();
+        let bind = |target: &str, fstype: &str| MountInfo {
+            fsroot: "/containers/abc/hostname".to_string(),
+            ..mount(&format!("{}/{}", root, target), "/dev/sda1", fstype, "rw")
+        };
+        let file_bind = bind("etc/hostname", "ext4");
+        let dir_bind = bind("etc/conf.d", "ext4");
+
+        let mut filter = MountFilter::new(root);
+        assert!(!filter.accept(&file_bind));
+        assert!(filter.accept(&dir_bind));
+        assert_eq!(filter.skipped().get("file-bind"), Some(&1));
+
+        // Only bind mounts of local filesystems are stat'ed
+        let whole = mount(&format!("{}/etc/motd", root), "/dev/sda2", "ext4", "rw");
+        assert!(MountFilter::new(root).accept(&whole));
+        assert!(MountFilter::new(root).accept(&bind("etc/motd", "nfs4")));
+        assert!(MountFilter::new(root).accept(&bind("etc/motd", "fuse.sshfs")));
+
+        let config = Config {
+            keep_file_binds: true,
+            ..Config::default()
+        };
+        assert!(MountFilter::for_config(root, &config).accept(&file_bind));
+        let config = Config {
+            device_list: true,
+            ..Config::default()
+        };
+        assert!(MountFilter::for_config(root, &config).accept(&file_bind));
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_mount_filter_keep_sized_tmpfs() {
+        let config = Config {
+            keep_sized_tmpfs: true,
+            ..Config::default()
+        };
+        let mounts = vec![
+            mount("/mnt", "UUID=root", "ext4", "rw"),
+            mount("/mnt/tmp", "tmpfs", "tmpfs", "rw,nosuid,nodev,size=2G"),
+            mount("/mnt/run", "tmpfs", "tmpfs", "rw,nosuid,nodev,mode=755"),
+        ];
+        let entries = entries_from_mounts(&mounts, "/mnt", &config);
+        let targets: Vec<_> = entries.iter().map(|e| e.mountpoint.as_str()).collect();
+        assert_eq!(targets, ["/", "/tmp"]);
+        assert_eq!(entries[1].fs_spec, "tmpfs");
+        assert_eq!(entries[1].options, "nosuid,nodev,size=2G");
+        assert_eq!(entries[1].pass, 0);
+
+        // Off by default
+        assert_eq!(
+            entries_from_mounts(&mounts, "/mnt", &Config::default()).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_subvolume_templates_for_unmounted_only() {
+        let mounts = vec![
+            mount("/mnt", "/dev/sda2[/@]", "btrfs", "rw,subvol=/@"),
+            mount(
+                "/mnt/home",
+                "/dev/sda2[/@home]",
+                "btrfs",
+                "rw,subvol=/@home",
+            ),
+            mount("/mnt/boot", "/dev/sda1", "vfat", "rw"),
+        ];
+        let stub = |target: &str| {
+            assert_eq!(target, "/mnt", "listed once per filesystem");
+            Some(vec![
+                "/@".to_string(),
+                "/@home".to_string(),
+                "/@snapshots".to_string(),
+            ])
+        };
+
+        let accepted: Vec<&MountInfo> = mounts.iter().collect();
+        let mut entries: Vec<FstabEntry> = mounts
+            .iter()
+            .map(|m| entry_from_mount(m, "/mnt", &Config::default()))
+            .collect();
+        add_subvolume_templates(&accepted, &mut entries, &mounts, stub);
+
+        assert_eq!(entries[0].comments.len(), 2);
+        assert!(entries[0].comments[1].contains("\t/@snapshots\tbtrfs\tsubvol=/@snapshots"));
+        // Mounted subvolumes stay active entries without templates
+        assert_eq!(entries[1].comments.len(), 1);
+        assert_eq!(entries[2].comments.len(), 1);
+    }
+
+    fn propagated(target: &str, source: &str, fsroot: &str, propagation: &str) -> MountInfo {
+        MountInfo {
+            fsroot: fsroot.to_string(),
+            propagation: propagation.to_string(),
+            ..mount(target, source, "ext4", "rw")
+        }
+    }
+
+    #[test]
+    fn test_mount_filter_skips_propagated_copies() {
+        let mut filter = MountFilter::new("/mnt");
+        assert!(filter.accept(&propagated("/mnt", "/dev/sda2", "/", "shared")));
+        assert!(filter.accept(&propagated("/mnt/boot", "/dev/sda1", "/", "shared")));
+        // Copies propagated into a peer of /mnt under /mnt/var/lib/peer
+        assert!(!filter.accept(&propagated(
+            "/mnt/var/lib/peer/boot",
+            "/dev/sda1",
+            "/",
+            "slave"
+        )));
+        // A re-mount of the root device is not a copy of /
+        assert!(filter.accept(&propagated("/mnt/var/lib/peer", "/dev/sda2", "/", "shared")));
+        // Same filesystem deliberately mounted elsewhere is kept
+        assert!(filter.accept(&propagated("/mnt/srv", "/dev/sda1", "/", "shared")));
+        // Private mounts never propagate, so are never copies
+        assert!(filter.accept(&propagated("/mnt/data/boot", "/dev/sda1", "/", "private")));
+        // A different fsroot of the same device is a distinct mount
+        assert!(filter.accept(&propagated("/mnt/x/boot", "/dev/sda1", "/sub", "shared")));
+    }
+
+    #[test]
+    fn test_mount_filter_keeps_propagated_tmpfs_pair() {
+        let config = Config {
+            keep_sized_tmpfs: true,
+            ..Config::default()
+        };
+        let tmpfs = |target: &str| MountInfo {
+            fsroot: "/".to_string(),
+            propagation: "shared".to_string(),
+            ..mount(target, "tmpfs", "tmpfs", "rw,size=2G")
+        };
+        let mut filter = MountFilter::for_config("/mnt", &config);
+        assert!(filter.accept(&tmpfs("/mnt/tmp")));
+        // Only block devices can be copies; tmpfs sources all look alike
+        assert!(filter.accept(&tmpfs("/mnt/var/tmp")));
+    }
+
+    fn stub_zfs(dataset: &str) -> Option<String> {
+        match dataset {
+            "rpool/ROOT/default" => Some("legacy".to_string()),
+            _ => Some("/home".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_mount_filter_zfs_legacy_only() {
+        let mut filter = MountFilter::new("/mnt").with_zfs_query(stub_zfs);
+        assert!(filter.accept(&mount("/mnt", "rpool/ROOT/default", "zfs", "rw,zfsutil")));
+        assert!(!filter.accept(&mount("/mnt/home", "rpool/home", "zfs", "rw,zfsutil")));
+
+        // Without a zfs command the dataset is kept
+        let mut filter = MountFilter::new("/mnt").with_zfs_query(|_| None);
+        assert!(filter.accept(&mount("/mnt/home", "rpool/home", "zfs", "rw")));
+    }
+
+    #[test]
+    fn test_id_type_for_removable() {
+        let config = Config {
+            removable_id: Some(IdType::Label),
+            ..Config::default()
+        };
+        let stub = |device: &str| device == "/dev/sdb1";
+        assert_eq!(id_type_for("/dev/sdb1", &config, stub), IdType::Label);
+        assert_eq!(id_type_for("/dev/sda1", &config, stub), IdType::Uuid);
+
+        // Without the policy, removable media use the global type
+        assert_eq!(
+            id_type_for("/dev/sdb1", &Config::default(), stub),
+            IdType::Uuid
+        );
+    }
+
+    #[test]
+    fn test_entries_from_mounts_overlay_lower() {
+        let mounts = vec![
+            mount("/sysroot", "UUID=base", "ext4", "ro,relatime"),
+            mount(
+                "/mnt",
+                "overlay",
+                "overlay",
+                "rw,lowerdir=/sysroot/image,upperdir=/mnt/.upper",
+            ),
+            mount("/mnt/boot", "UUID=boot", "vfat", "rw"),
+        ];
+        let config = Config {
+            overlay_lower: true,
+            ..Config::default()
+        };
+
+        let entries = entries_from_mounts(&mounts, "/mnt", &config);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].fs_spec, "UUID=base");
+        assert_eq!(entries[0].mountpoint, "/");
+        assert_eq!(entries[0].pass, 1);
+        assert!(entries[0].comments[1].contains("/sysroot/image"));
+
+        // Without the flag the overlay root is skipped as before
+        let entries = entries_from_mounts(&mounts, "/mnt", &Config::default());
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].mountpoint, "/boot");
+    }
+
+    #[test]
+    fn test_entries_from_mounts_overlay_lower_with_prefix() {
+        // A root entry already exists, so the overlay does not add another
+        let mounts = vec![
+            mount("/sysroot", "UUID=base", "ext4", "ro,relatime"),
+            mount("/mnt", "UUID=root", "ext4", "rw"),
+            mount("/mnt", "overlay", "overlay", "rw,lowerdir=/sysroot/image"),
+        ];
+        let config = Config {
+            overlay_lower: true,
+            target_prefix: Some("/target".to_string()),
+            ..Config::default()
+        };
+
+        let entries = entries_from_mounts(&mounts, "/mnt", &config);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].fs_spec, "UUID=root");
+        assert_eq!(entries[0].mountpoint, "/target");
+    }
+
+    #[test]
+    fn test_no_esp_skips_esp_keeps_data_vfat() {
+        let mounts = vec![
+            mount("/mnt", "UUID=root", "ext4", "rw"),
+            mount("/mnt/boot/efi", "UUID=ESP1-2345", "vfat", "rw,fmask=0077"),
+            mount("/mnt/data", "UUID=DATA-0001", "vfat", "rw"),
+        ];
+        let config = Config {
+            no_esp: true,
+            ..Config::default()
+        };
+        let entries = entries_from_mounts(&mounts, "/mnt", &config);
+        let targets: Vec<_> = entries.iter().map(|e| e.mountpoint.as_str()).collect();
+        assert_eq!(targets, ["/", "/data"]);
+
+        let entries = entries_from_mounts(&mounts, "/mnt", &Config::default());
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[test]
+    fn test_entry_from_mount_root_ro() {
+        let config = Config {
+            root_ro: true,
+            ..Config::default()
+        };
+        let root = entry_from_mount(
+            &mount("/mnt", "UUID=root", "btrfs", "rw,compress=zstd"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(root.options, "ro,compress=zstd");
+        assert_eq!(root.comments.len(), 2);
+        assert!(root.comments[1].contains("read-only"));
+
+        let home = entry_from_mount(
+            &mount("/mnt/home", "UUID=home", "ext4", "rw,nodev"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(home.options, "nodev");
+        assert_eq!(home.comments, ["UUID=home"]);
+    }
+
+    #[test]
+    fn test_entry_from_mount_read_only_pass() {
+        let config = Config {
+            keep_ro: true,
+            ..Config::default()
+        };
+        let ro = mount("/mnt/data", "UUID=data", "ext4", "ro,relatime");
+        let entry = entry_from_mount(&ro, "/mnt", &config);
+        assert_eq!(entry.options, "ro");
+        assert_eq!(entry.pass, 0);
+
+        let rw = mount("/mnt/data", "UUID=data", "ext4", "rw,relatime");
+        assert_eq!(entry_from_mount(&rw, "/mnt", &config).pass, 2);
+
+        // Without --keep-ro the ro flag is filtered, so the pass is unchanged
+        assert_eq!(entry_from_mount(&ro, "/mnt", &Config::default()).pass, 2);
+
+        let root_ro = Config {
+            root_ro: true,
+            ..Config::default()
+        };
+        let root = mount("/mnt", "UUID=root", "ext4", "rw");
+        assert_eq!(entry_from_mount(&root, "/mnt", &root_ro).pass, 1);
+    }
+
+    #[test]
+    fn test_entry_from_mount_mkdir() {
+        let config = Config {
+            mkdir: true,
+            root_ro: true,
+            sort_options: true,
+            ..Config::default()
+        };
+        let data = entry_from_mount(
+            &mount("/mnt/data", "UUID=data", "ext4", "rw,x-mount.mkdir,nodev"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(data.options, "nodev,x-mount.mkdir");
+
+        let root = entry_from_mount(&mount("/mnt", "UUID=root", "ext4", "rw"), "/mnt", &config);
+        assert_eq!(root.options, "ro");
+
+        let swaps = vec![SwapInfo {
+            filename: "/dev/sda3".to_string(),
+            swap_type: "partition".to_string(),
+        }];
+        let swap = swap_entries(&swaps, "/mnt", &config, &[]);
+        assert_eq!(swap.len(), 1);
+        assert!(!swap[0].options.contains("x-mount.mkdir"));
+    }
+
+    #[test]
+    fn test_entry_from_mount_pin_nfs_version() {
+        let nfs = mount(
+            "/mnt/srv",
+            "server:/export",
+            "nfs",
+            "rw,relatime,vers=4.2,hard,proto=tcp",
+        );
+        let entry = entry_from_mount(&nfs, "/mnt", &Config::default());
+        assert_eq!(entry.fstype, "nfs");
+        assert_eq!(entry.options, "vers=4.2,hard,proto=tcp");
+
+        let config = Config {
+            pin_nfs_version: true,
+            ..Config::default()
+        };
+        let entry = entry_from_mount(&nfs, "/mnt", &config);
+        assert_eq!(entry.fstype, "nfs4");
+        assert_eq!(entry.options, "vers=4.2,hard,proto=tcp");
+        assert_eq!(entry.pass, 0);
+    }
+
+    #[test]
+    fn test_entry_from_mount_network_options() {
+        let nfs = mount("/mnt/srv", "server:/export", "nfs4", "rw,hard");
+        let config = Config {
+            netdev: true,
+            automount_network: true,
+            ..Config::default()
+        };
+        let entry = entry_from_mount(&nfs, "/mnt", &config);
+        assert_eq!(entry.options, "hard,_netdev,x-systemd.automount");
+
+        let local = mount("/mnt/data", "UUID=data", "ext4", "rw");
+        assert_eq!(
+            entry_from_mount(&local, "/mnt", &config).options,
+            "defaults"
+        );
+    }
+
+    #[test]
+    fn test_entry_from_mount_nofail_removable() {
+        let sys = std::env::temp_dir().join("recfstab_test_nofail_removable");
+        let _ = std::fs::remove_dir_all(&sys);
+        for (disk, removable) in [("sda", "0"), ("sdb", "1")] {
+            let dir = sys.join("class/block").join(disk);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("removable"), removable).unwrap();
+        }
+        let config = Config {
+            nofail_removable: true,
+            sys_root: Some(sys.clone()),
+            ..Config::default()
+        };
+
+        let usb = entry_from_mount(
+            &mount("/mnt/usb", "/dev/sdb", "vfat", "rw"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(usb.options, "defaults,nofail");
+        let fixed = entry_from_mount(
+            &mount("/mnt/data", "/dev/sda", "ext4", "rw"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(fixed.options, "defaults");
+        let _ = std::fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_entry_from_mount_squashfs_image() {
+        let sys = std::env::temp_dir().join("recfstab_test_squashfs_loop");
+        let _ = std::fs::remove_dir_all(&sys);
+        let dir = sys.join("class/block/loop0/loop");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("backing_file"), "/var/lib/images/app.sqfs\n").unwrap();
+        let config = Config {
+            sys_root: Some(sys.clone()),
+            ..Config::default()
+        };
+
+        let image = mount(
+            "/mnt/opt/app",
+            "/dev/loop0",
+            "squashfs",
+            "ro,relatime,errors=continue",
+        );
+        assert!(MountFilter::new("/mnt").accept(&image));
+        let entry = entry_from_mount(&image, "/mnt", &config);
+        assert_eq!(entry.options, "ro,errors=continue");
+        assert_eq!(entry.pass, 0);
+        assert_eq!(
+            entry.comments,
+            [
+                "/dev/loop0",
+                "loop device backed by /var/lib/images/app.sqfs"
+            ]
+        );
+
+        // erofs keeps ro even if the kernel did not report it
+        let erofs = mount("/mnt/usr", "UUID=e1", "erofs", "relatime");
+        let entry = entry_from_mount(&erofs, "/mnt", &config);
+        assert_eq!(entry.options, "ro");
+        assert_eq!(entry.pass, 0);
+        let _ = std::fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_root_options_override() {
+        let config = Config {
+            root_options: Some("subvol=/@,errors=remount-ro".to_string()),
+            ..Config::default()
+        };
+        let mounts = vec![
+            mount("/mnt", "UUID=root", "btrfs", "rw,relatime,subvol=/@root"),
+            mount("/mnt/home", "UUID=home", "ext4", "rw,nodev,relatime"),
+        ];
+        let entries = entries_from_mounts(&mounts, "/mnt", &config);
+        assert_eq!(entries[0].mountpoint, "/");
+        assert_eq!(entries[0].options, "subvol=/@,errors=remount-ro");
+        assert_eq!(entries[1].options, "nodev");
+    }
+
+    #[test]
+    fn test_missing_mountpoints() {
+        let target = std::env::temp_dir().join("recfstab_test_missing_mountpoints");
+        let _ = std::fs::create_dir_all(target.join("boot"));
+        let entries = entries_from_mounts(
+            &[
+                mount("/mnt", "UUID=root", "ext4", "rw"),
+                mount("/mnt/boot", "UUID=boot", "vfat", "rw"),
+                mount("/mnt/home", "UUID=home", "ext4", "rw"),
+                mount("/mnt/srv", "UUID=srv", "ext4", "rw,x-mount.mkdir"),
+            ],
+            "/mnt",
+            &Config::default(),
+        );
+        assert_eq!(missing_mountpoints(&entries, &target), ["/home"]);
+
+        let _ = std::fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn test_noauto_warning() {
+        let manual = mount("/mnt/backup", "UUID=backup", "ext4", "rw,noauto");
+        let entry = entry_from_mount(&manual, "/mnt", &Config::default());
+        assert_eq!(entry.options, "noauto");
+        assert_eq!(
+            noauto_warning(&entry).as_deref(),
+            Some("/backup has noauto and will not be mounted at boot")
+        );
+
+        let data = mount("/mnt/data", "UUID=data", "ext4", "rw,noatime");
+        assert_eq!(
+            noauto_warning(&entry_from_mount(&data, "/mnt", &Config::default())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_prefer_fstab_options_rebuilds_btrfs_subvol() {
+        let config = Config {
+            prefer_fstab_options: true,
+            ..Config::default()
+        };
+        let mut home = mount(
+            "/mnt/home",
+            "/dev/sda2",
+            "btrfs",
+            "rw,noatime,compress=zstd:3,subvolid=257",
+        );
+        home.fsroot = "/@home".to_string();
+        let entry = entry_from_mount(&home, "/mnt", &config);
+        assert_eq!(entry.options, "compress=zstd:3,subvol=/@home");
+    }
+
+    #[test]
+    fn test_swap_count_warning() {
+        let swap = |filename: &str, swap_type: &str| SwapInfo {
+            filename: filename.to_string(),
+            swap_type: swap_type.to_string(),
+        };
+        let swaps = [
+            swap("/mnt/swapfile", "file"),
+            swap("/mnt/swap/extra", "file"),
+            swap("/mnt/var/swap", "file"),
+        ];
+        let entries = swap_entries(&swaps, "/mnt", &Config::default(), &[]);
+        assert_eq!(
+            swap_count_warning(&entries, 2).as_deref(),
+            Some(
+                "3 swap entries (more than --max-swaps 2): \
+                 /mnt/swapfile, /mnt/swap/extra, /mnt/var/swap"
+            )
+        );
+        assert_eq!(swap_count_warning(&entries, 3), None);
+    }
+
+    #[test]
+    fn test_group_by_device_single_header_per_device() {
+        let runner = crate::command::MockRunner::new(0, "0f1e2d3c\n");
+        let entries: Vec<FstabEntry> = [
+            mount("/mnt", "/dev/sda2[/@]", "btrfs", "rw,subvol=/@"),
+            mount("/mnt/boot", "/dev/sda1", "vfat", "rw"),
+            mount(
+                "/mnt/home",
+                "/dev/sda2[/@home]",
+                "btrfs",
+                "rw,subvol=/@home",
+            ),
+            mount("/mnt/tmp", "tmpfs", "tmpfs", "rw"),
+        ]
+        .iter()
+        .map(|m| entry_from_mount_with(m, "/mnt", &Config::default(), &runner))
+        .collect();
+
+        let grouped = group_by_device(entries);
+        let order: Vec<_> = grouped.iter().map(|e| e.mountpoint.as_str()).collect();
+        assert_eq!(order, ["/", "/home", "/boot", "/tmp"]);
+        assert_eq!(grouped[0].comments, ["Device: /dev/sda2 (UUID=0f1e2d3c)"]);
+        assert!(grouped[1].comments.is_empty());
+        assert_eq!(grouped[2].comments, ["Device: /dev/sda1 (UUID=0f1e2d3c)"]);
+        assert_eq!(grouped[3].comments, ["tmpfs"]);
+
+        let rendered: String = grouped.iter().map(|e| e.render()).collect();
+        assert_eq!(rendered.matches("# Device: /dev/sda2").count(), 1);
+    }
+
+    #[test]
+    fn test_line_length_warning() {
+        let options = format!("nodev,comment={}", "x".repeat(60));
+        let long = mount("/mnt/data", "UUID=abc", "ext4", &options);
+        let entry = entry_from_mount(&long, "/mnt", &Config::default());
+        assert_eq!(
+            line_length_warning(&entry, 40, OutputEncoding::Utf8).as_deref(),
+            Some("the line for /data is 98 characters long (more than --max-line-length 40)")
+        );
+        assert_eq!(line_length_warning(&entry, 98, OutputEncoding::Utf8), None);
+
+        let config = Config {
+            max_line_length: Some(40),
+            ..Config::default()
+        };
+        assert!(check_line_lengths(std::slice::from_ref(&entry), &config).is_ok());
+        let strict = Config {
+            strict: true,
+            ..config
+        };
+        let err = check_line_lengths(&[entry], &strict).unwrap_err();
+        assert_eq!(err.code, crate::error::ErrorCode::LineTooLong);
+        assert!(err.message.contains("/data"), "{}", err);
+    }
+
+    #[test]
+    fn test_empty_options_warning() {
+        let config = Config {
+            no_defaults_fallback: true,
+            ..Config::default()
+        };
+        let bare = mount("/mnt/data", "UUID=abc", "ext4", "rw,relatime,seclabel");
+        let entry = entry_from_mount(&bare, "/mnt", &config);
+        assert_eq!(entry.options, "rw");
+        assert_eq!(
+            empty_options_warning(&entry).as_deref(),
+            Some("the options for /data were all dropped; writing rw (--no-defaults-fallback)")
+        );
+        let tuned = mount("/mnt/srv", "UUID=abc", "ext4", "rw,relatime,nodev");
+        assert_eq!(
+            empty_options_warning(&entry_from_mount(&tuned, "/mnt", &config)),
+            None
+        );
+
+        assert!(check_empty_options(std::slice::from_ref(&entry), &config).is_ok());
+        let strict = Config {
+            strict: true,
+            ..config
+        };
+        let err = check_empty_options(&[entry], &strict).unwrap_err();
+        assert_eq!(err.code, crate::error::ErrorCode::EmptyOptions);
+        assert!(err.message.contains("/data"), "{}", err);
+    }
+
+    #[test]
+    fn test_label_collision_warning() {
+        let config = Config {
+            id_type: IdType::Label,
+            label_collision_check: true,
+            ..Config::default()
+        };
+        let runner = crate::command::MockRunner::new(0, "boot\n");
+        let entries: Vec<FstabEntry> = [
+            mount("/mnt", "/dev/sda2[/@]", "btrfs", "rw,subvol=/@"),
+            mount(
+                "/mnt/home",
+                "/dev/sda2[/@home]",
+                "btrfs",
+                "rw,subvol=/@home",
+            ),
+            mount("/mnt/boot", "/dev/sda1", "vfat", "rw"),
+            mount("/mnt/media/usb", "/dev/sdb1", "vfat", "rw"),
+        ]
+        .iter()
+        .map(|m| entry_from_mount_with(m, "/mnt", &config, &runner))
+        .collect();
+        assert!(entries.iter().all(|e| e.fs_spec == "LABEL=boot"));
+
+        let collisions = find_duplicate_identifiers(&entries, "LABEL=");
+        assert_eq!(
+            collisions,
+            [(
+                "LABEL=boot".to_string(),
+                vec![
+                    "/dev/sda2".to_string(),
+                    "/dev/sda1".to_string(),
+                    "/dev/sdb1".to_string()
+                ]
+            )]
+        );
+        assert_eq!(
+            label_collision_warning(&collisions[0].0, &collisions[0].1),
+            "LABEL=boot is shared by /dev/sda2, /dev/sda1, /dev/sdb1; entries using it \
+             may mount the wrong device"
+        );
+        // Subvolumes of one device share their LABEL legitimately
+        assert!(find_duplicate_identifiers(&entries[..2], "LABEL=").is_empty());
+
+        assert!(check_label_collisions(&entries, &config).is_ok());
+        let strict = Config {
+            strict: true,
+            ..config
+        };
+        let err = check_label_collisions(&entries, &strict).unwrap_err();
+        assert_eq!(err.code, crate::error::ErrorCode::DuplicateLabel);
+        assert!(err.message.contains("LABEL=boot"), "{}", err);
+    }
+
+    #[test]
+    fn test_entry_from_mount_cluster_and_log_structured() {
+        let config = Config::default();
+        let ocfs2 = entry_from_mount(
+            &mount("/mnt/shared", "UUID=abc", "ocfs2", "rw,relatime"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(ocfs2.options, "defaults,_netdev");
+        assert_eq!(ocfs2.pass, 0);
+        let gfs2 = entry_from_mount(
+            &mount("/mnt", "UUID=abc", "gfs2", "rw,_netdev,noatime"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!((gfs2.options.as_str(), gfs2.pass), ("_netdev", 0));
+
+        let nilfs2 = entry_from_mount(
+            &mount("/mnt/log", "UUID=abc", "nilfs2", "rw,relatime"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!((nilfs2.options.as_str(), nilfs2.pass), ("defaults", 0));
+        assert_eq!(
+            crate::filter::classify_fstype("ocfs2"),
+            crate::filter::FstypeClass::Known
+        );
+    }
+
+    #[test]
+    fn test_entry_from_mount_genfstab_compat_passes() {
+        let config = Config {
+            genfstab_compat: true,
+            keep_ro: true,
+            ..Default::default()
+        };
+        // genfstab has no fsck.gfs2, so it gets 0 from genfstab's own rules
+        let gfs2 = entry_from_mount(
+            &mount("/mnt", "UUID=abc", "gfs2", "rw,_netdev"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(gfs2.pass, 0);
+        // The ro rule is recfstab's, not genfstab's
+        let data = entry_from_mount(
+            &mount("/mnt/data", "UUID=abc", "ext4", "ro,relatime"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!((data.options.as_str(), data.pass), ("ro", 2));
+    }
+
+    #[test]
+    fn test_entry_from_mount_initrd_mount() {
+        let usr = mount("/mnt/usr", "UUID=usr", "ext4", "rw,relatime");
+        let var = mount("/mnt/var", "UUID=var", "xfs", "rw,nodev");
+        assert_eq!(
+            entry_from_mount(&usr, "/mnt", &Config::default()).options,
+            "defaults"
+        );
+
+        let config = Config {
+            auto_initrd_usr: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            entry_from_mount(&usr, "/mnt", &config).options,
+            "defaults,x-initrd.mount"
+        );
+        assert_eq!(entry_from_mount(&var, "/mnt", &config).options, "nodev");
+
+        let config = Config {
+            initrd_mount: vec!["/var/".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            entry_from_mount(&var, "/mnt", &config).options,
+            "nodev,x-initrd.mount"
+        );
+        assert_eq!(entry_from_mount(&usr, "/mnt", &config).options, "defaults");
+    }
+
+    #[test]
+    fn test_entry_from_mount_read_only_bind() {
+        // As findmnt reports it: a device source with a FSROOT subpath
+        let bind = MountInfo {
+            fsroot: "/srv/www".to_string(),
+            ..mount("/mnt/var/www", "/dev/sda2", "ext4", "ro,relatime")
+        };
+        let entry = entry_from_mount(&bind, "/mnt", &Config::default());
+        assert_eq!(entry.options, "ro");
+        assert_eq!(entry.pass, 0);
+
+        // A btrfs subvolume mount is not a bind
+        let subvol = MountInfo {
+            fsroot: "/@data".to_string(),
+            ..mount(
+                "/mnt/data",
+                "/dev/sda3",
+                "btrfs",
+                "ro,relatime,subvol=/@data",
+            )
+        };
+        let entry = entry_from_mount(&subvol, "/mnt", &Config::default());
+        assert_eq!(entry.options, "subvol=/@data");
+
+        let ext4 = mount("/mnt/data", "UUID=data", "ext4", "ro,relatime");
+        assert_eq!(
+            entry_from_mount(&ext4, "/mnt", &Config::default()).options,
+            "defaults"
+        );
+    }
+
+    #[test]
+    fn test_entry_from_mount_annotate_fstype_driver() {
+        let config = Config {
+            annotate_fstype_driver: true,
+            ..Config::default()
+        };
+        let ntfs = mount("/mnt/win", "UUID=0123", "ntfs3", "rw,uid=1000");
+        assert_eq!(
+            entry_from_mount(&ntfs, "/mnt", &config).comments,
+            ["UUID=0123", "uses the ntfs3 kernel driver (not ntfs-3g)"]
+        );
+        let ext4 = mount("/mnt/data", "UUID=4567", "ext4", "rw");
+        assert_eq!(
+            entry_from_mount(&ext4, "/mnt", &config).comments,
+            ["UUID=4567"]
+        );
+        // Off by default
+        assert_eq!(
+            entry_from_mount(&ntfs, "/mnt", &Config::default()).comments,
+            ["UUID=0123"]
+        );
+    }
+
+    #[test]
+    fn test_entry_from_mount_cifs_credentials() {
+        let share = mount(
+            "/mnt/share",
+            "//nas/share",
+            "cifs",
+            "rw,vers=3.1.1,username=alice,password=hunter2,domain=CORP",
+        );
+        let entry = entry_from_mount(&share, "/mnt", &Config::default());
+        assert!(!entry.data_line().contains("hunter2"));
+        assert_eq!(entry.options, "vers=3.1.1,username=alice,domain=CORP");
+
+        let config = Config {
+            cifs_credentials: Some("/etc/samba/nas.cred".into()),
+            ..Config::default()
+        };
+        let entry = entry_from_mount(&share, "/mnt", &config);
+        assert_eq!(entry.options, "vers=3.1.1,credentials=/etc/samba/nas.cred");
+    }
+
+    #[test]
+    fn test_entry_from_mount_cifs_unc_source() {
+        let share = mount("/mnt/share", "\\\\nas\\share", "cifs", "rw,vers=3.0");
+        let entry = entry_from_mount(&share, "/mnt", &Config::default());
+        assert_eq!(entry.fs_spec, "//nas/share");
+        // The comment keeps the source as it was mounted
+        assert_eq!(entry.comments, ["\\\\nas\\share"]);
+        assert_eq!(
+            entry.render(),
+            "# \\\\nas\\share\n//nas/share\t/share\tcifs\tvers=3.0\t0\t0\n\n"
+        );
+    }
+
+    #[test]
+    fn test_self_check_entries_flags_unescaped_space() {
+        let mut entry = entry_from_mount(
+            &mount("/mnt/data", "UUID=data", "ext4", "rw"),
+            "/mnt",
+            &Config::default(),
+        );
+        assert!(self_check_entries(std::slice::from_ref(&entry), &Config::default()).is_ok());
+
+        entry.options = "noatime nodev".to_string();
+        let config = Config {
+            self_check: true,
+            ..Config::default()
+        };
+        let err = self_check_entries(&[entry], &config).unwrap_err();
+        assert_eq!(err.code, crate::error::ErrorCode::SelfCheckFailed);
+        assert!(err.message.contains("/data"));
+    }
+
+    #[test]
+    fn test_entry_from_mount_strips_zfsutil() {
+        let entry = entry_from_mount(
+            &mount("/mnt", "rpool/ROOT/default", "zfs", "rw,xattr,zfsutil"),
+            "/mnt",
+            &Config::default(),
+        );
+        assert_eq!(entry.options, "xattr");
+        assert_eq!(entry.fs_spec, "rpool/ROOT/default");
+    }
+
+    #[test]
+    fn test_is_propagated_copy() {
+        assert!(is_propagated_copy("/peer/boot", "/boot"));
+        assert!(is_propagated_copy("/var/lib/peer/boot/efi", "/boot/efi"));
+        assert!(!is_propagated_copy("/peer", "/"));
+        assert!(!is_propagated_copy("/boot", "/boot"));
+        assert!(!is_propagated_copy("/srv", "/boot"));
+        // Suffix must start at a path component boundary
+        assert!(!is_propagated_copy("/reboot", "/boot"));
+    }
+
+    #[test]
+    fn test_entry_from_mount() {
+        let config = Config::default();
+        let entry = entry_from_mount(
+            &mount("/mnt/home", "UUID=abc", "ext4", "rw,relatime"),
+            "/mnt",
+            &config,
+        );
+        assert_eq!(entry.comments, vec!["UUID=abc"]);
+        assert_eq!(entry.data_line(), "UUID=abc\t/home\text4\tdefaults\t0\t2");
+    }
+
+    #[test]
+    fn test_entry_from_mount_blank_source_no_comment() {
+        let entry = entry_from_mount(
+            &mount("/mnt/scratch", "none", "ext4", "rw"),
+            "/mnt",
+            &Config::default(),
+        );
+        assert!(entry.comments.is_empty());
+        assert_eq!(entry.render(), "none\t/scratch\text4\tdefaults\t0\t2\n\n");
+    }
+
+    #[test]
+    fn test_collect_entries_static_provider() {
+        let provider = StaticProvider(vec![
+            mount("/mnt", "UUID=root", "ext4", "rw,relatime"),
+            mount("/mnt/proc", "proc", "proc", "rw"),
+            mount("/mnt/boot", "UUID=ABCD-1234", "vfat", "rw,fmask=0077"),
+            mount("/home", "UUID=host-home", "ext4", "rw"),
+        ]);
+        let entries = collect_entries(&provider, "/mnt", &Config::default()).unwrap();
+        let lines: Vec<String> = entries.iter().map(FstabEntry::data_line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "UUID=root\t/\text4\tdefaults\t0\t1",
+                "UUID=ABCD-1234\t/boot\tvfat\tfmask=0077\t0\t0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_root_inside_mount_uses_containing_mount() {
+        let mounts = vec![
+            mount("/", "UUID=host", "ext4", "rw"),
+            mount("/mnt", "UUID=target", "ext4", "rw,relatime"),
+            mount("/mnt/proc", "proc", "proc", "rw"),
+            mount("/mnt/subdir/boot", "UUID=ABCD-1234", "vfat", "rw"),
+        ];
+        assert_eq!(
+            containing_mount(&mounts, "/mnt/subdir/").map(|m| m.target.as_str()),
+            Some("/mnt")
+        );
+        assert!(containing_mount(&mounts, "/mnt").is_none());
+
+        let config = Config::default();
+        let entries = entries_from_mounts(&mounts, "/mnt/subdir", &config);
+        let lines: Vec<String> = entries.iter().map(FstabEntry::data_line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "UUID=target\t/\text4\tdefaults\t0\t1",
+                "UUID=ABCD-1234\t/boot\tvfat\tdefaults\t0\t0",
+            ]
+        );
+
+        let mut buffered = Vec::new();
+        write_entries(&mut buffered, &entries, config.output_encoding).unwrap();
+        let mut streamed = Vec::new();
+        let mut writer = StreamWriter::new("/mnt/subdir", &config, &mut streamed);
+        for mount in mounts.clone() {
+            writer.push(mount).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(writer.written(), 2);
+        assert_eq!(streamed, buffered);
+    }
+
+    #[test]
+    fn test_root_inside_mount_with_nothing_below() {
+        let mounts = vec![
+            mount("/", "UUID=host", "ext4", "rw"),
+            mount("/mnt", "UUID=target", "btrfs", "rw,subvol=/@"),
+        ];
+        let config = Config::default();
+        let entries = entries_from_mounts(&mounts, "/mnt/subdir", &config);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].mountpoint, "/");
+        assert_eq!(entries[0].fs_spec, "UUID=target");
+
+        let mut streamed = Vec::new();
+        let mut writer = StreamWriter::new("/mnt/subdir", &config, &mut streamed);
+        for mount in mounts {
+            writer.push(mount).unwrap();
+        }
+        assert_eq!(writer.written(), 0);
+        writer.finish().unwrap();
+        assert_eq!(writer.written(), 1);
+    }
+
+    #[test]
+    fn test_root_inside_host_root_is_not_a_target() {
+        let mounts = vec![
+            mount("/", "UUID=host", "ext4", "rw"),
+            mount("/proc", "proc", "proc", "rw"),
+        ];
+        assert!(containing_mount(&mounts, "/srv/empty").is_none());
+        let config = Config::default();
+        assert!(entries_from_mounts(&mounts, "/srv/empty", &config).is_empty());
+
+        let mut streamed = Vec::new();
+        let mut writer = StreamWriter::new("/srv/empty", &config, &mut streamed);
+        for mount in mounts {
+            writer.push(mount).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(writer.written(), 0);
+        assert!(streamed.is_empty());
+    }
+
+    #[test]
+    fn test_swap_entries_resolve_swapfile() {
+        let mounts = vec![
+            mount("/swap", "/dev/sda2[/@swap]", "btrfs", "rw,subvol=/@swap"),
+            mount(
+                "/mnt/swap",
+                "/dev/sda2[/@swap]",
+                "btrfs",
+                "rw,subvol=/@swap",
+            ),
+        ];
+        let swaps = vec![SwapInfo {
+            filename: "/swap/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        }];
+
+        // Without the flag the swapfile is outside the root and skipped
+        assert!(swap_entries(&swaps, "/mnt", &Config::default(), &mounts).is_empty());
+
+        let config = Config {
+            resolve_swapfile: true,
+            ..Config::default()
+        };
+        let entries = swap_entries(&swaps, "/mnt", &config, &mounts);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].mountpoint, "/swap/swapfile");
+    }
+
+    #[test]
+    fn test_swap_entries_swap_id_type_overrides_global() {
+        let swaps = vec![SwapInfo {
+            filename: "/dev/sdz3".to_string(),
+            swap_type: "partition".to_string(),
+        }];
+        let config = Config {
+            id_type: IdType::Label,
+            swap_id_type: Some(SwapIdType::Device),
+            ..Config::default()
+        };
+        let entries = swap_entries(&swaps, "/mnt", &config, &[]);
+        assert_eq!(entries[0].fs_spec, "/dev/sdz3");
+        assert_eq!(
+            SwapIdType::from(Config::default().id_type),
+            SwapIdType::Uuid
+        );
+    }
+
+    #[test]
+    fn test_target_prefix_rebases_mounts_and_swapfile() {
+        let config = Config {
+            target_prefix: Some("/srv/guest".to_string()),
+            ..Config::default()
+        };
+        let root = entry_from_mount(&mount("/mnt", "UUID=root", "ext4", "rw"), "/mnt", &config);
+        assert_eq!(root.mountpoint, "/srv/guest");
+        assert_eq!(root.pass, 1);
+
+        let swaps = vec![SwapInfo {
+            filename: "/mnt/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        }];
+        let entries = swap_entries(&swaps, "/mnt", &config, &[]);
+        assert_eq!(entries[0].mountpoint, "/srv/guest/swapfile");
+    }
+
+    #[test]
+    fn test_normalize_mountpoint_case() {
+        let config = Config {
+            mountpoint_case: crate::fstab::MountpointCase::Lower,
+            target_prefix: Some("/Target".to_string()),
+            ..Config::default()
+        };
+        let win = mount("/mnt/Windows/C", "UUID=ABCD-1234", "ntfs3", "rw,uid=1000");
+        let entry = entry_from_mount(&win, "/mnt", &config);
+        assert_eq!(entry.mountpoint, "/Target/windows/c");
+        assert_eq!(entry.fs_spec, "UUID=ABCD-1234");
+        assert_eq!(entry.comments, ["UUID=ABCD-1234"]);
+
+        let entry = entry_from_mount(&win, "/mnt", &Config::default());
+        assert_eq!(entry.mountpoint, "/Windows/C");
+    }
+
+    #[test]
+    fn test_entry_from_mount_genfstab_compat() {
+        let config = Config {
+            genfstab_compat: true,
+            ..Config::default()
+        };
+        let esp = mount("/mnt/boot", "UUID=ABCD-1234", "vfat", "rw,fmask=0022");
+        assert_eq!(entry_from_mount(&esp, "/mnt", &config).pass, 2);
+        assert_eq!(entry_from_mount(&esp, "/mnt", &Config::default()).pass, 0);
+    }
+
+    #[test]
+    fn test_streaming_matches_buffered_on_large_fixture() {
+        let config = Config::default();
+        let provider = StaticProvider(large_fixture(5000));
+
+        let entries = collect_entries(&provider, "/mnt", &config).unwrap();
+        let mut buffered = Vec::new();
+        write_entries(&mut buffered, &entries, config.output_encoding).unwrap();
+
+        let mut streamed = Vec::new();
+        let mut writer = StreamWriter::new("/mnt", &config, &mut streamed);
+        provider.for_each_mount(&mut |m| writer.push(m)).unwrap();
+        assert_eq!(writer.written(), 5001);
+        // Only the dedup set is retained: one target per emitted entry
+        assert_eq!(writer.tracked_targets(), 5001);
+
+        assert_eq!(streamed, buffered);
+        let text = String::from_utf8(streamed).unwrap();
+        assert!(!text.contains("UUID=shadow"));
+        assert!(!text.contains("tmpfs"));
+    }
+}
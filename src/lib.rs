@@ -23,26 +23,61 @@
 //! recfstab -L /mnt >> /mnt/etc/fstab
 //! ```
 //!
+//! ## Reproducibility
+//!
+//! Output depends only on the mount table and device metadata, never on
+//! hash or directory iteration order: entries follow findmnt's order (or
+//! mountpoint order with `--output-sorted-unique`) and every lookup that can
+//! match several names picks the same one each run. Running twice with the
+//! same flags on an unchanged system gives byte-identical output, except
+//! that a blkid probe cut short by `--blkid-timeout` falls back to the device
+//! path and blkid's cache may refresh a stale identifier between runs.
+//!
 //! ## Requirements
 //!
 //! - Linux system with `findmnt` and `blkid` utilities
 //! - Root privileges (for blkid to read device UUIDs)
 
+pub mod btrfs;
+pub mod cifs;
+pub mod command;
+pub mod config;
+pub mod crypttab;
 pub mod device;
+pub mod device_list;
+pub mod diff;
 pub mod error;
 pub mod filter;
 pub mod fstab;
+pub mod generate;
+pub mod log;
+pub mod merge;
 pub mod mount;
+pub mod nfs;
+pub mod options_map;
+pub mod output;
+pub mod overlay;
+pub mod pass_map;
+pub mod summary;
 pub mod swap;
+pub mod sysfs;
+pub mod verify;
+pub mod zfs;
 
-use std::collections::HashSet;
+use std::io::{BufRead, Write};
 use std::path::Path;
 
-pub use device::{get_device_identifier, IdType};
+pub use config::Config;
+pub use device::{classify_source, get_device_identifier, IdType, SourceKind};
 pub use error::{ErrorCode, RecfstabError, Result};
-pub use filter::{filter_options, is_pseudo_filesystem, is_under_root};
-pub use fstab::{determine_pass_number, escape_fstab, make_fstab_target};
-pub use mount::{get_mounts, MountInfo};
+pub use filter::{
+    filter_options, filter_options_with, is_pseudo_filesystem, is_under_root, OptionFilter,
+};
+pub use fstab::{determine_pass_number, escape_fstab, make_fstab_target, FstabEntry};
+pub use generate::collect_entries;
+pub use merge::{merge_entries, MergePolicy};
+pub use mount::{get_mounts, FindmntProvider, MountInfo, MountProvider, StaticProvider};
+pub use output::{write_atomic, ExistingBlockAction, OutputFile, OutputTargets, WriteMode};
 pub use swap::{read_swaps, SwapInfo};
 
 /// Main entry point for the fstab generator.
@@ -53,103 +88,561 @@ pub use swap::{read_swaps, SwapInfo};
 /// * `root_path` - The root directory to scan for mounts
 /// * `id_type` - The identifier type to use (UUID, LABEL, PARTUUID, PARTLABEL)
 pub fn run(root_path: &str, id_type: IdType) -> Result<()> {
-    // Validate input - empty or whitespace-only paths are invalid
-    let root_path = root_path.trim();
-    if root_path.is_empty() {
-        return Err(RecfstabError::root_not_found("(empty path)"));
+    run_with_config(root_path, &Config::new(id_type))
+}
+
+/// Generate fstab entries for `root_path` using `config`, printing to stdout.
+pub fn run_with_config(root_path: &str, config: &Config) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    run_to_writer(root_path, config, &mut out)
+}
+
+/// Generate fstab entries for `root_path` using `config`, writing to `path`.
+///
+/// The entries are wrapped in [`output::MARKER_BEGIN`]/[`output::MARKER_END`]
+/// lines. On error, an atomic write leaves `path` untouched; append/truncate
+/// modes may leave a partial write, as with a shell redirect.
+pub fn run_to_file(root_path: &str, config: &Config, path: &Path, mode: WriteMode) -> Result<()> {
+    write_fstab_file(
+        root_path,
+        config,
+        &system_provider(root_path, config),
+        path,
+        mode,
+        ExistingBlockAction::Append,
+    )
+}
+
+/// Provider for the running system, reading options as `config` asks and
+/// scoped to `root_path` with `config.findmnt_scope`.
+fn system_provider(root_path: &str, config: &Config) -> FindmntProvider {
+    FindmntProvider {
+        options: config.findmnt_options,
+        scope: config.findmnt_scope.then(|| root_path.trim().to_string()),
     }
+}
 
-    let root = Path::new(root_path);
+/// Generate fstab (and crypttab, if requested) into the files in `targets`.
+pub fn run_to_targets(root_path: &str, config: &Config, targets: &OutputTargets) -> Result<()> {
+    run_to_targets_with(
+        root_path,
+        config,
+        &system_provider(root_path, config),
+        targets,
+    )
+}
 
-    // Validate root directory
-    if !root.exists() {
-        return Err(RecfstabError::root_not_found(root_path));
+/// Like [`run_to_targets`], but reads mounts and swaps from `provider`.
+///
+/// fstab is written first; crypttab covers the LUKS devices behind the same
+/// mounts and swaps. With `targets.interactive`, the replace/append question
+/// is asked on stderr and answered on stdin.
+pub fn run_to_targets_with(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    targets: &OutputTargets,
+) -> Result<()> {
+    let stdin = std::io::stdin();
+    run_to_targets_prompting(root_path, config, provider, targets, &mut stdin.lock())
+}
+
+fn run_to_targets_prompting(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    targets: &OutputTargets,
+    input: &mut dyn BufRead,
+) -> Result<()> {
+    match (&targets.dropin_dir, &targets.fstab) {
+        (Some(dir), _) => write_dropin_dir(root_path, config, provider, dir)?,
+        (None, Some(path)) => {
+            let mut action = ExistingBlockAction::Append;
+            if targets.interactive {
+                let existing = std::fs::read_to_string(path).unwrap_or_default();
+                if output::has_marked_block(&existing) {
+                    action =
+                        output::prompt_existing_block(path, input, &mut std::io::stderr().lock())
+                            .map_err(|e| RecfstabError::read_failed(Path::new("stdin"), e))?;
+                }
+            }
+            write_fstab_file(root_path, config, provider, path, targets.mode, action)?
+        }
+        (None, None) => {
+            let stdout = std::io::stdout();
+            run_to_writer_with(root_path, config, provider, &mut stdout.lock())?;
+        }
     }
-    if !root.is_dir() {
-        return Err(RecfstabError::not_a_directory(root_path));
+
+    if let Some(path) = &targets.crypttab {
+        let root_str = resolve_root(root_path.trim(), config)?;
+        let mounts = provider.mounts()?;
+        let swaps = provider.swaps().unwrap_or_default();
+        let entries =
+            crypttab::crypttab_entries_in(config.sys_root(), &mounts, &swaps, &root_str, config);
+
+        let mut file = OutputFile::open(path, targets.mode)?;
+        for entry in &entries {
+            file.write_all(entry.render().as_bytes())
+                .map_err(|e| RecfstabError::output_failed(path, e))?;
+        }
+        file.finish(path)?;
     }
 
-    // Canonicalize the root path to resolve symlinks
-    // This ensures we match mount targets correctly even if root is a symlink
-    let canonical_root = std::fs::canonicalize(root).map_err(RecfstabError::current_dir_failed)?;
-    let root_str = canonical_root.to_string_lossy().to_string();
+    Ok(())
+}
 
-    // Remove trailing slash for consistent comparison, but keep "/" as-is
-    let root_str = if root_str == "/" {
-        root_str
-    } else {
-        root_str.trim_end_matches('/').to_string()
-    };
+/// Write a marker-wrapped fstab block to `path`.
+///
+/// The block is generated in full before the file is opened. With
+/// [`ExistingBlockAction::Replace`] it atomically swaps the file's current
+/// block; otherwise it is written per `mode`.
+fn write_fstab_file(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    path: &Path,
+    mode: WriteMode,
+    action: ExistingBlockAction,
+) -> Result<()> {
+    // Generate before touching the file, so a failed run leaves it as it was
+    let mut rendered = Vec::new();
+    run_to_writer_with(root_path, config, provider, &mut rendered)?;
+    let block = output::marked_block(&rendered);
 
-    // Determine the blkid tag to use
-    let id_tag = id_type.blkid_tag();
+    if action == ExistingBlockAction::Replace {
+        let existing =
+            std::fs::read_to_string(path).map_err(|e| RecfstabError::read_failed(path, e))?;
+        let block = String::from_utf8_lossy(&block);
+        return write_atomic(path, &output::replace_marked_block(&existing, &block));
+    }
 
-    // Get all mounts using findmnt
-    let mounts = get_mounts()?;
-    let mut seen_targets: HashSet<String> = HashSet::new();
-    let mut found_any = false;
+    let mut file = OutputFile::open(path, mode)?;
+    file.write_all(&block)
+        .map_err(|e| RecfstabError::output_failed(path, e))?;
+    file.finish(path)
+}
 
-    for mount in mounts {
-        // Skip mounts not under our root
-        if !is_under_root(&mount.target, &root_str) {
-            continue;
-        }
+/// Write one fstab drop-in per generated entry into `dir` (see
+/// [`output::write_dropins`]).
+fn write_dropin_dir(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    dir: &Path,
+) -> Result<()> {
+    // Render, then parse back, so each file holds exactly what would be written
+    let mut rendered = Vec::new();
+    run_to_writer_with(root_path, config, provider, &mut rendered)?;
+    let entries = fstab::parse_fstab(&String::from_utf8_lossy(&rendered));
+    output::write_dropins(dir, &entries)?;
+    Ok(())
+}
 
-        // Skip pseudo-filesystems
-        if is_pseudo_filesystem(&mount.fstype) {
-            continue;
-        }
+/// Generate the fstab text for `root_path` using `config`.
+///
+/// The one-call API for embedding: returns exactly what [`run_to_writer`]
+/// would write (entries, then swaps), or the error it would fail with.
+pub fn generate(root_path: &str, config: &Config) -> Result<String> {
+    generate_with(root_path, config, &system_provider(root_path, config))
+}
 
-        // Skip duplicates
-        if seen_targets.contains(&mount.target) {
-            continue;
-        }
-        seen_targets.insert(mount.target.clone());
+/// Like [`generate`], but reads mounts and swaps from `provider`.
+pub fn generate_with(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+) -> Result<String> {
+    let mut rendered = Vec::new();
+    run_to_writer_with(root_path, config, provider, &mut rendered)?;
+    // Non-UTF-8 paths are octal-escaped, so the output is always valid UTF-8
+    Ok(String::from_utf8_lossy(&rendered).into_owned())
+}
 
-        // Convert absolute target path to path relative to root
-        let fstab_target = make_fstab_target(&mount.target, &root_str);
+/// Trace how the entry for `mountpoint` (as written in fstab, e.g. `/home`)
+/// is built from the mounts under `root_path` (see
+/// [`generate::explain_entry_with`]).
+pub fn explain_entry(root_path: &str, config: &Config, mountpoint: &str) -> Result<String> {
+    let blkid = config.blkid();
+    explain_entry_with(
+        root_path,
+        config,
+        &system_provider(root_path, config),
+        blkid.runner().as_ref(),
+        mountpoint,
+    )
+}
 
-        // Get UUID/LABEL/PARTUUID/PARTLABEL for the device
-        let identifier = get_device_identifier(&mount.source, id_tag);
+/// Like [`explain_entry`], but reads mounts from `provider` and runs blkid
+/// through `runner`.
+///
+/// Mounts go through the same selection as generation, so a mount that
+/// would be skipped (pseudo, duplicate, ...) has no entry to explain.
+pub fn explain_entry_with(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    runner: &dyn command::CommandRunner,
+    mountpoint: &str,
+) -> Result<String> {
+    let root_str = resolve_root(root_path.trim(), config)?;
+    let wanted = config
+        .mountpoint_case
+        .apply(&fstab::normalize_path(mountpoint));
+    let mut filter = generate::MountFilter::for_config(&root_str, config);
+    provider
+        .mounts()?
+        .iter()
+        .filter(|mount| filter.accept(mount))
+        .find(|mount| {
+            config
+                .mountpoint_case
+                .apply(&make_fstab_target(&mount.target, &root_str))
+                == wanted
+        })
+        .map(|mount| generate::explain_entry_with(mount, &root_str, config, runner))
+        .ok_or_else(|| RecfstabError::entry_not_found(&wanted, &root_str))
+}
 
-        // Determine fsck pass number
-        let pass = determine_pass_number(&fstab_target, &mount.fstype);
+/// Generate entries for `root_path` and write how they differ from the
+/// fstab at `old` (see [`diff::FstabDiff::render`]) to `out`.
+pub fn diff_to_writer<W: Write>(
+    root_path: &str,
+    config: &Config,
+    old: &Path,
+    out: &mut W,
+) -> Result<()> {
+    diff_to_writer_with(
+        root_path,
+        config,
+        &system_provider(root_path, config),
+        old,
+        out,
+    )
+}
 
-        // Filter runtime-only mount options
-        let filtered_options = filter_options(&mount.options);
+/// Like [`diff_to_writer`], but reads mounts and swaps from `provider`.
+pub fn diff_to_writer_with<W: Write>(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    old: &Path,
+    out: &mut W,
+) -> Result<()> {
+    let diff = generated_diff(root_path, config, provider, old)?;
+    out.write_all(diff.render().as_bytes())
+        .map_err(RecfstabError::write_failed)?;
+    out.flush().map_err(RecfstabError::write_failed)
+}
 
-        // Output fstab entry (escape spaces/tabs/newlines per fstab(5))
-        println!("# {}", mount.source);
-        println!(
-            "{}\t{}\t{}\t{}\t0\t{}",
-            escape_fstab(&identifier),
-            escape_fstab(&fstab_target),
-            mount.fstype,
-            filtered_options,
-            pass
-        );
-        println!();
+/// Show what regenerating the fstab at `old` would change, without writing
+/// anything: unified-style `-`/`+` lines (see
+/// [`diff::FstabDiff::render_unified`]) go to `out` and a count of the
+/// changes to stderr.
+pub fn dry_run_diff_to_writer<W: Write>(
+    root_path: &str,
+    config: &Config,
+    old: &Path,
+    out: &mut W,
+) -> Result<()> {
+    dry_run_diff_to_writer_with(
+        root_path,
+        config,
+        &system_provider(root_path, config),
+        old,
+        out,
+    )
+}
+
+/// Like [`dry_run_diff_to_writer`], but reads mounts and swaps from `provider`.
+pub fn dry_run_diff_to_writer_with<W: Write>(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    old: &Path,
+    out: &mut W,
+) -> Result<()> {
+    let diff = generated_diff(root_path, config, provider, old)?;
+    out.write_all(diff.render_unified().as_bytes())
+        .map_err(RecfstabError::write_failed)?;
+    out.flush().map_err(RecfstabError::write_failed)?;
+    log::note(
+        "dry-run-summary",
+        &format!("{} (nothing written to {})", diff.summary(), old.display()),
+        &[("path", &old.display().to_string())],
+    );
+    Ok(())
+}
+
+/// Compare the fstab at `old` with the entries generated for `root_path`.
+fn generated_diff(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    old: &Path,
+) -> Result<diff::FstabDiff> {
+    let content = std::fs::read_to_string(old).map_err(|e| RecfstabError::read_failed(old, e))?;
+    // Render, then parse back, so the comparison sees exactly what would be written
+    let mut rendered = Vec::new();
+    run_to_writer_with(root_path, config, provider, &mut rendered)?;
+    let generated = fstab::parse_fstab(&String::from_utf8_lossy(&rendered));
+    Ok(diff::diff_fstab(&fstab::parse_fstab(&content), &generated))
+}
+
+/// Check that each generated entry's options would reproduce its running
+/// mount (see [`verify::compare_options`]), writing one line per
+/// discrepancy to `out`.
+///
+/// Fails with E015 if any entry differs.
+pub fn verify_options_to_writer<W: Write>(
+    root_path: &str,
+    config: &Config,
+    out: &mut W,
+) -> Result<()> {
+    verify_options_to_writer_with(root_path, config, &system_provider(root_path, config), out)
+}
 
-        found_any = true;
+/// Like [`verify_options_to_writer`], but reads mounts from `provider`.
+pub fn verify_options_to_writer_with<W: Write>(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    out: &mut W,
+) -> Result<()> {
+    let root_str = resolve_root(root_path.trim(), config)?;
+    let mut filter = generate::MountFilter::for_config(&root_str, config);
+    let mut differing = Vec::new();
+    for mount in provider
+        .mounts()?
+        .iter()
+        .filter(|mount| filter.accept(mount))
+    {
+        let entry = generate::entry_from_mount(mount, &root_str, config);
+        let mismatch = verify::compare_options(&entry.options, &mount.options);
+        if !mismatch.is_empty() {
+            writeln!(out, "{}", mismatch.describe(&entry.mountpoint))
+                .map_err(RecfstabError::write_failed)?;
+            differing.push(entry.mountpoint);
+        }
+    }
+    out.flush().map_err(RecfstabError::write_failed)?;
+    if differing.is_empty() {
+        Ok(())
+    } else {
+        Err(RecfstabError::options_mismatch(&differing))
+    }
+}
+
+/// Generate fstab entries for `root_path` using `config`, writing to `out`.
+///
+/// With `config.streaming` set, mounts are read from findmnt and written one
+/// at a time; otherwise all entries are collected before any are written.
+pub fn run_to_writer<W: Write>(root_path: &str, config: &Config, out: &mut W) -> Result<()> {
+    run_to_writer_with(root_path, config, &system_provider(root_path, config), out)
+}
+
+/// Like [`run_to_writer`], but reads mounts and swaps from `provider`.
+pub fn run_to_writer_with<W: Write>(
+    root_path: &str,
+    config: &Config,
+    provider: &dyn MountProvider,
+    out: &mut W,
+) -> Result<()> {
+    // Validate input - empty or whitespace-only paths are invalid
+    let root_path = root_path.trim();
+    let root_str = resolve_root(root_path, config)?;
+
+    // Read the merge target up front so a bad path fails before any output
+    let existing = match &config.merge {
+        Some(path) => {
+            let content =
+                std::fs::read_to_string(path).map_err(|e| RecfstabError::read_failed(path, e))?;
+            Some(fstab::parse_fstab(&content))
+        }
+        None => None,
+    };
+    let options_map = match &config.options_map {
+        Some(path) => {
+            let content =
+                std::fs::read_to_string(path).map_err(|e| RecfstabError::read_failed(path, e))?;
+            Some(options_map::parse_options_map(&content))
+        }
+        None => None,
+    };
+    let pass_map = match &config.pass_map {
+        Some(path) => {
+            let content =
+                std::fs::read_to_string(path).map_err(|e| RecfstabError::read_failed(path, e))?;
+            let map = pass_map::parse_pass_map(&content)
+                .map_err(|problem| RecfstabError::invalid_pass_map(path, &problem))?;
+            Some(map)
+        }
+        None => None,
+    };
+
+    if config.template_header {
+        out.write_all(fstab::TEMPLATE_HEADER.as_bytes())
+            .map_err(RecfstabError::write_failed)?;
+    }
+
+    // Streaming never holds the mount table; swap resolution then sees none
+    let mut mounts = Vec::new();
+    let mut entries = Vec::new();
+    let mut fallbacks = Vec::new();
+    let mut summary = summary::Summary::default();
+    let has_root;
+    if config.streams() {
+        let mut writer = generate::StreamWriter::new(&root_str, config, out);
+        provider.for_each_mount(&mut |mount| writer.push(mount))?;
+        writer.finish()?;
+        fallbacks = writer.fallbacks().to_vec();
+        has_root = writer.wrote_root();
+        summary.filesystems = writer.written();
+        summary.skipped = writer.skipped().clone();
+    } else {
+        mounts = provider.mounts()?;
+        (entries, summary.skipped) =
+            generate::entries_from_mounts_counted(&mounts, &root_str, config);
+        has_root = entries
+            .iter()
+            .any(|entry| generate::is_root_entry(entry, config));
+        summary.filesystems = entries.len();
+    }
+    // Streamed entries are already out; the error still fails the run
+    if config.require_root && !has_root {
+        return Err(RecfstabError::root_entry_missing(root_path));
     }
 
     // Add swap entries
-    if let Ok(swaps) = read_swaps() {
-        for swap_entry in &swaps {
-            if swap::is_swap_under_root(swap_entry, &root_str) {
-                swap::print_swap_entries(std::slice::from_ref(swap_entry), &root_str, id_tag);
-                found_any = true;
-            }
+    let swaps = if config.no_swap {
+        Ok(Vec::new())
+    } else {
+        provider.swaps()
+    };
+    if let Ok(swaps) = swaps {
+        let swap_entries = generate::swap_entries(&swaps, &root_str, config, &mounts);
+        summary.swaps = swap_entries.len();
+        entries.extend(swap_entries);
+    }
+    if let Some(map) = &options_map {
+        options_map::apply_options_map(&mut entries, map, config.genfstab_compat);
+    }
+    if let Some(map) = &pass_map {
+        pass_map::apply_pass_map(&mut entries, map);
+    }
+
+    // Checked before writing, except for mounts already streamed out
+    fallbacks.extend(
+        entries
+            .iter()
+            .filter(|entry| entry.id_fallback)
+            .map(|entry| entry.fs_spec.clone()),
+    );
+    for device in &fallbacks {
+        log::debug(
+            "identifier-fallback",
+            &format!("no identifier found for {}; using the device path", device),
+            &[("device", device)],
+        );
+    }
+    if config.fail_on_fallback && !fallbacks.is_empty() {
+        return Err(RecfstabError::identifier_fallback(&fallbacks));
+    }
+
+    if config.no_comments {
+        for entry in &mut entries {
+            entry.comments.clear();
+        }
+    }
+    generate::self_check_entries(&entries, config)?;
+    generate::check_line_lengths(&entries, config)?;
+    generate::check_empty_options(&entries, config)?;
+    generate::check_label_collisions(&entries, config)?;
+    if let Some(target_root) = &config.check_mountpoints {
+        let missing = generate::missing_mountpoints(&entries, target_root);
+        if config.strict && !missing.is_empty() {
+            return Err(RecfstabError::mountpoint_missing(target_root, &missing));
+        }
+        for mountpoint in &missing {
+            log::warning(
+                "mountpoint-missing",
+                &format!(
+                    "{} has no directory under {}; it will fail to mount",
+                    mountpoint,
+                    target_root.display()
+                ),
+                &[("mountpoint", mountpoint)],
+            );
         }
     }
+    if let Some(existing) = existing {
+        entries = merge::merge_entries(existing, entries, config.merge_policy);
+    }
+    if config.sorted_unique {
+        entries = generate::sorted_unique_entries(entries);
+    }
+    if config.group_by == Some(generate::GroupBy::Device) && !config.no_comments {
+        entries = generate::group_by_device(entries);
+    }
+    if let Some(width) = config.comment_width {
+        for entry in &mut entries {
+            entry.wrap_comments(width);
+        }
+    }
+    generate::warn_noauto_entries(&entries, config);
+    generate::write_entries(out, &entries, config.output_encoding)?;
+    if config.summary_comment {
+        out.write_all(summary.comment().as_bytes())
+            .map_err(RecfstabError::write_failed)?;
+    }
+
+    out.flush().map_err(RecfstabError::write_failed)?;
 
-    if !found_any {
+    if summary.filesystems + summary.swaps == 0 {
         return Err(RecfstabError::no_filesystems(root_path));
     }
 
     Ok(())
 }
 
+/// Validate the root directory and normalize it for mount matching.
+///
+/// Returns the canonical path (or, with `config.no_canonicalize`, the path
+/// as given) without a trailing slash ("/" stays as-is).
+fn resolve_root(root_path: &str, config: &Config) -> Result<String> {
+    if root_path.is_empty() {
+        return Err(RecfstabError::root_not_found("(empty path)"));
+    }
+
+    let root = Path::new(root_path);
+
+    // Validate root directory
+    if !root.exists() {
+        return Err(RecfstabError::root_not_found(root_path));
+    }
+    if !root.is_dir() {
+        return Err(RecfstabError::not_a_directory(root_path));
+    }
+
+    // Canonicalize the root path to resolve symlinks
+    // This ensures we match mount targets correctly even if root is a symlink
+    let root_str = if config.no_canonicalize {
+        root_path.to_string()
+    } else {
+        let canonical_root =
+            std::fs::canonicalize(root).map_err(RecfstabError::current_dir_failed)?;
+        canonical_root.to_string_lossy().to_string()
+    };
+
+    // Remove trailing slash for consistent comparison, but keep "/" as-is
+    if root_str == "/" {
+        Ok(root_str)
+    } else {
+        Ok(root_str.trim_end_matches('/').to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -232,4 +725,753 @@ mod tests {
         // Should report trimmed path, not whitespace version
         assert!(err.message.contains("/nonexistent"));
     }
+
+    #[test]
+    fn test_resolve_root_no_canonicalize_keeps_symlink() {
+        let temp_dir = std::env::temp_dir().join("recfstab_test_no_canonicalize");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(temp_dir.join("real")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.join("real"), temp_dir.join("link")).unwrap();
+        let link = temp_dir.join("link");
+        let link_str = link.to_str().unwrap();
+
+        let config = Config {
+            no_canonicalize: true,
+            ..Config::default()
+        };
+        let as_given = resolve_root(&format!("{}/", link_str), &config).unwrap();
+        assert_eq!(as_given, link_str);
+
+        let resolved = resolve_root(link_str, &Config::default()).unwrap();
+        let real = std::fs::canonicalize(temp_dir.join("real")).unwrap();
+        assert_eq!(resolved, real.to_str().unwrap());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    /// Create an empty `temp_dir()/name` with a `root/` directory to scan.
+    ///
+    /// Returns the directory, for fixture files, and the canonical root path
+    /// that mount targets have to match.
+    fn test_root(name: &str) -> (std::path::PathBuf, String) {
+        let temp_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(temp_dir.join("root")).unwrap();
+        let root = std::fs::canonicalize(temp_dir.join("root")).unwrap();
+        (temp_dir, root.to_str().unwrap().to_string())
+    }
+
+    /// A mount at `target` under `root` (`""` for the root itself).
+    fn mount(root: &str, target: &str, source: &str, fstype: &str, options: &str) -> MountInfo {
+        MountInfo {
+            target: format!("{}{}", root, target),
+            source: source.to_string(),
+            fstype: fstype.to_string(),
+            options: options.to_string(),
+            ..MountInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_fail_on_fallback() {
+        let (temp_dir, root) = test_root("recfstab_test_fail_on_fallback");
+        // blkid has nothing for a device that does not exist
+        let provider = StaticProvider(vec![mount(
+            &root,
+            "",
+            "/dev/recfstab-test-missing",
+            "ext4",
+            "rw",
+        )]);
+
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &Config::default(), &provider, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("/dev/recfstab-test-missing\t/\t"));
+
+        let config = Config {
+            fail_on_fallback: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let err = run_to_writer_with(&root, &config, &provider, &mut out).unwrap_err();
+        assert_eq!(err.code, ErrorCode::IdentifierFallback);
+        assert!(err.message.contains("/dev/recfstab-test-missing"));
+        assert!(out.is_empty());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_with_static_provider() {
+        let (temp_dir, root) = test_root("recfstab_test_static_provider");
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/proc", "proc", "proc", "rw"),
+        ]);
+
+        for streaming in [false, true] {
+            let config = Config {
+                streaming,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "# UUID=root\nUUID=root\t/\text4\tdefaults\t0\t1\n\n"
+            );
+        }
+
+        let empty = StaticProvider::default();
+        let err =
+            run_to_writer_with(&root, &Config::default(), &empty, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NoFilesystems);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_unmounted_dir_is_no_filesystems() {
+        // Nothing is mounted at or under the root; only the host's / holds it
+        let (temp_dir, root) = test_root("recfstab_test_unmounted_dir");
+        let provider = StaticProvider(vec![
+            mount("/", "", "UUID=host", "ext4", "rw"),
+            mount("/proc", "", "proc", "proc", "rw"),
+        ]);
+
+        for streaming in [false, true] {
+            let config = Config {
+                streaming,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            let err = run_to_writer_with(&root, &config, &provider, &mut out).unwrap_err();
+            assert_eq!(err.code, ErrorCode::NoFilesystems);
+            assert!(out.is_empty());
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_summary_comment() {
+        struct WithSwap(StaticProvider, Vec<SwapInfo>);
+        impl MountProvider for WithSwap {
+            fn mounts(&self) -> Result<Vec<MountInfo>> {
+                self.0.mounts()
+            }
+            fn swaps(&self) -> Result<Vec<SwapInfo>> {
+                Ok(self.1.clone())
+            }
+        }
+
+        let (temp_dir, root) = test_root("recfstab_test_summary_comment");
+        let provider = WithSwap(
+            StaticProvider(vec![
+                mount(&root, "", "UUID=root", "ext4", "rw"),
+                mount(&root, "/boot", "UUID=boot", "vfat", "rw"),
+                mount(&root, "/proc", "proc", "proc", "rw"),
+                mount(&root, "/sys", "sysfs", "sysfs", "rw"),
+                mount(&root, "/boot", "UUID=other", "vfat", "rw"),
+                mount("/elsewhere", "", "UUID=out", "ext4", "rw"),
+            ]),
+            vec![SwapInfo {
+                filename: format!("{}/swapfile", root),
+                swap_type: "file".to_string(),
+            }],
+        );
+
+        for streaming in [false, true] {
+            let config = Config {
+                streaming,
+                summary_comment: true,
+                ..Config::default()
+            };
+            let mut out = Vec::new();
+            run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(
+                out.ends_with(
+                    "\n# recfstab: 2 filesystems, 1 swap, 3 skipped (2 pseudo, 1 duplicate)\n"
+                ),
+                "{}",
+                out
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_generate_with_static_provider() {
+        let (temp_dir, root) = test_root("recfstab_test_generate");
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/home", "LABEL=home", "xfs", "rw,relatime"),
+        ]);
+
+        assert_eq!(
+            generate_with(&root, &Config::default(), &provider).unwrap(),
+            "# UUID=root\nUUID=root\t/\text4\tdefaults\t0\t1\n\n\
+             # LABEL=home\nLABEL=home\t/home\txfs\tdefaults\t0\t2\n\n"
+        );
+
+        let err = generate_with(&root, &Config::default(), &StaticProvider::default()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NoFilesystems);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_generate_is_idempotent() {
+        let (temp_dir, root) = test_root("recfstab_test_idempotent");
+        let provider = StaticProvider(vec![
+            mount(
+                &root,
+                "",
+                "UUID=root",
+                "btrfs",
+                "rw,noatime,compress=zstd,subvol=/@",
+            ),
+            mount(&root, "/var", "UUID=data", "xfs", "rw,relatime,inode64"),
+            mount(
+                &root,
+                "/home",
+                "UUID=root",
+                "btrfs",
+                "rw,noatime,subvol=/@home",
+            ),
+            mount(
+                &root,
+                "/boot",
+                "UUID=ABCD",
+                "vfat",
+                "rw,relatime,fmask=0022,dmask=0022",
+            ),
+            mount(&root, "/var", "UUID=data", "xfs", "rw,relatime,inode64"),
+            mount(&root, "/proc", "proc", "proc", "rw"),
+            mount(&root, "/srv", "UUID=data", "xfs", "rw,relatime"),
+        ]);
+
+        let configs = [
+            Config::default(),
+            Config {
+                sort_options: true,
+                normalize_option_order: true,
+                sorted_unique: true,
+                summary_comment: true,
+                ..Config::default()
+            },
+            Config {
+                streaming: true,
+                summary_comment: true,
+                ..Config::default()
+            },
+        ];
+        for config in &configs {
+            let first = generate_with(&root, config, &provider).unwrap();
+            let second = generate_with(&root, config, &provider).unwrap();
+            assert_eq!(first, second);
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_explain_entry_traces_blkid_and_options() {
+        let (temp_dir, root) = test_root("recfstab_test_explain_entry");
+        let provider = StaticProvider(vec![
+            mount(&root, "", "/dev/sda2", "ext4", "rw,relatime"),
+            mount(
+                &root,
+                "/home",
+                "/dev/sda3",
+                "xfs",
+                "rw,noatime,inode64,logbufs=8",
+            ),
+        ]);
+        let runner = command::MockRunner::new(0, "a1b2-c3d4\n");
+
+        let text =
+            explain_entry_with(&root, &Config::default(), &provider, &runner, "/home/").unwrap();
+        assert!(
+            text.contains("blkid -s UUID -o value /dev/sda3 -> exit 0: a1b2-c3d4"),
+            "{}",
+            text
+        );
+        assert!(text.contains("raw options: rw,noatime,inode64,logbufs=8\n"));
+        assert!(text.contains("filtered:    inode64,logbufs=8\n"));
+        assert!(text.contains("pass:        2\n"));
+        assert!(text.contains("entry:       UUID=a1b2-c3d4\t/home\txfs"));
+        assert_eq!(runner.calls(), ["blkid -s UUID -o value /dev/sda3"]);
+
+        let err =
+            explain_entry_with(&root, &Config::default(), &provider, &runner, "/srv").unwrap_err();
+        assert_eq!(err.code, ErrorCode::EntryNotFound);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_template_header_precedes_entries() {
+        let (temp_dir, root) = test_root("recfstab_test_template_header");
+        let provider = StaticProvider(vec![mount(&root, "", "UUID=root", "ext4", "rw,relatime")]);
+        for streaming in [false, true] {
+            let config = Config {
+                template_header: true,
+                streaming,
+                ..Config::default()
+            };
+            assert_eq!(
+                generate_with(&root, &config, &provider).unwrap(),
+                "# Static information about the filesystems.\n\
+                 # See fstab(5) for details.\n\
+                 \n\
+                 # <file system> <dir> <type> <options> <dump> <pass>\n\
+                 # UUID=root\nUUID=root\t/\text4\tdefaults\t0\t1\n\n"
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_verify_options_reports_discrepancy() {
+        let (temp_dir, root) = test_root("recfstab_test_verify_options");
+        let provider = StaticProvider(vec![
+            mount(
+                &root,
+                "",
+                "UUID=root",
+                "btrfs",
+                "rw,noatime,subvolid=256,subvol=/@",
+            ),
+            mount(
+                &root,
+                "/home",
+                "UUID=home",
+                "ext4",
+                "rw,relatime,errors=remount-ro",
+            ),
+        ]);
+
+        let mut out = Vec::new();
+        verify_options_to_writer_with(&root, &Config::default(), &provider, &mut out).unwrap();
+        assert_eq!(out, b"");
+
+        // A preserved option the running mount does not have
+        let config = Config {
+            root_options: Some("subvol=/@,compress=zstd".to_string()),
+            ..Config::default()
+        };
+        let err = verify_options_to_writer_with(&root, &config, &provider, &mut out).unwrap_err();
+        assert_eq!(err.code, ErrorCode::OptionsMismatch);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/: not applied at runtime: compress=zstd\n"
+        );
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_check_mountpoints_strict() {
+        let (temp_dir, root) = test_root("recfstab_test_check_mountpoints");
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw"),
+            mount(&root, "/data", "UUID=data", "ext4", "rw"),
+        ]);
+
+        // The scan root doubles as the target root; it has no data/ directory
+        let mut config = Config {
+            check_mountpoints: Some(root.clone().into()),
+            ..Config::default()
+        };
+        assert!(generate_with(&root, &config, &provider).is_ok());
+
+        config.strict = true;
+        let err = generate_with(&root, &config, &provider).unwrap_err();
+        assert_eq!(err.code, ErrorCode::MountpointMissing);
+        assert!(err.message.contains("/data"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_streaming_validates_root() {
+        let config = Config {
+            streaming: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let err = run_to_writer("/nonexistent/stream/root", &config, &mut out).unwrap_err();
+        assert_eq!(err.code, ErrorCode::RootNotFound);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_run_to_writer_with_merge() {
+        let (temp_dir, root) = test_root("recfstab_test_merge");
+        let existing = temp_dir.join("fstab");
+        std::fs::write(
+            &existing,
+            "# old root\nUUID=old\t/\text4\tnoatime\t0\t1\n\nsrv:/x\t/srv\tnfs\tdefaults\t0\t0\n",
+        )
+        .unwrap();
+
+        let provider = StaticProvider(vec![mount(&root, "", "UUID=root", "ext4", "rw,relatime")]);
+
+        let mut config = Config {
+            merge: Some(existing.clone()),
+            // Ignored: merging needs the whole entry list
+            streaming: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# UUID=root\nUUID=root\t/\text4\tdefaults\t0\t1\n\nsrv:/x\t/srv\tnfs\tdefaults\t0\t0\n\n"
+        );
+
+        config.merge = Some(temp_dir.join("missing"));
+        let err = run_to_writer_with(&root, &config, &provider, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ReadFailed);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_sorted_unique_after_merge() {
+        let (temp_dir, root) = test_root("recfstab_test_sorted_unique");
+        // Hand-edited fstab that already lists the root twice
+        let existing = temp_dir.join("fstab");
+        std::fs::write(
+            &existing,
+            "srv:/x\t/srv\tnfs\tdefaults\t0\t0\n\
+             UUID=root\t/\text4\tdefaults\t0\t1\n\
+             # copy\nUUID=root\t/\text4\tdefaults\t0\t1\n",
+        )
+        .unwrap();
+
+        let provider = StaticProvider(vec![mount(&root, "", "UUID=root", "ext4", "rw,relatime")]);
+        let mut config = Config {
+            merge: Some(existing),
+            no_comments: true,
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("UUID=root\t/\t").count(), 2);
+
+        config.sorted_unique = true;
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "UUID=root\t/\text4\tdefaults\t0\t1\n\n\
+             srv:/x\t/srv\tnfs\tdefaults\t0\t0\n\n"
+        );
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_options_map_overrides_home() {
+        let (temp_dir, root) = test_root("recfstab_test_options_map");
+        let map = temp_dir.join("options");
+        std::fs::write(&map, "# tuned\n/home noatime,commit=60\n").unwrap();
+
+        let options = "rw,relatime,errors=remount-ro";
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", options),
+            mount(&root, "/home", "UUID=home", "ext4", options),
+            mount(&root, "/srv", "UUID=srv", "ext4", options),
+        ]);
+        let mut config = Config {
+            options_map: Some(map),
+            no_comments: true,
+            // Ignored: the map is applied to the collected entries
+            streaming: true,
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "UUID=root\t/\text4\terrors=remount-ro\t0\t1\n\n\
+             UUID=home\t/home\text4\tnoatime,commit=60\t0\t2\n\n\
+             UUID=srv\t/srv\text4\terrors=remount-ro\t0\t2\n\n"
+        );
+
+        config.options_map = Some(temp_dir.join("missing"));
+        let err = run_to_writer_with(&root, &config, &provider, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ReadFailed);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_writer_pass_map_overrides_home() {
+        let (temp_dir, root) = test_root("recfstab_test_pass_map");
+        let map = temp_dir.join("passes");
+        std::fs::write(&map, "# fsck with the root\n/home 1\n").unwrap();
+
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/home", "UUID=home", "ext4", "rw,relatime"),
+            mount(&root, "/srv", "UUID=srv", "ext4", "rw,relatime"),
+        ]);
+        let config = Config {
+            pass_map: Some(map.clone()),
+            no_comments: true,
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        run_to_writer_with(&root, &config, &provider, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "UUID=root\t/\text4\tdefaults\t0\t1\n\n\
+             UUID=home\t/home\text4\tdefaults\t0\t1\n\n\
+             UUID=srv\t/srv\text4\tdefaults\t0\t2\n\n"
+        );
+
+        std::fs::write(&map, "/home 3\n").unwrap();
+        let err = run_to_writer_with(&root, &config, &provider, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidPassMap);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_diff_to_writer_with() {
+        let (temp_dir, root) = test_root("recfstab_test_diff");
+        let old = temp_dir.join("fstab");
+        std::fs::write(
+            &old,
+            "UUID=root\t/\text4\tnoatime\t0\t1\nUUID=data\t/data\txfs\tdefaults\t0\t2\n",
+        )
+        .unwrap();
+
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/boot", "UUID=ABCD-1234", "vfat", "rw,umask=0077"),
+        ]);
+        let mut out = Vec::new();
+        diff_to_writer_with(&root, &Config::default(), &provider, &old, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "- UUID=data\t/data\txfs\tdefaults\t0\t2\n\
+             + UUID=ABCD-1234\t/boot\tvfat\tumask=0077\t0\t0\n\
+             ~ / options: noatime -> defaults\n"
+        );
+
+        let missing = temp_dir.join("missing");
+        let err = diff_to_writer_with(
+            &root,
+            &Config::default(),
+            &provider,
+            &missing,
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::ReadFailed);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_dry_run_diff_leaves_fstab_unchanged() {
+        let (temp_dir, root) = test_root("recfstab_test_dry_run_diff");
+        let old = temp_dir.join("fstab");
+        let content = "# existing\nUUID=root\t/\text4\tnoatime\t0\t1\n";
+        std::fs::write(&old, content).unwrap();
+
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/home", "UUID=home", "ext4", "rw,nodev"),
+        ]);
+        let mut out = Vec::new();
+        dry_run_diff_to_writer_with(&root, &Config::default(), &provider, &old, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "-UUID=root\t/\text4\tnoatime\t0\t1\n\
+             +UUID=root\t/\text4\tdefaults\t0\t1\n\
+             +UUID=home\t/home\text4\tnodev\t0\t2\n"
+        );
+        assert_eq!(std::fs::read_to_string(&old).unwrap(), content);
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_require_root() {
+        let (temp_dir, root) = test_root("recfstab_test_require_root");
+        let with_root = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw"),
+            mount(&root, "/home", "UUID=home", "ext4", "rw"),
+        ]);
+        let submounts_only = StaticProvider(vec![mount(&root, "/home", "UUID=home", "ext4", "rw")]);
+
+        for streaming in [false, true] {
+            let config = Config {
+                require_root: true,
+                streaming,
+                ..Config::default()
+            };
+            run_to_writer_with(&root, &config, &with_root, &mut Vec::new()).unwrap();
+            let err =
+                run_to_writer_with(&root, &config, &submounts_only, &mut Vec::new()).unwrap_err();
+            assert_eq!(err.code, ErrorCode::RootEntryMissing);
+        }
+        // Off by default
+        run_to_writer_with(&root, &Config::default(), &submounts_only, &mut Vec::new()).unwrap();
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_targets_routes_each_artifact() {
+        let (temp_dir, root) = test_root("recfstab_test_split_output");
+        let sys = crypttab::tests::sysfs_fixture("recfstab_test_split_output_sys");
+
+        let provider = StaticProvider(vec![
+            mount(&root, "", "/dev/mapper/cryptroot", "ext4", "rw"),
+            mount(&root, "/boot", "UUID=BOOT-0001", "vfat", "rw"),
+        ]);
+        let config = Config {
+            sys_root: Some(sys.clone()),
+            ..Config::default()
+        };
+        let targets = OutputTargets {
+            fstab: Some(temp_dir.join("fstab")),
+            crypttab: Some(temp_dir.join("crypttab")),
+            mode: WriteMode::Truncate,
+            ..OutputTargets::default()
+        };
+        run_to_targets_with(&root, &config, &provider, &targets).unwrap();
+
+        let fstab = std::fs::read_to_string(temp_dir.join("fstab")).unwrap();
+        let crypttab = std::fs::read_to_string(temp_dir.join("crypttab")).unwrap();
+        assert!(fstab.contains("/boot\tvfat"), "fstab was: {}", fstab);
+        assert!(!fstab.contains("luks"), "fstab was: {}", fstab);
+        assert_eq!(
+            crypttab,
+            "cryptroot\tUUID=1b4e28ba-2fa1-411f-9b57-d8a3c1e7e3c9\tnone\tluks\n"
+        );
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let _ = std::fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_run_to_targets_failure_leaves_fstab_untouched() {
+        let (temp_dir, root) = test_root("recfstab_test_failed_output");
+        let fstab = temp_dir.join("fstab");
+        let content = "UUID=old\t/\text4\tdefaults\t0\t1\n";
+
+        for mode in [WriteMode::Append, WriteMode::Truncate, WriteMode::Atomic] {
+            std::fs::write(&fstab, content).unwrap();
+            let targets = OutputTargets {
+                fstab: Some(fstab.clone()),
+                mode,
+                ..OutputTargets::default()
+            };
+            // No mounts under the root: E006 before anything is written
+            let err = run_to_targets_with(
+                &root,
+                &Config::default(),
+                &StaticProvider::default(),
+                &targets,
+            )
+            .unwrap_err();
+            assert_eq!(err.code, ErrorCode::NoFilesystems);
+            assert_eq!(std::fs::read_to_string(&fstab).unwrap(), content);
+        }
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_run_to_targets_dropin_dir() {
+        let (temp_dir, root) = test_root("recfstab_test_dropin");
+        let dir = temp_dir.join("fstab.d");
+
+        let provider = StaticProvider(vec![
+            mount(&root, "", "UUID=root", "ext4", "rw,relatime"),
+            mount(&root, "/home", "UUID=home", "ext4", "rw,relatime"),
+            mount(&root, "/srv/my data", "UUID=srv", "ext4", "rw,relatime"),
+        ]);
+        let targets = OutputTargets {
+            dropin_dir: Some(dir.clone()),
+            ..OutputTargets::default()
+        };
+        run_to_targets_with(&root, &Config::default(), &provider, &targets).unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["-.fstab", "home.fstab", "srv-my\\x20data.fstab"]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("home.fstab")).unwrap(),
+            "# UUID=home\nUUID=home\t/home\text4\tdefaults\t0\t2\n\n"
+        );
+        let srv = std::fs::read_to_string(dir.join("srv-my\\x20data.fstab")).unwrap();
+        assert_eq!(fstab::parse_fstab(&srv).len(), 1);
+        assert_eq!(fstab::parse_fstab(&srv)[0].mountpoint, "/srv/my data");
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_interactive_replace_swaps_marked_block() {
+        let (temp_dir, root) = test_root("recfstab_test_interactive");
+        let fstab = temp_dir.join("fstab");
+
+        let provider = StaticProvider(vec![mount(&root, "", "UUID=new", "ext4", "rw")]);
+        let targets = OutputTargets {
+            fstab: Some(fstab.clone()),
+            interactive: true,
+            ..OutputTargets::default()
+        };
+
+        // First run: no block yet, so no prompt (empty input would append anyway)
+        std::fs::write(&fstab, "# header\n").unwrap();
+        let mut no_input = std::io::Cursor::new("");
+        run_to_targets_prompting(
+            &root,
+            &Config::default(),
+            &provider,
+            &targets,
+            &mut no_input,
+        )
+        .unwrap();
+        let first = std::fs::read_to_string(&fstab).unwrap();
+        assert!(
+            first.starts_with("# header\n# BEGIN recfstab"),
+            "was: {}",
+            first
+        );
+
+        // Second run: the old block is replaced, not duplicated
+        let provider = StaticProvider(vec![mount(&root, "", "UUID=newer", "ext4", "rw")]);
+        let mut replace = std::io::Cursor::new("r\n");
+        run_to_targets_prompting(&root, &Config::default(), &provider, &targets, &mut replace)
+            .unwrap();
+        let second = std::fs::read_to_string(&fstab).unwrap();
+        assert_eq!(second.matches(output::MARKER_BEGIN).count(), 1);
+        assert!(second.contains("UUID=newer"), "was: {}", second);
+        assert!(!second.contains("UUID=new\t"), "was: {}", second);
+        assert!(second.starts_with("# header\n"));
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 }
@@ -0,0 +1,184 @@
+//! Diagnostics on stderr: errors, warnings, notes, and skip reasons.
+//!
+//! Every diagnostic goes through this module so it can be written as text
+//! (`recfstab: warning: ...`) or, with `--log-format json`, as one JSON
+//! object per line:
+//!
+//! ```text
+//! {"level":"warning","event":"unknown-fstype","message":"...","mountpoint":"/data","fstype":"foofs"}
+//! ```
+//!
+//! `event` is a stable kebab-case name for the kind of diagnostic; the
+//! other fields give its context. Format and level are process-wide and set
+//! once by the binary ([`init`]); the library defaults to text at
+//! [`Level::Note`].
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How diagnostics are written (`--log-format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// `recfstab: warning: message` lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Severity of a diagnostic; each level includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The run failed (always shown)
+    Error,
+    /// Something in the output may be wrong
+    Warning,
+    /// Informational (hidden by `--quiet`)
+    Note,
+    /// Per-mount decisions such as skip reasons (shown with `-v`)
+    Debug,
+}
+
+impl Level {
+    /// Name used in both formats (`warning`, ...).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Note as u8);
+
+/// Set the format and the most verbose level shown for the rest of the
+/// process.
+pub fn init(format: LogFormat, max_level: Level) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+    MAX_LEVEL.store(max_level as u8, Ordering::Relaxed);
+}
+
+fn format() -> LogFormat {
+    if FORMAT.load(Ordering::Relaxed) == LogFormat::Json as u8 {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+/// Render one diagnostic as a line (without trailing newline).
+///
+/// Text errors have no level prefix (`recfstab: E008: ...`) and text
+/// output leaves out the context fields, which the message already names.
+pub fn render(
+    format: LogFormat,
+    level: Level,
+    event: &str,
+    message: &str,
+    fields: &[(&str, &str)],
+) -> String {
+    match (format, level) {
+        (LogFormat::Text, Level::Error) => format!("recfstab: {}", message),
+        (LogFormat::Text, _) => format!("recfstab: {}: {}", level.as_str(), message),
+        (LogFormat::Json, _) => {
+            let mut line = format!(
+                "{{\"level\":{},\"event\":{},\"message\":{}",
+                json_string(level.as_str()),
+                json_string(event),
+                json_string(message)
+            );
+            for (key, value) in fields {
+                let _ = write!(line, ",{}:{}", json_string(key), json_string(value));
+            }
+            line.push('}');
+            line
+        }
+    }
+}
+
+/// Quote and escape `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write a diagnostic to stderr if `level` is shown.
+pub fn log(level: Level, event: &str, message: &str, fields: &[(&str, &str)]) {
+    if level as u8 <= MAX_LEVEL.load(Ordering::Relaxed) {
+        eprintln!("{}", render(format(), level, event, message, fields));
+    }
+}
+
+/// Log at [`Level::Error`].
+pub fn error(event: &str, message: &str, fields: &[(&str, &str)]) {
+    log(Level::Error, event, message, fields);
+}
+
+/// Log at [`Level::Warning`].
+pub fn warning(event: &str, message: &str, fields: &[(&str, &str)]) {
+    log(Level::Warning, event, message, fields);
+}
+
+/// Log at [`Level::Note`].
+pub fn note(event: &str, message: &str, fields: &[(&str, &str)]) {
+    log(Level::Note, event, message, fields);
+}
+
+/// Log at [`Level::Debug`].
+pub fn debug(event: &str, message: &str, fields: &[(&str, &str)]) {
+    log(Level::Debug, event, message, fields);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let fields = [("mountpoint", "/data"), ("fstype", "foofs")];
+        assert_eq!(
+            render(
+                LogFormat::Text,
+                Level::Warning,
+                "unknown-fstype",
+                "unknown fstype",
+                &fields
+            ),
+            "recfstab: warning: unknown fstype"
+        );
+        assert_eq!(
+            render(LogFormat::Text, Level::Error, "error", "E008: oops", &[]),
+            "recfstab: E008: oops"
+        );
+        assert_eq!(
+            render(
+                LogFormat::Json,
+                Level::Warning,
+                "unknown-fstype",
+                "say \"hi\"\n",
+                &fields
+            ),
+            "{\"level\":\"warning\",\"event\":\"unknown-fstype\",\"message\":\"say \\\"hi\\\"\\n\",\
+             \"mountpoint\":\"/data\",\"fstype\":\"foofs\"}"
+        );
+        assert_eq!(json_string("a\\b\u{1}"), "\"a\\\\b\\u0001\"");
+    }
+}
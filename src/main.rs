@@ -1,8 +1,59 @@
 //! CLI entry point for recfstab.
 
-use clap::Parser;
-use recfstab::{run, IdType};
+use clap::{ArgGroup, Parser, ValueEnum};
+use recfstab::config::Profile;
+use recfstab::device::{FallbackFormat, IdFormat, NamingScheme};
+use recfstab::device_list::DeviceList;
+#[cfg(feature = "regex")]
+use recfstab::filter::OptionPattern;
+use recfstab::filter::{
+    CLUSTER_FILESYSTEMS, KNOWN_FILESYSTEMS, NETWORK_FILESYSTEMS, PSEUDO_FILESYSTEMS,
+    READ_ONLY_FILESYSTEMS, RUNTIME_OPTIONS,
+};
+use recfstab::fstab::{
+    MountpointCase, OutputEncoding, FSCK_FILESYSTEMS, GENFSTAB_FSCK_FILESYSTEMS,
+};
+use recfstab::generate::{DedupeBy, GroupBy};
+use recfstab::log::{self, Level, LogFormat};
+use recfstab::mount::{format_mount_table, FindmntOptions};
+use recfstab::swap::{SwapIdType, SwapMountpoint};
+use recfstab::{
+    diff_to_writer_with, dry_run_diff_to_writer_with, explain_entry_with, run_to_targets_with,
+    verify_options_to_writer_with, Config, FindmntProvider, IdType, MergePolicy, MountProvider,
+    OutputTargets, RecfstabError, WriteMode,
+};
+use std::path::PathBuf;
 use std::process::ExitCode;
+use std::time::Duration;
+
+/// Topics available through `--explain`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Topic {
+    /// When to use UUID, LABEL, PARTUUID, or PARTLABEL
+    IdTypes,
+    /// Which mounts and mount options are dropped
+    Options,
+    /// How the fsck pass number is chosen
+    Pass,
+}
+
+/// Identifier types allowed for `--removable-id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum RemovableId {
+    /// Filesystem LABEL
+    Label,
+    /// Partition LABEL (GPT PARTLABEL)
+    Partlabel,
+}
+
+impl From<RemovableId> for IdType {
+    fn from(id: RemovableId) -> Self {
+        match id {
+            RemovableId::Label => IdType::Label,
+            RemovableId::Partlabel => IdType::Partlabel,
+        }
+    }
+}
 
 /// Command-line arguments for recfstab.
 #[derive(Parser, Debug)]
@@ -14,9 +65,30 @@ use std::process::ExitCode;
     fstab entries with UUIDs (or LABELs/PARTUUIDs/PARTLABELs). Designed for system \
     installation workflows where you need to generate /etc/fstab for a newly installed system."
 )]
+#[command(group(
+    ArgGroup::new("outputs")
+        .multiple(true)
+        .args(["output", "dropin_dir", "crypttab_out"])
+))]
+#[command(group(
+    ArgGroup::new("strict_checks")
+        .multiple(true)
+        .args([
+            "check_mountpoints",
+            "max_line_length",
+            "no_defaults_fallback",
+            "label_collision_check"
+        ])
+))]
 struct Args {
     /// Root directory to scan for mounted filesystems (e.g., /mnt)
-    root: String,
+    #[arg(required_unless_present_any = ["explain", "list_mounts", "devices_from"])]
+    root: Option<String>,
+
+    /// Generate entries for the unmounted devices listed in FILE
+    /// (DEVICE MOUNTPOINT FSTYPE [OPTIONS] per line) instead of scanning ROOT
+    #[arg(long, value_name = "FILE", conflicts_with = "root")]
+    devices_from: Option<PathBuf>,
 
     /// Use filesystem LABEL instead of UUID for device identification
     #[arg(short = 'L', long, conflicts_with_all = ["partuuid", "partlabel"])]
@@ -29,10 +101,518 @@ struct Args {
     /// Use partition LABEL (PARTLABEL) instead of filesystem UUID
     #[arg(short = 't', long, conflicts_with_all = ["label", "partuuid"])]
     partlabel: bool,
+
+    /// Try these identifier types in order (e.g. uuid,partuuid,label), using
+    /// the first a device has; the device path is the last resort
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "LIST",
+        visible_alias = "partuuid-fallback-chain",
+        conflicts_with_all = ["label", "partuuid", "partlabel"]
+    )]
+    id_chain: Vec<IdType>,
+
+    /// Identify devices on removable media by this type instead
+    #[arg(long, value_enum, value_name = "TYPE")]
+    removable_id: Option<RemovableId>,
+
+    /// Identify swap entries by this type instead (device: the raw /dev path)
+    #[arg(long, value_enum, value_name = "TYPE")]
+    swap_id_type: Option<SwapIdType>,
+
+    /// Mountpoint field for swap entries [default: none for devices, the path for files]
+    #[arg(long, value_enum, value_name = "STYLE")]
+    swap_mountpoint: Option<SwapMountpoint>,
+
+    /// Warn, listing them, when more than N swap entries are generated
+    #[arg(long, value_name = "N")]
+    max_swaps: Option<usize>,
+
+    /// Write identifiers as tags (UUID=...) or /dev/disk/by-* paths
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    id_format: IdFormat,
+
+    /// How to write a device that has no identifier
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    device_path_fallback_format: FallbackFormat,
+
+    /// Exit with an error (E009) if any device has no identifier of the
+    /// requested type, instead of writing its device path
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    fail_on_fallback: Option<bool>,
+
+    /// Warn about mountpoints with no directory under TARGET_ROOT
+    #[arg(long, value_name = "TARGET_ROOT", conflicts_with = "stream")]
+    check_mountpoints: Option<PathBuf>,
+
+    /// Exit with an error (E012, E016, E018, E019) instead of warning about
+    /// missing mountpoints, overlong lines, entries with no options left or
+    /// shared LABELs
+    #[arg(long, requires = "strict_checks")]
+    strict: bool,
+
+    /// Warn, naming the mountpoint, when an entry's line is longer than N characters
+    #[arg(long, value_name = "N", visible_alias = "max-options-length")]
+    max_line_length: Option<usize>,
+
+    /// Write rw instead of defaults when every option is filtered out, and warn
+    #[arg(long)]
+    no_defaults_fallback: bool,
+
+    /// Warn when several devices resolve to the same LABEL
+    #[arg(
+        long,
+        visible_alias = "resolve-by-label-collision-check",
+        conflicts_with = "stream"
+    )]
+    label_collision_check: bool,
+
+    /// Exit with an error (E011) if no / entry is generated
+    #[arg(long, visible_alias = "root-mount-required")]
+    require_root: bool,
+
+    /// Start from a preset of options; explicit flags override it
+    #[arg(long, value_enum)]
+    profile: Option<Profile>,
+
+    /// Add nofail to devices on removable media
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    nofail_removable: Option<bool>,
+
+    /// Add _netdev to network filesystems
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    netdev: Option<bool>,
+
+    /// Add x-systemd.automount to network filesystems
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    automount_network: Option<bool>,
+
+    /// Leave swap out of the output
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    no_swap: Option<bool>,
+
+    /// Write entries without comment lines
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    no_comments: Option<bool>,
+
+    /// Read options from findmnt's OPTIONS (vfs) or FS-OPTIONS (fstab) column
+    #[arg(long, value_enum, value_name = "COLUMN", default_value_t)]
+    use_findmnt_options: FindmntOptions,
+
+    /// Have findmnt list only ROOT and its submounts (--submounts --target ROOT)
+    #[arg(long, visible_alias = "entries-from-findmnt-target")]
+    use_findmnt_scope: bool,
+
+    /// What makes two mounts duplicates: same target, or every field
+    #[arg(long, value_enum, value_name = "KEY", default_value_t)]
+    dedupe_by: DedupeBy,
+
+    /// Write entries as mounts are read instead of buffering them (bounded memory)
+    #[arg(long)]
+    stream: bool,
+
+    /// Compute fsck pass numbers exactly like Arch's genfstab
+    #[arg(long)]
+    genfstab_compat: bool,
+
+    /// Map swapfiles on btrfs subvolumes to where the subvolume is mounted under ROOT
+    #[arg(long, conflicts_with = "stream")]
+    resolve_relative_swapfile: bool,
+
+    /// Keep tmpfs mounts that set size= (assumed deliberately configured)
+    #[arg(long)]
+    keep_sized_tmpfs: bool,
+
+    /// Keep mounts of single files (e.g. a container's bind-mounted /etc/hostname)
+    #[arg(long)]
+    keep_file_binds: bool,
+
+    /// Skip the EFI System Partition (for BIOS/legacy boot targets)
+    #[arg(long)]
+    no_esp: bool,
+
+    /// Mount the root entry read-only (adds `ro` and a comment)
+    #[arg(long)]
+    root_ro: bool,
+
+    /// Use OPTIONS verbatim for the / entry instead of the mounted options
+    #[arg(long, value_name = "OPTIONS", value_parser = parse_root_options, conflicts_with = "root_ro")]
+    root_options: Option<String>,
+
+    /// Keep ro on read-only mounts instead of filtering it (they get fsck pass 0)
+    #[arg(long)]
+    keep_ro: bool,
+
+    /// Keep ro or rw, whichever the mount has, instead of filtering both
+    #[arg(long, visible_alias = "preserve-rw-ro")]
+    preserve_mount_state: bool,
+
+    /// If ROOT is an overlay, emit its lowerdir's backing filesystem as /
+    #[arg(long, conflicts_with = "stream")]
+    overlay_lower: bool,
+
+    /// Sort mount options alphabetically within each entry
+    #[arg(long)]
+    sort_options: bool,
+
+    /// Write generic options (nodev, nofail, ...) before fs-specific ones
+    #[arg(long)]
+    normalize_option_order: bool,
+
+    /// Drop mount options matching the regex PATTERN (e.g. '^x-systemd\.')
+    #[cfg(feature = "regex")]
+    #[arg(long, value_name = "PATTERN", visible_alias = "strip-options")]
+    strip_options_regex: Option<OptionPattern>,
+
+    /// Rebuild btrfs options from FSROOT (always subvol=, never subvolid=)
+    #[arg(long)]
+    prefer_fstab_options: bool,
+
+    /// Add commented-out entries for btrfs subvolumes that are not mounted
+    #[arg(long, conflicts_with = "stream")]
+    resolve_all_btrfs_subvols: bool,
+
+    /// Replace inline CIFS login options with credentials=FILE
+    #[arg(long, value_name = "FILE")]
+    cifs_credentials: Option<PathBuf>,
+
+    /// Keep NFS mounts on their negotiated vers= with a matching fstype
+    #[arg(long)]
+    pin_nfs_version: bool,
+
+    /// Add x-mount.mkdir so systemd creates missing mountpoints (not for / or swap)
+    #[arg(long, visible_alias = "emit-x-mount-mkdir")]
+    mkdir: bool,
+
+    /// Add x-initrd.mount to the entry for MOUNTPOINT (repeatable)
+    #[arg(long, value_name = "MOUNTPOINT")]
+    initrd_mount: Vec<String>,
+
+    /// Add x-initrd.mount to a separate /usr entry
+    #[arg(long)]
+    auto_initrd_usr: bool,
+
+    /// Add the blkid TYPE (and SEC_TYPE) to each entry's comment
+    #[arg(long)]
+    blkid_comment: bool,
+
+    /// Name the comment's device by the first scheme with a /dev/disk link
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_delimiter = ',',
+        visible_alias = "source-priority"
+    )]
+    comment_source_priority: Vec<NamingScheme>,
+
+    /// Comment which driver ambiguous fstypes (ntfs, ntfs3, exfat, ...) assume
+    #[arg(long)]
+    annotate_fstype_driver: bool,
+
+    /// Lowercase mountpoints, for targets whose directories were created lowercase
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CASE",
+        default_value_t,
+        visible_alias = "mountpoint-case-normalize"
+    )]
+    normalize_mountpoint_case: MountpointCase,
+
+    /// Rebase mountpoints (and swapfile paths) under PREFIX
+    #[arg(long, value_name = "PREFIX")]
+    target_prefix: Option<String>,
+
+    /// Use ROOT as given instead of resolving symlinks in it
+    #[arg(long)]
+    no_canonicalize: bool,
+
+    /// Don't warn about filesystem types recfstab does not recognize
+    #[arg(long)]
+    skip_fstype_warnings: bool,
+
+    /// Warn about entries with noauto, which will not be mounted at boot
+    #[arg(long, visible_alias = "warn-on-noauto")]
+    warn_noauto: bool,
+
+    /// End the output with a comment counting generated and skipped entries
+    #[arg(long, visible_alias = "output-comment-summary")]
+    summary_comment: bool,
+
+    /// Start with the "# <file system> <dir> ..." header of Arch's stock fstab
+    #[arg(long, visible_alias = "emit-fstab-version-header")]
+    template_header: bool,
+
+    /// Write paths as UTF-8, or octal-escape every non-ASCII byte (escape-all)
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t)]
+    output_encoding: OutputEncoding,
+
+    /// Re-parse each generated line and fail (E010) if it is malformed
+    #[arg(long)]
+    self_check: bool,
+
+    /// Probe devices instead of trusting blkid's cache (slower; avoids stale UUIDs)
+    #[arg(long)]
+    no_blkid_cache: bool,
+
+    /// Kill blkid after SECONDS and fall back to the device path
+    #[arg(long, value_name = "SECONDS")]
+    blkid_timeout: Option<u64>,
+
+    /// Drop repeated data lines from the final output and sort by mountpoint
+    #[arg(long, conflicts_with = "stream")]
+    output_sorted_unique: bool,
+
+    /// Gather entries under one comment header per device (MODE: device)
+    #[arg(long, value_name = "MODE", conflicts_with = "stream")]
+    group_by: Option<GroupBy>,
+
+    /// Wrap comments at word boundaries to keep each line within N columns
+    #[arg(long, value_name = "N", visible_alias = "comment-wrap")]
+    comment_width: Option<usize>,
+
+    /// Write entries to FILE instead of stdout
+    #[arg(short = 'o', long, visible_alias = "fstab-out", value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Write each entry to its own DIR/<escaped mountpoint>.fstab file
+    /// (e.g. /etc/fstab.d) instead of stdout
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "merge"])]
+    dropin_dir: Option<PathBuf>,
+
+    /// Ask whether to replace or append when the --output file already has
+    /// recfstab entries
+    #[arg(long, requires = "output")]
+    interactive: bool,
+
+    /// Also write crypttab lines for LUKS devices behind the entries to FILE
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    crypttab_out: Option<PathBuf>,
+
+    /// How --output/--crypttab-out write their files (atomic: temp file + rename)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        requires = "outputs"
+    )]
+    write_mode: WriteMode,
+
+    /// Replace the options of listed mountpoints with those in FILE
+    /// (one `MOUNTPOINT OPTIONS` pair per line)
+    #[arg(
+        long,
+        value_name = "FILE",
+        visible_alias = "mount-options-from-file",
+        conflicts_with = "stream"
+    )]
+    options_map: Option<PathBuf>,
+
+    /// Use FILE's fsck pass numbers (MOUNTPOINT PASS lines, 0-2) for listed mountpoints
+    #[arg(
+        long,
+        value_name = "FILE",
+        visible_alias = "fsck-pass-override",
+        conflicts_with = "stream"
+    )]
+    pass_map: Option<PathBuf>,
+
+    /// Print how the generated entries differ from the fstab at PATH
+    /// (+ added, - removed, ~ changed) instead of the entries
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["outputs", "merge"])]
+    diff: Option<PathBuf>,
+
+    /// Show what regenerating the fstab at PATH would change as unified-style
+    /// -/+ lines, with a count on stderr; nothing is written
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["outputs", "merge", "diff", "verify_options"]
+    )]
+    dry_run_diff: Option<PathBuf>,
+
+    /// Report entries whose options would not reproduce the running mount
+    /// (runtime-only options aside) instead of printing the entries
+    #[arg(
+        long,
+        visible_alias = "compare-with-mount",
+        conflicts_with_all = ["outputs", "merge", "diff"]
+    )]
+    verify_options: bool,
+
+    /// Merge generated entries into the existing fstab FILE and print the result
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    merge: Option<PathBuf>,
+
+    /// Which entry wins when --merge finds the same mountpoint in both
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        requires = "merge"
+    )]
+    merge_policy: MergePolicy,
+
+    /// Explain a topic using the rules built into this binary, then exit
+    #[arg(long, value_name = "TOPIC")]
+    explain: Option<Topic>,
+
+    /// Trace how the entry for MOUNTPOINT (e.g. /home) is built, then exit
+    #[arg(long, value_name = "MOUNTPOINT")]
+    explain_entry: Option<String>,
+
+    /// Print the mount table as parsed from findmnt, before filtering, then exit
+    #[arg(long)]
+    list_mounts: bool,
+
+    /// Also report per-mount decisions on stderr (skipped mounts, fallbacks)
+    #[arg(short = 'v', long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only report errors on stderr, not warnings or notes
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Write stderr diagnostics as text or as one JSON object per line
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    log_format: LogFormat,
+}
+
+/// Reject `--root-options` values that would not form a valid fstab field.
+fn parse_root_options(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        Err("must not be empty".to_string())
+    } else if value.chars().any(char::is_whitespace) {
+        Err("must not contain whitespace".to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Build the `--explain` text from the library's live constants.
+fn explain(topic: Topic) -> String {
+    let mut text = String::new();
+    match topic {
+        Topic::IdTypes => {
+            text.push_str("Identifier types (first fstab field):\n");
+            for id_type in IdType::ALL {
+                text.push_str(&format!(
+                    "  {:<10} {}\n",
+                    format!("{}=", id_type.fstab_prefix()),
+                    id_type.description()
+                ));
+            }
+            text.push_str(
+                "Sources that already use an identifier are kept as-is. When blkid \
+                 finds no value, the raw device path is used.\n",
+            );
+        }
+        Topic::Options => {
+            text.push_str("Filesystem types never written to fstab:\n");
+            text.push_str(&format!("  {}\n", PSEUDO_FILESYSTEMS.join(" ")));
+            text.push_str("Runtime-only mount options removed from each entry:\n");
+            text.push_str(&format!("  {}\n", RUNTIME_OPTIONS.join(" ")));
+            text.push_str(
+                "  subvolid=* (subvol= is kept instead, resolved from the id if missing)\n",
+            );
+            text.push_str("  ro is kept on bind mounts, and everywhere with --keep-ro\n");
+            text.push_str("  ro/rw: the one in effect is kept with --preserve-mount-state\n");
+            text.push_str("An entry left with no options uses \"defaults\".\n");
+        }
+        Topic::Pass => {
+            text.push_str("fsck pass number (sixth fstab field):\n");
+            text.push_str("  1  the root filesystem (/)\n");
+            text.push_str(&format!(
+                "  2  other mounts of: {}\n",
+                FSCK_FILESYSTEMS.join(" ")
+            ));
+            let no_fsck: Vec<&str> = KNOWN_FILESYSTEMS
+                .iter()
+                .copied()
+                .filter(|fstype| !FSCK_FILESYSTEMS.contains(fstype) && *fstype != "none")
+                .collect();
+            text.push_str(&format!(
+                "  0  every other fstype, e.g.: {}\n",
+                no_fsck.join(" ")
+            ));
+            text.push_str(&format!(
+                "  0  network filesystems: {}\n",
+                NETWORK_FILESYSTEMS.join(" ")
+            ));
+            text.push_str(
+                "  0  read-only (ro) mounts other than root, wherever ro is kept: bind mounts,\n     \
+                 --keep-ro, --preserve-mount-state, or ro set by --options-map\n",
+            );
+            text.push_str(&format!(
+                "  0  read-only images, even at root: {}\n",
+                READ_ONLY_FILESYSTEMS.join(" ")
+            ));
+            text.push_str(&format!(
+                "  0  cluster filesystems, even at root (they also get _netdev): {}\n",
+                CLUSTER_FILESYSTEMS.join(" ")
+            ));
+            text.push_str(&format!(
+                "With --genfstab-compat, only these types get 1 (root) or 2, and the ro,\n\
+                 image and cluster rules above do not apply: {}\n",
+                GENFSTAB_FSCK_FILESYSTEMS.join(" ")
+            ));
+        }
+    }
+    text
+}
+
+/// Log a failed run's error.
+fn report(e: &RecfstabError) {
+    log::error("error", &e.to_string(), &[("code", e.code.code())]);
 }
 
 fn main() -> ExitCode {
     let args = Args::parse();
+    log::init(
+        args.log_format,
+        if args.quiet {
+            Level::Error
+        } else if args.verbose {
+            Level::Debug
+        } else {
+            Level::Note
+        },
+    );
+
+    if let Some(topic) = args.explain {
+        print!("{}", explain(topic));
+        return ExitCode::SUCCESS;
+    }
+    if args.list_mounts {
+        let provider = FindmntProvider {
+            options: args.use_findmnt_options,
+            scope: args.root.clone().filter(|_| args.use_findmnt_scope),
+        };
+        return match provider.mounts() {
+            Ok(mounts) => {
+                print!("{}", format_mount_table(&mounts));
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                report(&e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    // clap guarantees ROOT is present unless --explain, --list-mounts or
+    // --devices-from was given
+    let root = args.root.unwrap_or_default();
+
+    // RECFSTAB_* variables set defaults, the profile overrides them, and
+    // flags given explicitly (even =false) win over both
+    let mut base = Config::from_env();
+    if let Some(profile) = args.profile {
+        profile.apply(&mut base);
+    }
 
     // Determine identifier type from flags
     let id_type = if args.label {
@@ -42,13 +622,176 @@ fn main() -> ExitCode {
     } else if args.partlabel {
         IdType::Partlabel
     } else {
-        IdType::Uuid
+        base.id_type
     };
+    let mut config = Config {
+        id_type,
+        id_chain: args.id_chain,
+        removable_id: args.removable_id.map(IdType::from),
+        swap_id_type: args.swap_id_type,
+        swap_mountpoint: args.swap_mountpoint,
+        max_swaps: args.max_swaps,
+        id_format: args.id_format,
+        fallback_format: args.device_path_fallback_format,
+        require_root: args.require_root,
+        check_mountpoints: args.check_mountpoints,
+        strict: args.strict,
+        max_line_length: args.max_line_length,
+        no_defaults_fallback: args.no_defaults_fallback,
+        label_collision_check: args.label_collision_check,
+        findmnt_options: args.use_findmnt_options,
+        findmnt_scope: args.use_findmnt_scope,
+        dedupe_by: args.dedupe_by,
+        streaming: args.stream,
+        genfstab_compat: args.genfstab_compat,
+        resolve_swapfile: args.resolve_relative_swapfile,
+        root_ro: args.root_ro,
+        root_options: args.root_options,
+        keep_ro: args.keep_ro,
+        preserve_mount_state: args.preserve_mount_state,
+        keep_sized_tmpfs: args.keep_sized_tmpfs,
+        keep_file_binds: args.keep_file_binds,
+        device_list: args.devices_from.is_some(),
+        no_esp: args.no_esp,
+        sort_options: args.sort_options,
+        normalize_option_order: args.normalize_option_order,
+        #[cfg(feature = "regex")]
+        strip_options: args.strip_options_regex,
+        prefer_fstab_options: args.prefer_fstab_options,
+        overlay_lower: args.overlay_lower,
+        btrfs_subvol_templates: args.resolve_all_btrfs_subvols,
+        pin_nfs_version: args.pin_nfs_version,
+        cifs_credentials: args.cifs_credentials,
+        mkdir: args.mkdir,
+        initrd_mount: args.initrd_mount,
+        auto_initrd_usr: args.auto_initrd_usr,
+        blkid_comment: args.blkid_comment,
+        comment_source_priority: args.comment_source_priority,
+        annotate_fstype_driver: args.annotate_fstype_driver,
+        mountpoint_case: args.normalize_mountpoint_case,
+        target_prefix: args.target_prefix,
+        no_canonicalize: args.no_canonicalize,
+        skip_fstype_warnings: args.skip_fstype_warnings,
+        warn_noauto: args.warn_noauto,
+        summary_comment: args.summary_comment,
+        template_header: args.template_header,
+        output_encoding: args.output_encoding,
+        self_check: args.self_check,
+        no_blkid_cache: args.no_blkid_cache,
+        blkid_timeout: args.blkid_timeout.map(Duration::from_secs),
+        sorted_unique: args.output_sorted_unique,
+        group_by: args.group_by,
+        comment_width: args.comment_width,
+        options_map: args.options_map,
+        pass_map: args.pass_map,
+        merge: args.merge,
+        merge_policy: args.merge_policy,
+        sys_root: None,
+        ..base
+    };
+    for (flag, field) in [
+        (args.fail_on_fallback, &mut config.fail_on_fallback),
+        (args.nofail_removable, &mut config.nofail_removable),
+        (args.netdev, &mut config.netdev),
+        (args.automount_network, &mut config.automount_network),
+        (args.no_swap, &mut config.no_swap),
+        (args.no_comments, &mut config.no_comments),
+    ] {
+        if let Some(value) = flag {
+            *field = value;
+        }
+    }
+
+    if config.no_canonicalize {
+        log::warning(
+            "no-canonicalize",
+            "--no-canonicalize: ROOT is not resolved, so mounts reached through a \
+             symlink in it may not match",
+            &[],
+        );
+    }
+
+    if config.no_blkid_cache {
+        log::warning(
+            "no-blkid-cache",
+            "--no-blkid-cache: probing every device directly, which is slower than \
+             reading blkid's cache",
+            &[],
+        );
+    }
 
-    match run(&args.root, id_type) {
+    let targets = OutputTargets {
+        fstab: args.output,
+        dropin_dir: args.dropin_dir,
+        crypttab: args.crypttab_out,
+        mode: args.write_mode,
+        interactive: args.interactive,
+    };
+
+    // A device list stands in for the mount table, with mountpoints under /
+    let (root, provider): (String, Box<dyn MountProvider>) = match &args.devices_from {
+        Some(path) => match DeviceList::read(path) {
+            Ok(list) => ("/".to_string(), Box::new(list)),
+            Err(e) => {
+                report(&e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => {
+            let provider = FindmntProvider {
+                options: config.findmnt_options,
+                scope: config.findmnt_scope.then(|| root.clone()),
+            };
+            (root, Box::new(provider))
+        }
+    };
+
+    if let Some(mountpoint) = &args.explain_entry {
+        let blkid = config.blkid();
+        return match explain_entry_with(
+            &root,
+            &config,
+            provider.as_ref(),
+            blkid.runner().as_ref(),
+            mountpoint,
+        ) {
+            Ok(text) => {
+                print!("{}", text);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                report(&e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let result = match (&args.diff, &args.dry_run_diff) {
+        (Some(old), _) => diff_to_writer_with(
+            &root,
+            &config,
+            provider.as_ref(),
+            old,
+            &mut std::io::stdout().lock(),
+        ),
+        (None, Some(old)) => dry_run_diff_to_writer_with(
+            &root,
+            &config,
+            provider.as_ref(),
+            old,
+            &mut std::io::stdout().lock(),
+        ),
+        (None, None) if args.verify_options => verify_options_to_writer_with(
+            &root,
+            &config,
+            provider.as_ref(),
+            &mut std::io::stdout().lock(),
+        ),
+        (None, None) => run_to_targets_with(&root, &config, provider.as_ref(), &targets),
+    };
+    match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            eprintln!("recfstab: {}", e);
+            report(&e);
             ExitCode::FAILURE
         }
     }
@@ -0,0 +1,128 @@
+//! Merging generated entries into an existing fstab (`--merge`).
+
+use crate::fstab::FstabEntry;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Which side wins when both fstabs have an entry for the same mountpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MergePolicy {
+    /// Replace the existing entry with the generated one (default)
+    #[default]
+    Generated,
+    /// Keep the existing entry and drop the generated one
+    Existing,
+}
+
+/// Key used to match entries between the two fstabs.
+///
+/// Swap entries all share the `none` mountpoint, so they are matched by
+/// fs_spec instead.
+pub(crate) fn merge_key(entry: &FstabEntry) -> &str {
+    if entry.fstype == "swap" {
+        &entry.fs_spec
+    } else {
+        &entry.mountpoint
+    }
+}
+
+/// Merge `generated` into `existing`, resolving collisions with `policy`.
+///
+/// Existing entries keep their order (a replaced entry stays in place);
+/// generated entries with no counterpart are appended in generation order.
+pub fn merge_entries(
+    existing: Vec<FstabEntry>,
+    generated: Vec<FstabEntry>,
+    policy: MergePolicy,
+) -> Vec<FstabEntry> {
+    let mut generated_by_key: HashMap<String, FstabEntry> = HashMap::new();
+    let mut order = Vec::new();
+    for entry in generated {
+        let key = merge_key(&entry).to_string();
+        if let Entry::Vacant(slot) = generated_by_key.entry(key.clone()) {
+            order.push(key);
+            slot.insert(entry);
+        }
+    }
+
+    let mut merged = Vec::with_capacity(existing.len() + order.len());
+    for entry in existing {
+        match generated_by_key.remove(merge_key(&entry)) {
+            Some(generated) if policy == MergePolicy::Generated => merged.push(generated),
+            _ => merged.push(entry),
+        }
+    }
+    merged.extend(
+        order
+            .into_iter()
+            .filter_map(|key| generated_by_key.remove(&key)),
+    );
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fs_spec: &str, mountpoint: &str, fstype: &str, options: &str) -> FstabEntry {
+        FstabEntry {
+            comments: Vec::new(),
+            fs_spec: fs_spec.to_string(),
+            mountpoint: mountpoint.to_string(),
+            fstype: fstype.to_string(),
+            options: options.to_string(),
+            dump: 0,
+            pass: 0,
+            mountpoint_bytes: None,
+            id_fallback: false,
+            device: None,
+        }
+    }
+
+    fn colliding_root() -> (Vec<FstabEntry>, Vec<FstabEntry>) {
+        let existing = vec![
+            entry("UUID=old", "/", "ext4", "noatime"),
+            entry("server:/srv", "/srv", "nfs", "defaults"),
+        ];
+        let generated = vec![
+            entry("UUID=new", "/", "ext4", "rw,relatime"),
+            entry("UUID=boot", "/boot", "vfat", "rw"),
+        ];
+        (existing, generated)
+    }
+
+    #[test]
+    fn test_merge_policy_generated_replaces_root() {
+        let (existing, generated) = colliding_root();
+        let merged = merge_entries(existing, generated, MergePolicy::Generated);
+
+        let mountpoints: Vec<_> = merged.iter().map(|e| e.mountpoint.as_str()).collect();
+        assert_eq!(mountpoints, ["/", "/srv", "/boot"]);
+        assert_eq!(merged[0].fs_spec, "UUID=new");
+        assert_eq!(merged[0].options, "rw,relatime");
+    }
+
+    #[test]
+    fn test_merge_policy_existing_keeps_root() {
+        let (existing, generated) = colliding_root();
+        let merged = merge_entries(existing, generated, MergePolicy::Existing);
+
+        let mountpoints: Vec<_> = merged.iter().map(|e| e.mountpoint.as_str()).collect();
+        assert_eq!(mountpoints, ["/", "/srv", "/boot"]);
+        assert_eq!(merged[0].fs_spec, "UUID=old");
+        assert_eq!(merged[0].options, "noatime");
+    }
+
+    #[test]
+    fn test_merge_swap_matched_by_spec() {
+        let existing = vec![entry("/swapfile", "none", "swap", "defaults")];
+        let generated = vec![
+            entry("/swapfile", "none", "swap", "pri=10"),
+            entry("UUID=swp", "none", "swap", "defaults"),
+        ];
+        let merged = merge_entries(existing, generated, MergePolicy::Generated);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].options, "pri=10");
+        assert_eq!(merged[1].fs_spec, "UUID=swp");
+    }
+}
@@ -1,42 +1,696 @@
 //! Mount point parsing from findmnt output.
+//!
+//! When findmnt cannot be run, the kernel's `/proc/self/mountinfo` is read
+//! instead (see [`parse_mountinfo`]). A findmnt too old for the PROPAGATION
+//! column is asked for the four positional columns only (see
+//! [`FindmntLayout`]).
 
+use crate::btrfs::normalize_subvol_path;
+use crate::command::{CommandRunner, SystemRunner};
+use crate::device::subvol_from_source;
 use crate::error::{RecfstabError, Result};
-use std::process::Command;
+use crate::filter::{is_valid_fstype, normalize_fuse_fstype, strip_fuse_subtype};
+use crate::fstab::unescape_fstab;
+use crate::log;
+use crate::swap::{read_swaps, SwapInfo};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Columns requested from findmnt, in output order.
+///
+/// OPTIONS stays last so that [`parse_findmnt_line`] can hand everything after
+/// the fifth separator to it.
+pub const FINDMNT_COLUMNS: &str = "TARGET,SOURCE,FSTYPE,FSROOT,PROPAGATION,OPTIONS";
+
+/// Like [`FINDMNT_COLUMNS`], but with only the filesystem-specific options.
+pub const FINDMNT_FS_COLUMNS: &str = "TARGET,SOURCE,FSTYPE,FSROOT,PROPAGATION,FS-OPTIONS";
+
+/// Which findmnt column the options field is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum FindmntOptions {
+    /// Every option in effect (`OPTIONS`), including the VFS flags the kernel
+    /// reports, which are then filtered (default)
+    #[default]
+    Vfs,
+    /// Only filesystem-specific options (`FS-OPTIONS`); per-mount flags such
+    /// as `nosuid` or `noexec` are not part of this column and are lost
+    Fstab,
+}
+
+impl FindmntOptions {
+    /// Column list to request from findmnt.
+    pub fn columns(self) -> &'static str {
+        match self {
+            FindmntOptions::Vfs => FINDMNT_COLUMNS,
+            FindmntOptions::Fstab => FINDMNT_FS_COLUMNS,
+        }
+    }
+}
+
+/// Columns requested from a findmnt older than
+/// [`FINDMNT_PROPAGATION_VERSION`], parsed with [`parse_mount_line`].
+pub const FINDMNT_POSITIONAL_COLUMNS: &str = "TARGET,SOURCE,FSTYPE,OPTIONS";
+
+/// Like [`FINDMNT_POSITIONAL_COLUMNS`], with only the filesystem-specific
+/// options.
+pub const FINDMNT_POSITIONAL_FS_COLUMNS: &str = "TARGET,SOURCE,FSTYPE,FS-OPTIONS";
+
+/// First util-linux version whose findmnt has the PROPAGATION column.
+pub const FINDMNT_PROPAGATION_VERSION: (u32, u32) = (2, 23);
+
+/// Column layout requested from findmnt, decided by its version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FindmntLayout {
+    /// [`FINDMNT_COLUMNS`] (or [`FINDMNT_FS_COLUMNS`]), with FSROOT and
+    /// PROPAGATION (default)
+    #[default]
+    Full,
+    /// [`FINDMNT_POSITIONAL_COLUMNS`] (or [`FINDMNT_POSITIONAL_FS_COLUMNS`]);
+    /// the filesystem root then comes from the `[/subvol]` source notation
+    /// and propagation is unknown
+    Positional,
+}
+
+impl FindmntLayout {
+    /// Layout for a findmnt of `version`. An unknown version (the probe
+    /// failed or its output was not understood) is assumed to be current.
+    pub fn for_version(version: Option<(u32, u32)>) -> Self {
+        match version {
+            Some(version) if version < FINDMNT_PROPAGATION_VERSION => FindmntLayout::Positional,
+            _ => FindmntLayout::Full,
+        }
+    }
+
+    /// Probe findmnt's version through `runner` and pick the layout, noting
+    /// on stderr when the positional fallback is used.
+    pub fn detect(runner: &dyn CommandRunner) -> Self {
+        let version = findmnt_version_with(runner);
+        let layout = Self::for_version(version);
+        if let (FindmntLayout::Positional, Some((major, minor))) = (layout, version) {
+            log::note(
+                "findmnt-positional",
+                &format!(
+                    "findmnt {}.{} has no PROPAGATION column (needs {}.{}); requesting {}",
+                    major,
+                    minor,
+                    FINDMNT_PROPAGATION_VERSION.0,
+                    FINDMNT_PROPAGATION_VERSION.1,
+                    FINDMNT_POSITIONAL_COLUMNS
+                ),
+                &[("version", &format!("{}.{}", major, minor))],
+            );
+        }
+        layout
+    }
+
+    /// Column list to request from findmnt.
+    pub fn columns(self, options: FindmntOptions) -> &'static str {
+        match (self, options) {
+            (FindmntLayout::Full, _) => options.columns(),
+            (FindmntLayout::Positional, FindmntOptions::Vfs) => FINDMNT_POSITIONAL_COLUMNS,
+            (FindmntLayout::Positional, FindmntOptions::Fstab) => FINDMNT_POSITIONAL_FS_COLUMNS,
+        }
+    }
+
+    /// Parse one raw output line in this layout.
+    fn parse_line(self, line: &[u8]) -> Option<MountInfo> {
+        match self {
+            FindmntLayout::Full => parse_findmnt_line_bytes(line),
+            FindmntLayout::Positional => parse_mount_line(&String::from_utf8_lossy(line)),
+        }
+    }
+}
+
+/// Parse `findmnt --version` output, e.g. `findmnt from util-linux 2.39.3`,
+/// into (major, minor).
+///
+/// Pre-release suffixes are ignored (`2.40-rc1` is 2.40). Returns None if
+/// no version number is found.
+pub fn parse_findmnt_version(output: &str) -> Option<(u32, u32)> {
+    let word = output
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))?;
+    let mut parts = word.split('.').map(|part| {
+        let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        part[..digits].parse::<u32>().ok()
+    });
+    Some((parts.next()??, parts.next()??))
+}
+
+/// Version of the installed findmnt, or None if it cannot be run or its
+/// `--version` output is not understood.
+pub fn findmnt_version() -> Option<(u32, u32)> {
+    findmnt_version_with(&SystemRunner)
+}
+
+/// Like [`findmnt_version`], running findmnt through `runner`.
+pub fn findmnt_version_with(runner: &dyn CommandRunner) -> Option<(u32, u32)> {
+    let output = runner.run("findmnt", &["--version"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_findmnt_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Kernel mount table read when findmnt is unavailable.
+pub const PROC_MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Arguments passed to findmnt: raw output, no header, exactly our columns,
+/// and with a `scope` only the filesystem at that path and its submounts.
+pub fn findmnt_args(
+    options: FindmntOptions,
+    layout: FindmntLayout,
+    scope: Option<&str>,
+) -> Vec<&str> {
+    let mut args = vec!["--raw", "--noheadings", "--output", layout.columns(options)];
+    if let Some(path) = scope {
+        args.extend(["--submounts", "--target", path]);
+    }
+    args
+}
+
+/// Propagation flags findmnt can report in the PROPAGATION column.
+const PROPAGATION_FLAGS: &[&str] = &["private", "shared", "slave", "unbindable"];
 
 /// Represents a single mount point from findmnt output.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct MountInfo {
     pub target: String,
     pub source: String,
     pub fstype: String,
     pub options: String,
+    /// Path inside the filesystem that is mounted (btrfs subvolume or bind
+    /// source); empty when unknown
+    pub fsroot: String,
+    /// Mount propagation flags (e.g. "shared", "private"); empty when unknown
+    pub propagation: String,
+    /// Exact target bytes when the path is not valid UTF-8 (`target` then
+    /// holds a lossy copy used for matching and display)
+    pub target_bytes: Option<Vec<u8>>,
+}
+
+impl MountInfo {
+    /// Filesystem root of this mount, from FSROOT or the `[/subvol]` source
+    /// notation, defaulting to "/".
+    pub fn fs_root(&self) -> &str {
+        if !self.fsroot.is_empty() {
+            &self.fsroot
+        } else {
+            subvol_from_source(&self.source).unwrap_or("/")
+        }
+    }
+
+    /// Whether this mount shows a subtree of its filesystem (a bind mount).
+    ///
+    /// findmnt does not report `bind` in the options, so this goes by
+    /// [`fs_root`](Self::fs_root): anything but "/" (or, on btrfs, the
+    /// subvolume named by `subvol=`) is a bind.
+    pub fn is_bind(&self) -> bool {
+        let root = self.fs_root();
+        if root == "/" {
+            return false;
+        }
+        if self.fstype == "btrfs" {
+            let subvol = self
+                .options
+                .split(',')
+                .find_map(|opt| opt.trim().strip_prefix("subvol="));
+            return subvol.map(normalize_subvol_path) != Some(normalize_subvol_path(root));
+        }
+        true
+    }
+
+    /// Give a bare `fuse` mount the subtype its source implies (see
+    /// [`normalize_fuse_fstype`]), dropping a legacy `type#` source prefix.
+    ///
+    /// The parsers apply this, so filtering and entry generation only ever
+    /// see the derived `fuse.<subtype>`.
+    pub fn with_fuse_subtype(mut self) -> Self {
+        let fstype = normalize_fuse_fstype(&self.fstype, &self.source);
+        if fstype != self.fstype {
+            self.source = strip_fuse_subtype(&self.source).to_string();
+            self.fstype = fstype;
+        }
+        self
+    }
+
+    /// Whether mounts here propagate to or from peers (shared or slave).
+    pub fn is_propagated(&self) -> bool {
+        self.propagation
+            .split(',')
+            .any(|flag| flag == "shared" || flag == "slave")
+    }
+}
+
+/// Source of the mount table (and active swaps) fed into entry generation.
+///
+/// The default [`FindmntProvider`] queries the running system. Library users
+/// can implement this to generate fstab from another source, e.g. a mount
+/// table captured on a remote machine.
+pub trait MountProvider {
+    /// Return all mounts, in mount order.
+    fn mounts(&self) -> Result<Vec<MountInfo>>;
+
+    /// Visit mounts one at a time, stopping at the first error from `f`.
+    ///
+    /// The default collects [`MountProvider::mounts`] first; providers that
+    /// can read incrementally should override this for streaming mode.
+    fn for_each_mount(&self, f: &mut dyn FnMut(MountInfo) -> Result<()>) -> Result<()> {
+        for mount in self.mounts()? {
+            f(mount)?;
+        }
+        Ok(())
+    }
+
+    /// Return active swap devices and files.
+    fn swaps(&self) -> Result<Vec<SwapInfo>> {
+        read_swaps()
+    }
+}
+
+/// Reads the live mount table with findmnt and swaps from /proc/swaps.
+///
+/// findmnt's version is probed first to pick the [`FindmntLayout`].
+#[derive(Debug, Clone, Default)]
+pub struct FindmntProvider {
+    /// Column the mount options are read from
+    pub options: FindmntOptions,
+    /// Have findmnt list only the filesystem at this path and its
+    /// submounts (`--use-findmnt-scope`) instead of the whole table
+    pub scope: Option<String>,
+}
+
+impl MountProvider for FindmntProvider {
+    fn mounts(&self) -> Result<Vec<MountInfo>> {
+        let layout = FindmntLayout::detect(&SystemRunner);
+        get_mounts_layout_in(
+            &SystemRunner,
+            self.options,
+            layout,
+            self.scope.as_deref(),
+            Path::new(PROC_MOUNTINFO),
+        )
+    }
+
+    fn for_each_mount(&self, f: &mut dyn FnMut(MountInfo) -> Result<()>) -> Result<()> {
+        for_each_mount_scoped(self.options, self.scope.as_deref(), f)
+    }
+}
+
+/// Fixed mount table, mainly for tests. Reports no active swaps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticProvider(pub Vec<MountInfo>);
+
+impl MountProvider for StaticProvider {
+    fn mounts(&self) -> Result<Vec<MountInfo>> {
+        Ok(self.0.clone())
+    }
+
+    fn swaps(&self) -> Result<Vec<SwapInfo>> {
+        Ok(Vec::new())
+    }
 }
 
 /// Get all current mounts from the system using findmnt.
 pub fn get_mounts() -> Result<Vec<MountInfo>> {
-    let output = Command::new("findmnt")
-        .args(["-rn", "-o", "TARGET,SOURCE,FSTYPE,OPTIONS"])
-        .output()
-        .map_err(RecfstabError::findmnt_not_found)?;
+    FindmntProvider::default().mounts()
+}
+
+/// Like [`get_mounts`], but runs findmnt through `runner` and reads options
+/// from the column selected by `options`. The version is not probed; the
+/// [`FindmntLayout::Full`] columns are requested.
+pub fn get_mounts_with(
+    runner: &dyn CommandRunner,
+    options: FindmntOptions,
+) -> Result<Vec<MountInfo>> {
+    get_mounts_in(runner, options, Path::new(PROC_MOUNTINFO))
+}
+
+/// Like [`get_mounts_with`], reading `mountinfo` instead of
+/// [`PROC_MOUNTINFO`] if findmnt cannot be run.
+///
+/// Fails with E014 only when both findmnt and `mountinfo` are unavailable;
+/// findmnt running and failing is still E005.
+pub fn get_mounts_in(
+    runner: &dyn CommandRunner,
+    options: FindmntOptions,
+    mountinfo: &Path,
+) -> Result<Vec<MountInfo>> {
+    get_mounts_layout_in(runner, options, FindmntLayout::Full, None, mountinfo)
+}
+
+/// Like [`get_mounts_in`], requesting and parsing the columns of `layout`,
+/// and with a `scope` only the subtree findmnt reports for that path (see
+/// [`findmnt_args`]).
+///
+/// The `mountinfo` fallback is never scoped; callers filter by root anyway.
+pub fn get_mounts_layout_in(
+    runner: &dyn CommandRunner,
+    options: FindmntOptions,
+    layout: FindmntLayout,
+    scope: Option<&str>,
+    mountinfo: &Path,
+) -> Result<Vec<MountInfo>> {
+    let output = match runner.run("findmnt", &findmnt_args(options, layout, scope)) {
+        Ok(output) => output,
+        Err(e) => return read_mountinfo_fallback(mountinfo, options, e),
+    };
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(RecfstabError::findmnt_failed(&stderr));
     }
 
-    let mounts_str = String::from_utf8_lossy(&output.stdout);
+    Ok(parse_mounts_output_layout(&output.stdout, layout))
+}
+
+/// Read `mountinfo` because findmnt could not be started (`findmnt_err`).
+fn read_mountinfo_fallback(
+    mountinfo: &Path,
+    options: FindmntOptions,
+    findmnt_err: io::Error,
+) -> Result<Vec<MountInfo>> {
+    match fs::read(mountinfo) {
+        Ok(content) => {
+            log::note(
+                "mountinfo-fallback",
+                &format!(
+                    "findmnt unavailable ({}); reading {}",
+                    findmnt_err,
+                    mountinfo.display()
+                ),
+                &[("path", &mountinfo.display().to_string())],
+            );
+            Ok(parse_mountinfo(&String::from_utf8_lossy(&content), options))
+        }
+        Err(e) => Err(RecfstabError::no_mount_source(&findmnt_err, mountinfo, &e)),
+    }
+}
+
+/// Parse `/proc/self/mountinfo` into mounts, like findmnt would report them.
+///
+/// Lines look like
+/// `36 35 98:0 /@home /mnt/home rw,noatime shared:1 - btrfs /dev/sda2 rw,subvol=/@home`:
+/// FSROOT and TARGET come before the optional fields, FSTYPE, SOURCE and the
+/// superblock options after the `-`. As with findmnt, the options are the
+/// per-mount flags followed by the superblock options ([`FindmntOptions::Vfs`])
+/// or the superblock options alone ([`FindmntOptions::Fstab`]). Paths are
+/// decoded as text, so non-UTF-8 targets are not kept byte-exact.
+pub fn parse_mountinfo(content: &str, options: FindmntOptions) -> Vec<MountInfo> {
+    content
+        .lines()
+        .filter_map(|line| parse_mountinfo_line(line, options))
+        .collect()
+}
+
+/// Parse one `/proc/self/mountinfo` line (see [`parse_mountinfo`]).
+pub fn parse_mountinfo_line(line: &str, options: FindmntOptions) -> Option<MountInfo> {
+    let (mount_part, fs_part) = line.split_once(" - ")?;
+    let fields: Vec<&str> = mount_part.split(' ').collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    let fs_fields: Vec<&str> = fs_part.splitn(3, ' ').collect();
+    if fs_fields.len() < 3 {
+        return None;
+    }
+
+    let target = unescape_fstab(fields[4]);
+    let fstype = fs_fields[0].to_string();
+    if !target.starts_with('/') || !is_valid_fstype(&fstype) {
+        return None;
+    }
+
+    let propagation: Vec<&str> = fields[6..]
+        .iter()
+        .filter_map(|tag| match tag.split(':').next() {
+            Some("shared") => Some("shared"),
+            Some("master") => Some("slave"),
+            Some("unbindable") => Some("unbindable"),
+            _ => None,
+        })
+        .collect();
+    let super_options = fs_fields[2].trim();
+    let options = match options {
+        FindmntOptions::Fstab => super_options.to_string(),
+        FindmntOptions::Vfs => {
+            let mut merged: Vec<&str> = fields[5].split(',').collect();
+            for option in super_options.split(',') {
+                if !merged.contains(&option) && !matches!(option, "rw" | "ro") {
+                    merged.push(option);
+                }
+            }
+            merged.join(",")
+        }
+    };
+
+    Some(
+        MountInfo {
+            target,
+            source: unescape_fstab(fs_fields[1]),
+            fstype,
+            options: normalize_options(&options),
+            fsroot: unescape_fstab(fields[3]),
+            propagation: if propagation.is_empty() {
+                "private".to_string()
+            } else {
+                propagation.join(",")
+            },
+            target_bytes: None,
+        }
+        .with_fuse_subtype(),
+    )
+}
+
+/// Format mounts as an aligned TARGET/SOURCE/FSTYPE/OPTIONS table
+/// (`--list-mounts`).
+///
+/// Every mount is listed as parsed, before any filtering, so pseudo
+/// filesystems and mounts outside the root show up too.
+pub fn format_mount_table(mounts: &[MountInfo]) -> String {
+    let header = ["TARGET", "SOURCE", "FSTYPE", "OPTIONS"];
+    let rows: Vec<[&str; 4]> = mounts
+        .iter()
+        .map(|m| [&m.target[..], &m.source, &m.fstype, &m.options])
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, field) in widths.iter_mut().zip(row) {
+            *width = (*width).max(field.len());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        let line = format!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Parse complete findmnt output, one mount per line.
+///
+/// A header line (if findmnt printed one despite `--noheadings`) is skipped
+/// and malformed lines are dropped. Warns on stderr when findmnt produced
+/// output but no line parsed, which usually means an unexpected column
+/// layout (e.g. a wrapper script or alias adding columns).
+pub fn parse_mounts_output(output: &str) -> Vec<MountInfo> {
+    parse_mounts_output_bytes(output.as_bytes())
+}
+
+/// Like [`parse_mounts_output`], for raw findmnt output that may contain
+/// non-UTF-8 paths (see [`parse_findmnt_line_bytes`]).
+pub fn parse_mounts_output_bytes(output: &[u8]) -> Vec<MountInfo> {
+    parse_mounts_output_layout(output, FindmntLayout::Full)
+}
+
+/// Like [`parse_mounts_output_bytes`], for output in the columns of
+/// `layout`.
+pub fn parse_mounts_output_layout(output: &[u8], layout: FindmntLayout) -> Vec<MountInfo> {
+    let mut lines = 0;
     let mut mounts = Vec::new();
 
-    for line in mounts_str.lines() {
-        if let Some(mount) = parse_mount_line(line) {
+    for (index, line) in output.split(|&b| b == b'\n').enumerate() {
+        let text = String::from_utf8_lossy(line);
+        if text.trim().is_empty() || is_header_line(&text) {
+            continue;
+        }
+        lines += 1;
+        warn_lossy_line(index + 1, line);
+        if let Some(mount) = layout.parse_line(line) {
             mounts.push(mount);
         }
     }
 
-    Ok(mounts)
+    warn_if_unparsed(lines, mounts.len(), layout);
+    mounts
+}
+
+/// Check whether a findmnt output line is the column header.
+fn is_header_line(line: &str) -> bool {
+    let line = line.trim_end();
+    [
+        FINDMNT_COLUMNS,
+        FINDMNT_FS_COLUMNS,
+        FINDMNT_POSITIONAL_COLUMNS,
+        FINDMNT_POSITIONAL_FS_COLUMNS,
+    ]
+    .iter()
+    .any(|columns| line == columns.replace(',', " "))
+}
+
+/// Warn when a findmnt line has invalid UTF-8 that parsing will replace.
+///
+/// Only the TARGET column is kept byte-exact (see
+/// [`parse_findmnt_line_bytes`]); invalid bytes anywhere else become U+FFFD
+/// in the parsed mount, so its SOURCE or OPTIONS no longer match the system.
+/// `number` is the 1-based line number in findmnt's output.
+pub fn lossy_line_warning(number: usize, line: &[u8]) -> Option<String> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let rest = match line.iter().position(|&b| b == b' ') {
+        Some(end) => &line[end..],
+        None => return None,
+    };
+    if std::str::from_utf8(rest).is_ok() {
+        return None;
+    }
+    Some(format!(
+        "findmnt output line {} has invalid UTF-8 outside TARGET, replaced with \
+         U+FFFD; the entry for it may be wrong: {}",
+        number,
+        String::from_utf8_lossy(line)
+    ))
+}
+
+fn warn_lossy_line(number: usize, line: &[u8]) {
+    if let Some(warning) = lossy_line_warning(number, line) {
+        log::warning(
+            "findmnt-lossy-line",
+            &warning,
+            &[("line", &number.to_string())],
+        );
+    }
+}
+
+fn warn_if_unparsed(lines: usize, parsed: usize, layout: FindmntLayout) {
+    if lines > 0 && parsed == 0 {
+        log::warning(
+            "findmnt-unparsed",
+            &format!(
+                "none of {} findmnt output lines could be parsed (expected columns {})",
+                lines,
+                layout.columns(FindmntOptions::Vfs)
+            ),
+            &[],
+        );
+    }
+}
+
+/// Stream current mounts from findmnt, calling `f` for each parsed line.
+///
+/// Unlike [`get_mounts`], the mount table is never collected in memory: each
+/// line is parsed and handed to `f` as soon as findmnt writes it. An error
+/// returned by `f` stops the scan and is propagated.
+pub fn for_each_mount<F>(f: F) -> Result<()>
+where
+    F: FnMut(MountInfo) -> Result<()>,
+{
+    for_each_mount_with(FindmntOptions::default(), f)
+}
+
+/// Like [`for_each_mount`], reading options from the column selected by
+/// `options`. findmnt's version is probed first (see [`FindmntLayout`]).
+pub fn for_each_mount_with<F>(options: FindmntOptions, f: F) -> Result<()>
+where
+    F: FnMut(MountInfo) -> Result<()>,
+{
+    for_each_mount_scoped(options, None, f)
+}
+
+/// Like [`for_each_mount_with`], with findmnt limited to `scope` and its
+/// submounts when set (see [`findmnt_args`]).
+pub fn for_each_mount_scoped<F>(
+    options: FindmntOptions,
+    scope: Option<&str>,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(MountInfo) -> Result<()>,
+{
+    let layout = FindmntLayout::detect(&SystemRunner);
+    let spawned = Command::new("findmnt")
+        .args(findmnt_args(options, layout, scope))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            let mounts = read_mountinfo_fallback(Path::new(PROC_MOUNTINFO), options, e)?;
+            return mounts.into_iter().try_for_each(f);
+        }
+    };
+
+    let mut lines = 0;
+    let mut parsed = 0;
+    let mut number = 0;
+    if let Some(stdout) = child.stdout.take() {
+        let mut reader = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Err(RecfstabError::findmnt_failed(&e.to_string())),
+            }
+            number += 1;
+            let line = String::from_utf8_lossy(&buf);
+            if line.trim().is_empty() || is_header_line(&line) {
+                continue;
+            }
+            lines += 1;
+            warn_lossy_line(number, &buf);
+            if let Some(mount) = layout.parse_line(&buf) {
+                parsed += 1;
+                if let Err(e) = f(mount) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    let status = child
+        .wait()
+        .map_err(|e| RecfstabError::findmnt_failed(&e.to_string()))?;
+    if !status.success() {
+        return Err(RecfstabError::findmnt_failed(&stderr));
+    }
+
+    warn_if_unparsed(lines, parsed, layout);
+    Ok(())
 }
 
-/// Parse a single line of findmnt output into a MountInfo struct.
+/// Parse a line of `TARGET,SOURCE,FSTYPE,OPTIONS` findmnt output.
 ///
 /// Returns None if the line is malformed or has empty required fields.
 pub fn parse_mount_line(line: &str) -> Option<MountInfo> {
@@ -52,21 +706,117 @@ pub fn parse_mount_line(line: &str) -> Option<MountInfo> {
     }
 
     let target = unescape_findmnt(parts[0]);
-    let source = unescape_findmnt(parts[1]);
-    let fstype = parts[2].to_string();
-    let options = parts[3].to_string();
+    let source = unescape_findmnt(parts[1]).trim().to_string();
+    let fstype = parts[2].trim().to_string();
+    let options = normalize_options(parts[3]);
 
     // Validate required fields are not empty after unescaping
-    if target.is_empty() || fstype.is_empty() {
+    if target.is_empty() || !is_valid_fstype(&fstype) {
+        return None;
+    }
+
+    Some(
+        MountInfo {
+            target,
+            source,
+            fstype,
+            options,
+            ..MountInfo::default()
+        }
+        .with_fuse_subtype(),
+    )
+}
+
+/// Parse a line of findmnt output using [`FINDMNT_COLUMNS`].
+///
+/// Empty columns (e.g. no PROPAGATION) appear as empty fields between single
+/// spaces, so only the line terminator is trimmed. Lines whose TARGET or
+/// FSROOT is not an absolute path, or whose PROPAGATION is not a set of
+/// propagation flags, come from a different column layout and are rejected.
+pub fn parse_findmnt_line(line: &str) -> Option<MountInfo> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let parts: Vec<&str> = line.splitn(6, ' ').collect();
+    if parts.len() < 6 {
         return None;
     }
 
-    Some(MountInfo {
-        target,
-        source,
-        fstype,
-        options,
-    })
+    let target = unescape_findmnt(parts[0]);
+    let fstype = parts[2].trim().to_string();
+    if !target.starts_with('/') || !is_valid_fstype(&fstype) {
+        return None;
+    }
+    if !(parts[3].is_empty() || parts[3].starts_with('/')) {
+        return None;
+    }
+    if !parts[4].is_empty()
+        && !parts[4]
+            .split(',')
+            .all(|flag| PROPAGATION_FLAGS.contains(&flag))
+    {
+        return None;
+    }
+
+    Some(
+        MountInfo {
+            target,
+            source: unescape_findmnt(parts[1]).trim().to_string(),
+            fstype,
+            fsroot: unescape_findmnt(parts[3]),
+            propagation: parts[4].to_string(),
+            options: normalize_options(parts[5]),
+            target_bytes: None,
+        }
+        .with_fuse_subtype(),
+    )
+}
+
+/// Parse a raw findmnt line, keeping a non-UTF-8 target byte-exact.
+///
+/// Fields are parsed as in [`parse_findmnt_line`] from a lossy copy; if the
+/// TARGET column (raw or `\xNN`-escaped) decodes to invalid UTF-8, the exact
+/// bytes are kept in [`MountInfo::target_bytes`].
+pub fn parse_findmnt_line_bytes(line: &[u8]) -> Option<MountInfo> {
+    let mut mount = parse_findmnt_line(&String::from_utf8_lossy(line))?;
+    let raw_target = line.split(|&b| b == b' ').next().unwrap_or_default();
+    let target = unescape_findmnt_bytes(raw_target);
+    if std::str::from_utf8(&target).is_err() {
+        mount.target = String::from_utf8_lossy(&target).into_owned();
+        mount.target_bytes = Some(target);
+    }
+    Some(mount)
+}
+
+/// Byte-oriented [`unescape_findmnt`]: decode every `\xNN` escape, so bytes
+/// findmnt escaped as non-printable come back verbatim.
+pub fn unescape_findmnt_bytes(field: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        if field[i] == b'\\' && field.get(i + 1) == Some(&b'x') {
+            let hex = field
+                .get(i + 2..i + 4)
+                .and_then(|h| std::str::from_utf8(h).ok());
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(field[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Trim an options field, treating one made only of whitespace and commas
+/// as empty (rendered as "defaults" downstream).
+fn normalize_options(options: &str) -> String {
+    let options = options.trim();
+    if options.chars().all(|c| c == ',' || c.is_whitespace()) {
+        String::new()
+    } else {
+        options.to_string()
+    }
 }
 
 /// Unescape special characters in findmnt -r output.
@@ -85,6 +835,240 @@ pub fn unescape_findmnt(s: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::command::MockRunner;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn test_get_mounts_with_fs_options_column() {
+        let runner = MockRunner::new(
+            0,
+            "TARGET SOURCE FSTYPE FSROOT PROPAGATION FS-OPTIONS\n\
+             /mnt /dev/sda2 btrfs /@ shared compress=zstd:3,subvol=/@\n",
+        );
+        let mounts = get_mounts_with(&runner, FindmntOptions::Fstab).unwrap();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].options, "compress=zstd:3,subvol=/@");
+        assert_eq!(
+            runner.calls(),
+            [format!(
+                "findmnt --raw --noheadings --output {}",
+                FINDMNT_FS_COLUMNS
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_findmnt_version() {
+        assert_eq!(
+            parse_findmnt_version("findmnt from util-linux 2.39.3\n"),
+            Some((2, 39))
+        );
+        assert_eq!(
+            parse_findmnt_version("findmnt from util-linux 2.20.1\n"),
+            Some((2, 20))
+        );
+        assert_eq!(
+            parse_findmnt_version("findmnt from util-linux 2.40-rc1\n"),
+            Some((2, 40))
+        );
+        assert_eq!(
+            parse_findmnt_version(
+                "findmnt from util-linux 2.41 (libmount 2.41.0: selinux, smack, btrfs, verity)\n"
+            ),
+            Some((2, 41))
+        );
+        assert_eq!(parse_findmnt_version("findmnt from util-linux\n"), None);
+        assert_eq!(parse_findmnt_version(""), None);
+    }
+
+    #[test]
+    fn test_findmnt_layout_for_version() {
+        assert_eq!(
+            FindmntLayout::for_version(Some((2, 39))),
+            FindmntLayout::Full
+        );
+        assert_eq!(
+            FindmntLayout::for_version(Some((2, 23))),
+            FindmntLayout::Full
+        );
+        assert_eq!(
+            FindmntLayout::for_version(Some((2, 20))),
+            FindmntLayout::Positional
+        );
+        assert_eq!(FindmntLayout::for_version(None), FindmntLayout::Full);
+
+        let runner = MockRunner::new(0, "findmnt from util-linux 2.20.1\n");
+        assert_eq!(FindmntLayout::detect(&runner), FindmntLayout::Positional);
+        assert_eq!(runner.calls(), ["findmnt --version"]);
+        assert_eq!(
+            FindmntLayout::detect(&MockRunner::missing()),
+            FindmntLayout::Full
+        );
+    }
+
+    #[test]
+    fn test_get_mounts_positional_layout() {
+        let runner = MockRunner::new(
+            0,
+            "TARGET SOURCE FSTYPE OPTIONS\n\
+             /mnt /dev/sda2[/@] btrfs rw,relatime,subvol=/@\n\
+             /mnt/my\\x20data /dev/sda3 xfs rw,inode64\n",
+        );
+        let mounts = get_mounts_layout_in(
+            &runner,
+            FindmntOptions::Vfs,
+            FindmntLayout::Positional,
+            None,
+            Path::new(PROC_MOUNTINFO),
+        )
+        .unwrap();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].fs_root(), "/@");
+        assert_eq!(mounts[0].propagation, "");
+        assert_eq!(mounts[1].target, "/mnt/my data");
+        assert_eq!(mounts[1].options, "rw,inode64");
+        assert_eq!(
+            runner.calls(),
+            [format!(
+                "findmnt --raw --noheadings --output {}",
+                FINDMNT_POSITIONAL_COLUMNS
+            )]
+        );
+    }
+
+    #[test]
+    fn test_get_mounts_scoped_to_target() {
+        assert_eq!(
+            findmnt_args(FindmntOptions::Vfs, FindmntLayout::Full, Some("/mnt")),
+            [
+                "--raw",
+                "--noheadings",
+                "--output",
+                FINDMNT_COLUMNS,
+                "--submounts",
+                "--target",
+                "/mnt"
+            ]
+        );
+
+        // findmnt does the scoping: only /mnt and below come back
+        let runner = MockRunner::new(
+            0,
+            "/mnt /dev/sda2 ext4 / shared rw,relatime\n\
+             /mnt/boot /dev/sda1 vfat / shared rw\n",
+        );
+        let mounts = get_mounts_layout_in(
+            &runner,
+            FindmntOptions::Vfs,
+            FindmntLayout::Full,
+            Some("/mnt"),
+            Path::new(PROC_MOUNTINFO),
+        )
+        .unwrap();
+        let targets: Vec<_> = mounts.iter().map(|m| m.target.as_str()).collect();
+        assert_eq!(targets, ["/mnt", "/mnt/boot"]);
+        assert_eq!(
+            runner.calls(),
+            [format!(
+                "findmnt --raw --noheadings --output {} --submounts --target /mnt",
+                FINDMNT_COLUMNS
+            )]
+        );
+    }
+
+    #[test]
+    fn test_format_mount_table_lists_every_mount() {
+        let runner = MockRunner::new(
+            0,
+            "/ /dev/sda2 ext4 / shared rw,relatime\n\
+             /proc proc proc / shared rw,nosuid,nodev,noexec\n\
+             /sys sysfs sysfs / shared rw,nosuid\n\
+             /mnt/boot /dev/sda1 vfat / shared rw,fmask=0022\n",
+        );
+        let table = format_mount_table(&get_mounts_with(&runner, FindmntOptions::Vfs).unwrap());
+        let lines: Vec<_> = table.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("TARGET"));
+        assert_eq!(
+            lines[2],
+            "/proc      proc       proc    rw,nosuid,nodev,noexec"
+        );
+        for target in ["/", "/proc", "/sys", "/mnt/boot"] {
+            assert!(lines.iter().any(|l| l.starts_with(&format!("{} ", target))));
+        }
+    }
+
+    #[test]
+    fn test_get_mounts_with_mock_runner() {
+        let runner = MockRunner::new(
+            0,
+            "/mnt /dev/sda2 ext4 / shared rw,relatime\n/mnt/boot /dev/sda1 vfat / shared rw\n",
+        );
+        let mounts = get_mounts_with(&runner, FindmntOptions::Vfs).unwrap();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[1].target, "/mnt/boot");
+        assert_eq!(
+            runner.calls(),
+            [format!(
+                "findmnt --raw --noheadings --output {}",
+                FINDMNT_COLUMNS
+            )]
+        );
+
+        let err = get_mounts_with(&MockRunner::new(1, ""), FindmntOptions::Vfs).unwrap_err();
+        assert_eq!(err.code, ErrorCode::FindmntFailed);
+    }
+
+    #[test]
+    fn test_get_mounts_falls_back_to_mountinfo() {
+        let dir = std::env::temp_dir().join("recfstab_test_mountinfo");
+        let _ = std::fs::create_dir_all(&dir);
+        let mountinfo = dir.join("mountinfo");
+        std::fs::write(
+            &mountinfo,
+            "22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw,errors=remount-ro\n\
+             23 22 0:5 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw\n\
+             40 22 8:3 /@home /home rw,noatime master:3 - btrfs /dev/sda3 rw,compress=zstd:3,subvol=/@home\n\
+             41 22 8:4 / /mnt/my\\040data rw,relatime - xfs /dev/sda4 rw,inode64\n",
+        )
+        .unwrap();
+
+        let mounts =
+            get_mounts_in(&MockRunner::missing(), FindmntOptions::Vfs, &mountinfo).unwrap();
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[0].options, "rw,relatime,errors=remount-ro");
+        assert_eq!(mounts[0].propagation, "shared");
+        assert_eq!(mounts[2].fsroot, "/@home");
+        assert_eq!(mounts[2].propagation, "slave");
+        assert_eq!(
+            mounts[2].options,
+            "rw,noatime,compress=zstd:3,subvol=/@home"
+        );
+        assert_eq!(mounts[3].target, "/mnt/my data");
+        assert_eq!(mounts[3].propagation, "private");
+
+        let mounts =
+            get_mounts_in(&MockRunner::missing(), FindmntOptions::Fstab, &mountinfo).unwrap();
+        assert_eq!(mounts[3].options, "rw,inode64");
+
+        // findmnt missing and no readable mount table: a distinct error
+        let err = get_mounts_in(
+            &MockRunner::missing(),
+            FindmntOptions::Vfs,
+            &dir.join("missing"),
+        )
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::NoMountSource);
+        assert!(err.message.contains("missing"), "{}", err);
+
+        // findmnt that runs and fails is not masked by the fallback
+        let err =
+            get_mounts_in(&MockRunner::new(1, ""), FindmntOptions::Vfs, &mountinfo).unwrap_err();
+        assert_eq!(err.code, ErrorCode::FindmntFailed);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 
     #[test]
     fn test_unescape_findmnt() {
@@ -105,6 +1089,19 @@ mod tests {
         assert_eq!(unescape_findmnt("/mnt/normal"), "/mnt/normal");
     }
 
+    #[test]
+    fn test_parse_findmnt_line_derives_fuse_subtype() {
+        let mount = parse_findmnt_line("/mnt/remote sshfs#user@host:/srv fuse / private rw,nosuid")
+            .unwrap();
+        assert_eq!(mount.fstype, "fuse.sshfs");
+        assert_eq!(mount.source, "user@host:/srv");
+        assert!(crate::filter::is_network_filesystem(&mount.fstype));
+
+        let mount = parse_findmnt_line("/run/portal portal fuse / private rw").unwrap();
+        assert_eq!(mount.fstype, "fuse.portal");
+        assert!(crate::filter::is_pseudo_filesystem(&mount.fstype));
+    }
+
     #[test]
     fn test_parse_mount_line() {
         let line = "/mnt /dev/sda1 ext4 rw,relatime";
@@ -177,9 +1174,173 @@ mod tests {
         assert_eq!(mount.options, "rw,user_xattr,some option with spaces");
     }
 
+    #[test]
+    fn test_parse_findmnt_line() {
+        let mount = parse_findmnt_line(
+            "/mnt/home /dev/sda2[/@home] btrfs /@home shared rw,subvol=/@home\n",
+        )
+        .unwrap();
+        assert_eq!(mount.target, "/mnt/home");
+        assert_eq!(mount.source, "/dev/sda2[/@home]");
+        assert_eq!(mount.fsroot, "/@home");
+        assert_eq!(mount.propagation, "shared");
+        assert_eq!(mount.options, "rw,subvol=/@home");
+        assert!(mount.is_propagated());
+
+        // Empty PROPAGATION column
+        let mount = parse_findmnt_line("/mnt /dev/sda1 ext4 /  rw").unwrap();
+        assert_eq!(mount.propagation, "");
+        assert_eq!(mount.options, "rw");
+        assert!(!mount.is_propagated());
+
+        // Legacy 4-column output is rejected
+        assert!(parse_findmnt_line("/mnt /dev/sda1 ext4 rw").is_none());
+    }
+
+    #[test]
+    fn test_mount_info_fs_root() {
+        let mut mount = parse_mount_line("/mnt /dev/sda1[/@] btrfs rw").unwrap();
+        assert_eq!(mount.fs_root(), "/@");
+        mount.fsroot = "/@root".to_string();
+        assert_eq!(mount.fs_root(), "/@root");
+        let plain = parse_mount_line("/mnt /dev/sda1 ext4 rw").unwrap();
+        assert_eq!(plain.fs_root(), "/");
+    }
+
+    #[test]
+    fn test_mount_info_is_bind() {
+        let plain = parse_mount_line("/mnt /dev/sda1 ext4 rw").unwrap();
+        assert!(!plain.is_bind());
+        let bind = MountInfo {
+            fsroot: "/srv/www".to_string(),
+            ..plain
+        };
+        assert!(bind.is_bind());
+
+        let subvol = parse_mount_line("/mnt /dev/sda2[/@home] btrfs rw,subvol=@home").unwrap();
+        assert!(!subvol.is_bind());
+        let subvol_bind = MountInfo {
+            fsroot: "/@home/alice".to_string(),
+            ..subvol
+        };
+        assert!(subvol_bind.is_bind());
+    }
+
+    #[test]
+    fn test_static_provider() {
+        let mount = parse_mount_line("/mnt /dev/sda1 ext4 rw").unwrap();
+        let provider = StaticProvider(vec![mount.clone()]);
+        assert_eq!(provider.mounts().unwrap(), vec![mount.clone()]);
+        assert!(provider.swaps().unwrap().is_empty());
+
+        let mut visited = Vec::new();
+        provider
+            .for_each_mount(&mut |m| {
+                visited.push(m);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(visited, vec![mount]);
+    }
+
     #[test]
     fn test_unescape_findmnt_newline() {
         // Newline escaping
         assert_eq!(unescape_findmnt("/mnt/new\\x0aline"), "/mnt/new\nline");
     }
+
+    #[test]
+    fn test_parse_mount_line_whitespace_only_options() {
+        let info = parse_mount_line("/mnt /dev/sda1 ext4 , ,\t,").unwrap();
+        assert_eq!(info.options, "");
+        assert_eq!(crate::filter::filter_options(&info.options), "defaults");
+
+        let info = parse_findmnt_line("/mnt /dev/sda1 ext4 / shared  ,, ").unwrap();
+        assert_eq!(info.options, "");
+    }
+
+    #[test]
+    fn test_parse_mount_line_padded_fields() {
+        let info = parse_mount_line("/mnt \t/dev/sda1 \text4\t rw,relatime").unwrap();
+        assert_eq!(info.source, "/dev/sda1");
+        assert_eq!(info.fstype, "ext4");
+
+        let info = parse_findmnt_line("/mnt /dev/sda1\t ext4\t / shared rw").unwrap();
+        assert_eq!(info.source, "/dev/sda1");
+        assert_eq!(info.fstype, "ext4");
+
+        // A padded-but-blank fstype is still rejected
+        assert!(parse_mount_line("/mnt /dev/sda1 \t rw").is_none());
+    }
+
+    #[test]
+    fn test_parse_mounts_output_skips_header() {
+        let output = "TARGET SOURCE FSTYPE FSROOT PROPAGATION OPTIONS\n\
+                      /mnt /dev/sda2 ext4 / shared rw\n\
+                      \n\
+                      /mnt/boot /dev/sda1 vfat / shared rw\n";
+        let mounts = parse_mounts_output(output);
+        let targets: Vec<_> = mounts.iter().map(|m| m.target.as_str()).collect();
+        assert_eq!(targets, ["/mnt", "/mnt/boot"]);
+    }
+
+    #[test]
+    fn test_parse_mounts_output_other_column_layout() {
+        // `findmnt --df` style columns: SOURCE FSTYPE SIZE USED AVAIL USE% TARGET
+        let df =
+            "/dev/sda2 ext4 50G 10G 40G 20% /mnt\n/dev/sda1 vfat 511M 60M 451M 12% /mnt/boot\n";
+        assert!(parse_mounts_output(df).is_empty());
+
+        // Six columns, but not ours
+        let other = "/mnt /dev/sda2 ext4 50G 10G 40G\n";
+        assert!(parse_mounts_output(other).is_empty());
+
+        // Good lines survive alongside garbage
+        let mixed = "garbage\n/mnt /dev/sda2 ext4 / private rw\n";
+        assert_eq!(parse_mounts_output(mixed).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_findmnt_line_bytes_non_utf8_target() {
+        // Escaped by findmnt
+        let mount = parse_findmnt_line_bytes(b"/mnt/caf\\xe9 /dev/sdb1 ext4 / private rw").unwrap();
+        assert_eq!(mount.target_bytes.as_deref(), Some(&b"/mnt/caf\xe9"[..]));
+        assert_eq!(mount.target, "/mnt/caf\u{fffd}");
+
+        // Raw invalid byte in the stream
+        let mount = parse_findmnt_line_bytes(b"/mnt/d\xffx /dev/sdb1 ext4 / private rw").unwrap();
+        assert_eq!(mount.target_bytes.as_deref(), Some(&b"/mnt/d\xffx"[..]));
+        assert_eq!(mount.source, "/dev/sdb1");
+
+        // Valid UTF-8 (even escaped) keeps the plain string path
+        let mount =
+            parse_findmnt_line_bytes(b"/mnt/my\\x20disk /dev/sdb1 ext4 / private rw").unwrap();
+        assert_eq!(mount.target, "/mnt/my disk");
+        assert!(mount.target_bytes.is_none());
+    }
+
+    #[test]
+    fn test_lossy_line_warning() {
+        let output = b"/mnt /dev/sda2 ext4 / private rw\n\
+                       /mnt/data /dev/sdb\xff1 ext4 / private rw\n";
+        let lines: Vec<&[u8]> = output.split(|&b| b == b'\n').collect();
+        assert_eq!(lossy_line_warning(1, lines[0]), None);
+        let warning = lossy_line_warning(2, lines[1]).unwrap();
+        assert!(warning.starts_with("findmnt output line 2 "), "{}", warning);
+        assert!(warning.ends_with("/mnt/data /dev/sdb\u{fffd}1 ext4 / private rw"));
+
+        // A non-UTF-8 target is kept byte-exact, so it needs no warning
+        assert_eq!(
+            lossy_line_warning(1, b"/mnt/d\xffx /dev/sdb1 ext4 / private rw"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unescape_findmnt_bytes() {
+        assert_eq!(unescape_findmnt_bytes(b"/a\\xff\\x20b"), b"/a\xff b");
+        assert_eq!(unescape_findmnt_bytes(b"/a\\x5cx20"), b"/a\\x20");
+        assert_eq!(unescape_findmnt_bytes(b"/a\\x"), b"/a\\x");
+        assert_eq!(unescape_findmnt_bytes(b"/a\\xzz"), b"/a\\xzz");
+    }
 }
@@ -0,0 +1,84 @@
+//! NFS protocol version pinning (`--pin-nfs-version`).
+//!
+//! The kernel reports the negotiated protocol version as `vers=` in the mount
+//! options (e.g. `vers=4.2`). It survives option filtering, so the generated
+//! entry already asks for the same version; pinning additionally makes the
+//! fstype agree with it (`nfs4` for 4.x, `nfs` for 2 and 3).
+
+/// Check whether a filesystem type is NFS.
+pub fn is_nfs(fstype: &str) -> bool {
+    matches!(fstype, "nfs" | "nfs4")
+}
+
+/// The NFS version from a `vers=` or `nfsvers=` option, if present.
+pub fn nfs_version(options: &str) -> Option<&str> {
+    options.split(',').find_map(|opt| {
+        let opt = opt.trim();
+        opt.strip_prefix("vers=")
+            .or_else(|| opt.strip_prefix("nfsvers="))
+    })
+}
+
+/// Filesystem type matching an NFS version: `nfs4` for 4.x, `nfs` otherwise.
+pub fn fstype_for_version(version: &str) -> &'static str {
+    if version.split('.').next() == Some("4") {
+        "nfs4"
+    } else {
+        "nfs"
+    }
+}
+
+/// Make an NFS entry's fstype and options agree on one protocol version.
+///
+/// With a `vers=` option the fstype follows it. Without one, an `nfs4` mount
+/// is pinned to `vers=4`; a plain `nfs` mount is left unpinned and `None` is
+/// returned in place of the options so the caller can warn.
+pub fn pin_nfs_version(fstype: &str, options: &str) -> (String, Option<String>) {
+    match nfs_version(options) {
+        Some(version) => (
+            fstype_for_version(version).to_string(),
+            Some(options.to_string()),
+        ),
+        None if fstype == "nfs4" => (
+            fstype.to_string(),
+            Some(crate::filter::append_option(options, "vers=4")),
+        ),
+        None => (fstype.to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::filter_options;
+
+    #[test]
+    fn test_filter_options_keeps_vers() {
+        let options = filter_options("rw,relatime,vers=4.2,rsize=1048576,hard,proto=tcp");
+        assert_eq!(options, "vers=4.2,rsize=1048576,hard,proto=tcp");
+        assert_eq!(nfs_version(&options), Some("4.2"));
+    }
+
+    #[test]
+    fn test_pin_nfs_version_consistent_fstype() {
+        let (fstype, options) = pin_nfs_version("nfs", "vers=4.2,hard");
+        assert_eq!(fstype, "nfs4");
+        assert_eq!(options.as_deref(), Some("vers=4.2,hard"));
+
+        let (fstype, _) = pin_nfs_version("nfs4", "nfsvers=3");
+        assert_eq!(fstype, "nfs");
+
+        let (fstype, options) = pin_nfs_version("nfs4", "hard");
+        assert_eq!(fstype, "nfs4");
+        assert_eq!(options.as_deref(), Some("hard,vers=4"));
+
+        assert_eq!(pin_nfs_version("nfs", "hard").1, None);
+    }
+
+    #[test]
+    fn test_fstype_for_version() {
+        assert_eq!(fstype_for_version("4.2"), "nfs4");
+        assert_eq!(fstype_for_version("4"), "nfs4");
+        assert_eq!(fstype_for_version("3"), "nfs");
+    }
+}
@@ -0,0 +1,74 @@
+//! Per-mountpoint option overrides (`--options-map`).
+//!
+//! The map file has one `MOUNTPOINT OPTIONS` pair per line, e.g.
+//! `/home noatime,compress=zstd:3`. Blank lines and `#` comments are
+//! ignored. The mountpoint uses fstab escaping (`\040` for a space); the
+//! options are the rest of the line and replace the filtered set verbatim.
+
+use crate::fstab::{escape_fstab, read_only_pass, unescape_fstab, FstabEntry};
+use std::collections::HashMap;
+
+/// Parse an options map file into mountpoint -> options.
+///
+/// Lines without options are skipped. A later line for the same mountpoint
+/// wins. Whitespace inside the options is escaped so the field stays one
+/// fstab column.
+pub fn parse_options_map(content: &str) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((mountpoint, options)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let options = options.trim();
+        if options.is_empty() {
+            continue;
+        }
+        map.insert(unescape_fstab(mountpoint), escape_fstab(options));
+    }
+    map
+}
+
+/// Replace the options of every entry whose mountpoint is in `map`.
+///
+/// Unless `genfstab_compat` is set, the pass is re-checked against the new
+/// options with [`read_only_pass`].
+pub fn apply_options_map(
+    entries: &mut [FstabEntry],
+    map: &HashMap<String, String>,
+    genfstab_compat: bool,
+) {
+    for entry in entries {
+        if let Some(options) = map.get(&entry.mountpoint) {
+            entry.options = options.clone();
+            if !genfstab_compat {
+                entry.pass = read_only_pass(entry.pass, options);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options_map() {
+        let map = parse_options_map(
+            "# overrides\n\n/home\tnoatime,compress=zstd:3\n/mnt/my\\040disk  ro\n/empty\n/home defaults\n",
+        );
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["/home"], "defaults");
+        assert_eq!(map["/mnt/my disk"], "ro");
+        assert!(!map.contains_key("/empty"));
+    }
+
+    #[test]
+    fn test_parse_options_map_escapes_whitespace() {
+        let map = parse_options_map("/data  x-systemd.description=data disk\n");
+        assert_eq!(map["/data"], "x-systemd.description=data\\040disk");
+    }
+}
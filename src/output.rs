@@ -0,0 +1,510 @@
+//! Writing generated entries to a file (`--output`) or a directory of
+//! drop-ins (`--dropin-dir`).
+//!
+//! stdout stays the default; these helpers exist so installers can write the
+//! target fstab without a shell redirect, and safely.
+
+use crate::error::{RecfstabError, Result};
+use crate::fstab::FstabEntry;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How `--output` writes to an existing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WriteMode {
+    /// Append to the file, like `>>` (default)
+    #[default]
+    Append,
+    /// Replace the file contents, like `>`
+    Truncate,
+    /// Append via a temp file that is renamed into place on success, so the
+    /// file is never left half-written
+    Atomic,
+}
+
+/// First line of the block recfstab writes to an `--output` fstab.
+pub const MARKER_BEGIN: &str = "# BEGIN recfstab generated entries";
+
+/// Last line of the block recfstab writes to an `--output` fstab.
+pub const MARKER_END: &str = "# END recfstab generated entries";
+
+/// Wrap rendered entries in the BEGIN and END marker lines.
+///
+/// Both markers are written together, only around a block that rendered in
+/// full, so a file never holds a BEGIN without its END.
+pub fn marked_block(entries: &[u8]) -> Vec<u8> {
+    let mut block = format!("{}\n", MARKER_BEGIN).into_bytes();
+    block.extend_from_slice(entries);
+    block.extend_from_slice(format!("{}\n", MARKER_END).as_bytes());
+    block
+}
+
+/// Check whether `content` holds a complete recfstab marker block.
+pub fn has_marked_block(content: &str) -> bool {
+    marked_block_range(content).is_some()
+}
+
+/// Byte range of the first marker block, from the BEGIN line through the
+/// END line's newline.
+///
+/// A BEGIN followed by another BEGIN before any END is unpaired (left by an
+/// interrupted write) and is not part of the block, so the content after it
+/// is never swallowed.
+fn marked_block_range(content: &str) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed == MARKER_BEGIN {
+            start = Some(offset);
+        } else if trimmed == MARKER_END {
+            if let Some(start) = start {
+                return Some((start, offset + line.len()));
+            }
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Replace the first marker block in `content` with `block`.
+///
+/// `block` should include its own markers. Content without a block gets
+/// `block` appended.
+pub fn replace_marked_block(content: &str, block: &str) -> String {
+    match marked_block_range(content) {
+        Some((start, end)) => format!("{}{}{}", &content[..start], block, &content[end..]),
+        None => format!("{}{}", content, block),
+    }
+}
+
+/// What to do with an `--output` file that already has a recfstab block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingBlockAction {
+    /// Swap the old block for the new entries
+    Replace,
+    /// Add the new entries after the existing content (non-interactive behavior)
+    Append,
+}
+
+/// Ask whether to replace or append to an existing recfstab block.
+///
+/// The question goes to `prompt` and the answer is read from `input`.
+/// Empty input or end of input keeps the non-interactive behavior (append);
+/// anything unrecognized asks again.
+pub fn prompt_existing_block<R, W>(
+    path: &Path,
+    input: &mut R,
+    prompt: &mut W,
+) -> io::Result<ExistingBlockAction>
+where
+    R: BufRead + ?Sized,
+    W: Write + ?Sized,
+{
+    loop {
+        write!(
+            prompt,
+            "recfstab: {} already contains recfstab entries. [r]eplace them or [a]ppend? [a] ",
+            path.display()
+        )?;
+        prompt.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(ExistingBlockAction::Append);
+        }
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "r" | "replace" => return Ok(ExistingBlockAction::Replace),
+            "" | "a" | "append" => return Ok(ExistingBlockAction::Append),
+            _ => {}
+        }
+    }
+}
+
+/// Where each generated artifact goes.
+///
+/// fstab goes to stdout when neither `fstab` nor `dropin_dir` is set;
+/// crypttab is only generated when `crypttab` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputTargets {
+    /// File receiving fstab entries (`--output`/`--fstab-out`)
+    pub fstab: Option<PathBuf>,
+    /// Directory receiving one file per fstab entry (`--dropin-dir`); takes
+    /// precedence over `fstab`
+    pub dropin_dir: Option<PathBuf>,
+    /// File receiving crypttab entries (`--crypttab-out`)
+    pub crypttab: Option<PathBuf>,
+    /// How each file is written
+    pub mode: WriteMode,
+    /// Ask before appending to an fstab file that already has a recfstab block
+    pub interactive: bool,
+}
+
+/// A file written to a temporary path and renamed over the target on commit.
+///
+/// Dropping without [`AtomicFile::commit`] removes the temp file and leaves
+/// the target untouched.
+pub struct AtomicFile {
+    file: Option<BufWriter<File>>,
+    temp_path: PathBuf,
+    target: PathBuf,
+    /// Set once the rename succeeded; until then Drop removes the temp file
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Create a temp file next to `target` (same directory, so rename is atomic).
+    ///
+    /// Permissions of an existing target are copied to the temp file.
+    pub fn create(target: &Path) -> io::Result<Self> {
+        let dir = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = target
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+        let temp_path = dir.join(format!(
+            ".{}.recfstab-{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(target) {
+            let _ = file.set_permissions(metadata.permissions());
+        }
+
+        Ok(Self {
+            file: Some(BufWriter::new(file)),
+            temp_path,
+            target: target.to_path_buf(),
+            committed: false,
+        })
+    }
+
+    /// Flush, fsync, and rename the temp file over the target.
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(writer) = self.file.take() {
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.target)?;
+        self.committed = true;
+        // Persist the rename itself; not all filesystems support dir fsync
+        if let Some(parent) = self.target.parent() {
+            if let Ok(dir) = File::open(parent) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file.as_mut() {
+            Some(file) => file.write(buf),
+            None => Err(io::Error::other("atomic file already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Atomically replace `path` with `contents`.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut file = AtomicFile::create(path).map_err(|e| RecfstabError::output_failed(path, e))?;
+    file.write_all(contents.as_bytes())
+        .map_err(|e| RecfstabError::output_failed(path, e))?;
+    file.commit()
+        .map_err(|e| RecfstabError::output_failed(path, e))
+}
+
+/// Escape `path` as `systemd-escape --path` does: `/var/lib/my disk`
+/// becomes `var-lib-my\x20disk` and `/` becomes `-`.
+///
+/// Slashes separate the components with `-`; bytes other than ASCII
+/// letters, digits, `:`, `_` and `.` (and a leading `.`) become `\xNN`.
+pub fn systemd_escape_path(path: &str) -> String {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    if components.is_empty() {
+        return "-".to_string();
+    }
+    let mut escaped = String::new();
+    for (index, byte) in components.join("/").bytes().enumerate() {
+        match byte {
+            b'/' => escaped.push('-'),
+            b'.' if index == 0 => escaped.push_str("\\x2e"),
+            b if b.is_ascii_alphanumeric() || matches!(b, b':' | b'_' | b'.') => {
+                escaped.push(b as char)
+            }
+            b => {
+                let _ = write!(escaped, "\\x{:02x}", b);
+            }
+        }
+    }
+    escaped
+}
+
+/// File name for `entry` in a `--dropin-dir`: the escaped mountpoint (or
+/// device, for a swap) plus `.fstab`, which libmount reads from
+/// `/etc/fstab.d`. E.g. `home.fstab`, or `-.fstab` for `/`.
+pub fn dropin_file_name(entry: &FstabEntry) -> String {
+    let name = if entry.mountpoint.starts_with('/') {
+        &entry.mountpoint
+    } else {
+        &entry.fs_spec
+    };
+    format!("{}.fstab", systemd_escape_path(name))
+}
+
+/// Write each entry to its own [`dropin_file_name`] file in `dir`, creating
+/// the directory if needed.
+///
+/// Each file is replaced atomically; files for entries no longer generated
+/// are left alone. Returns the paths written, in entry order.
+pub fn write_dropins(dir: &Path, entries: &[FstabEntry]) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).map_err(|e| RecfstabError::output_failed(dir, e))?;
+    let mut written = Vec::new();
+    for entry in entries {
+        let path = dir.join(dropin_file_name(entry));
+        write_atomic(&path, &entry.render())?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// An open `--output` destination.
+pub enum OutputFile {
+    /// Appending or truncating writes go straight to the file
+    Direct(BufWriter<File>),
+    /// Writes go to a temp file until [`OutputFile::finish`]
+    Atomic(AtomicFile),
+}
+
+impl OutputFile {
+    /// Open `path` for writing generated entries with the given mode.
+    ///
+    /// In [`WriteMode::Atomic`], existing contents are copied into the temp
+    /// file first so the generated entries are appended.
+    pub fn open(path: &Path, mode: WriteMode) -> Result<Self> {
+        let fail = |e| RecfstabError::output_failed(path, e);
+        match mode {
+            WriteMode::Append | WriteMode::Truncate => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(mode == WriteMode::Append)
+                    .truncate(mode == WriteMode::Truncate)
+                    .open(path)
+                    .map_err(fail)?;
+                Ok(OutputFile::Direct(BufWriter::new(file)))
+            }
+            WriteMode::Atomic => {
+                let mut file = AtomicFile::create(path).map_err(fail)?;
+                match fs::read(path) {
+                    Ok(existing) => file.write_all(&existing).map_err(fail)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(fail(e)),
+                }
+                Ok(OutputFile::Atomic(file))
+            }
+        }
+    }
+
+    /// Flush buffered output and, in atomic mode, move it into place.
+    pub fn finish(self, path: &Path) -> Result<()> {
+        let result = match self {
+            OutputFile::Direct(mut writer) => writer.flush(),
+            OutputFile::Atomic(file) => file.commit(),
+        };
+        result.map_err(|e| RecfstabError::output_failed(path, e))
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Direct(writer) => writer.write(buf),
+            OutputFile::Atomic(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Direct(writer) => writer.flush(),
+            OutputFile::Atomic(file) => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_systemd_escape_path() {
+        assert_eq!(systemd_escape_path("/"), "-");
+        assert_eq!(systemd_escape_path("/home"), "home");
+        assert_eq!(systemd_escape_path("//var/lib/"), "var-lib");
+        assert_eq!(systemd_escape_path("/srv/my data"), "srv-my\\x20data");
+        assert_eq!(systemd_escape_path("/mnt/a-b"), "mnt-a\\x2db");
+        assert_eq!(systemd_escape_path("/.snapshots"), "\\x2esnapshots");
+        assert_eq!(systemd_escape_path("/dev/sda3"), "dev-sda3");
+    }
+
+    #[test]
+    fn test_atomic_file_untouched_without_commit() {
+        let dir = test_dir("recfstab_test_atomic_abort");
+        let target = dir.join("fstab");
+        fs::write(&target, "old\n").unwrap();
+
+        {
+            let mut file = AtomicFile::create(&target).unwrap();
+            file.write_all(b"new\n").unwrap();
+            // Simulated failure: dropped before the rename
+        }
+
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old\n");
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            1,
+            "temp file left behind"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_atomic_file_failed_rename_removes_temp() {
+        let dir = test_dir("recfstab_test_atomic_failed_rename");
+        // A non-empty directory cannot be replaced by a file
+        let target = dir.join("fstab");
+        fs::create_dir_all(target.join("inside")).unwrap();
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"new\n").unwrap();
+        assert!(file.commit().is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let dir = test_dir("recfstab_test_atomic_commit");
+        let target = dir.join("fstab");
+        fs::write(&target, "old\n").unwrap();
+
+        write_atomic(&target, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    const EXISTING: &str = "# static\nUUID=a / ext4 defaults 0 1\n\
+                            # BEGIN recfstab generated entries\nold\n\
+                            # END recfstab generated entries\n# trailer\n";
+
+    #[test]
+    fn test_replace_marked_block() {
+        assert!(has_marked_block(EXISTING));
+        assert!(!has_marked_block(
+            "# BEGIN recfstab generated entries\nno end\n"
+        ));
+
+        let block = format!("{}\nnew\n{}\n", MARKER_BEGIN, MARKER_END);
+        assert_eq!(
+            replace_marked_block(EXISTING, &block),
+            format!("# static\nUUID=a / ext4 defaults 0 1\n{}# trailer\n", block)
+        );
+        assert_eq!(replace_marked_block("x\n", &block), format!("x\n{}", block));
+    }
+
+    #[test]
+    fn test_marked_block_skips_unpaired_begin() {
+        let block = marked_block(b"new\n");
+        assert_eq!(
+            String::from_utf8_lossy(&block),
+            format!("{}\nnew\n{}\n", MARKER_BEGIN, MARKER_END)
+        );
+
+        let damaged = format!("{}\n# user line\n{}", MARKER_BEGIN, EXISTING);
+        let block = String::from_utf8_lossy(&block);
+        assert_eq!(
+            replace_marked_block(&damaged, &block),
+            format!(
+                "{}\n# user line\n# static\nUUID=a / ext4 defaults 0 1\n{}# trailer\n",
+                MARKER_BEGIN, block
+            )
+        );
+    }
+
+    #[test]
+    fn test_prompt_existing_block() {
+        let path = Path::new("/mnt/etc/fstab");
+        let mut prompt = Vec::new();
+        let action =
+            prompt_existing_block(path, &mut io::Cursor::new("maybe\nr\n"), &mut prompt).unwrap();
+        assert_eq!(action, ExistingBlockAction::Replace);
+        // The unrecognized answer was asked again
+        let shown = String::from_utf8(prompt).unwrap();
+        assert_eq!(shown.matches("[r]eplace").count(), 2);
+
+        for answer in ["\n", "append\n", ""] {
+            let action =
+                prompt_existing_block(path, &mut io::Cursor::new(answer), &mut Vec::new()).unwrap();
+            assert_eq!(action, ExistingBlockAction::Append, "answer {:?}", answer);
+        }
+    }
+
+    #[test]
+    fn test_output_file_modes() {
+        let dir = test_dir("recfstab_test_output_modes");
+        let target = dir.join("fstab");
+
+        for (mode, expected) in [
+            (WriteMode::Append, "old\nentry\n"),
+            (WriteMode::Truncate, "entry\n"),
+            (WriteMode::Atomic, "old\nentry\n"),
+        ] {
+            fs::write(&target, "old\n").unwrap();
+            let mut out = OutputFile::open(&target, mode).unwrap();
+            out.write_all(b"entry\n").unwrap();
+            out.finish(&target).unwrap();
+            assert_eq!(fs::read_to_string(&target).unwrap(), expected, "{:?}", mode);
+        }
+
+        // Atomic mode creates a missing file
+        let fresh = dir.join("new-fstab");
+        let mut out = OutputFile::open(&fresh, WriteMode::Atomic).unwrap();
+        out.write_all(b"entry\n").unwrap();
+        out.finish(&fresh).unwrap();
+        assert_eq!(fs::read_to_string(&fresh).unwrap(), "entry\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
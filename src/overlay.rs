@@ -0,0 +1,111 @@
+//! Overlay root handling (`--overlay-lower`).
+//!
+//! Some immutable distros boot with `/` as an overlay whose lower layer is a
+//! directory on a real filesystem. The overlay itself can't go in fstab (it
+//! is assembled by the initramfs), but the backing filesystem can.
+
+use crate::mount::MountInfo;
+
+/// Lower layer directories from an overlay's `lowerdir=` option, top first.
+///
+/// Layers are separated by `:`; an escaped `\:` is part of a path.
+pub fn lowerdirs(options: &str) -> Vec<String> {
+    let Some(value) = options
+        .split(',')
+        .find_map(|opt| opt.strip_prefix("lowerdir="))
+    else {
+        return Vec::new();
+    };
+
+    let mut dirs = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            ':' => dirs.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    dirs.push(current);
+    dirs.retain(|dir| !dir.is_empty());
+    dirs
+}
+
+/// Find the mount holding `path`: the non-overlay mount with the longest
+/// target that is a prefix of `path`.
+pub fn backing_mount<'a>(path: &str, mounts: &'a [MountInfo]) -> Option<&'a MountInfo> {
+    mounts
+        .iter()
+        .filter(|mount| mount.fstype != "overlay")
+        .filter(|mount| {
+            let target = mount.target.trim_end_matches('/');
+            path == target || path.starts_with(&format!("{}/", target)) || target.is_empty()
+        })
+        .max_by_key(|mount| mount.target.len())
+}
+
+/// Resolve an overlay root to the mount backing its bottom lower layer.
+///
+/// Returns the lowerdir and its backing mount, or `None` if `overlay` is not
+/// an overlay or has no resolvable lowerdir.
+pub fn resolve_overlay_lower<'a>(
+    overlay: &MountInfo,
+    mounts: &'a [MountInfo],
+) -> Option<(String, &'a MountInfo)> {
+    if overlay.fstype != "overlay" {
+        return None;
+    }
+    // The rightmost layer is the bottom one, i.e. the base image
+    let lower = lowerdirs(&overlay.options).pop()?;
+    let mount = backing_mount(&lower, mounts)?;
+    Some((lower, mount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(target: &str, source: &str, fstype: &str, options: &str) -> MountInfo {
+        MountInfo {
+            target: target.to_string(),
+            source: source.to_string(),
+            fstype: fstype.to_string(),
+            options: options.to_string(),
+            ..MountInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_lowerdirs() {
+        assert_eq!(
+            lowerdirs("rw,lowerdir=/run/rootfsbase,upperdir=/run/upper,workdir=/run/work"),
+            ["/run/rootfsbase"]
+        );
+        assert_eq!(lowerdirs("lowerdir=/a:/b\\:c"), ["/a", "/b:c"]);
+        assert!(lowerdirs("rw,relatime").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_overlay_lower() {
+        let mounts = vec![
+            mount(
+                "/",
+                "overlay",
+                "overlay",
+                "rw,lowerdir=/run/ostree:/sysroot/base,upperdir=/run/u",
+            ),
+            mount("/sysroot", "/dev/vda3", "ext4", "ro"),
+            mount("/run", "tmpfs", "tmpfs", "rw"),
+        ];
+        let (lower, backing) = resolve_overlay_lower(&mounts[0], &mounts).unwrap();
+        assert_eq!(lower, "/sysroot/base");
+        assert_eq!(backing.source, "/dev/vda3");
+
+        // Not an overlay
+        assert!(resolve_overlay_lower(&mounts[1], &mounts).is_none());
+        // Lowerdir on no known mount
+        let orphan = mount("/", "overlay", "overlay", "lowerdir=/nowhere");
+        assert!(resolve_overlay_lower(&orphan, &mounts[..1]).is_none());
+    }
+}
@@ -0,0 +1,81 @@
+//! Per-mountpoint fsck pass overrides (`--pass-map`).
+//!
+//! The map file has one `MOUNTPOINT PASS` pair per line, e.g. `/home 1`,
+//! for administrators who group filesystems into their own fsck order.
+//! Blank lines and `#` comments are ignored. The mountpoint uses fstab
+//! escaping (`\040` for a space); the pass must be 0, 1 or 2.
+
+use crate::fstab::{unescape_fstab, FstabEntry};
+use std::collections::HashMap;
+
+/// Parse a pass map file into mountpoint -> pass number.
+///
+/// A later line for the same mountpoint wins. Returns a description of the
+/// first bad line (missing or invalid pass) on failure.
+pub fn parse_pass_map(content: &str) -> Result<HashMap<String, u8>, String> {
+    let mut map = HashMap::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(mountpoint), Some(pass), None) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(format!(
+                "line {}: expected MOUNTPOINT PASS: {}",
+                index + 1,
+                line
+            ));
+        };
+        let pass = match pass {
+            "0" => 0,
+            "1" => 1,
+            "2" => 2,
+            _ => {
+                return Err(format!(
+                    "line {}: pass for {} must be 0, 1 or 2, not {}",
+                    index + 1,
+                    mountpoint,
+                    pass
+                ))
+            }
+        };
+        map.insert(unescape_fstab(mountpoint), pass);
+    }
+    Ok(map)
+}
+
+/// Set the pass of every entry whose mountpoint is in `map`.
+pub fn apply_pass_map(entries: &mut [FstabEntry], map: &HashMap<String, u8>) {
+    for entry in entries {
+        if let Some(&pass) = map.get(&entry.mountpoint) {
+            entry.pass = pass;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pass_map() {
+        let map =
+            parse_pass_map("# fsck groups\n\n/home\t1\n/mnt/my\\040disk 0\n/home 2\n").unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["/home"], 2);
+        assert_eq!(map["/mnt/my disk"], 0);
+    }
+
+    #[test]
+    fn test_parse_pass_map_rejects_bad_lines() {
+        assert_eq!(
+            parse_pass_map("/home 1\n/srv 3\n").unwrap_err(),
+            "line 2: pass for /srv must be 0, 1 or 2, not 3"
+        );
+        assert!(parse_pass_map("/home\n").is_err());
+        assert!(parse_pass_map("/home 1 extra\n").is_err());
+        assert!(parse_pass_map("/home -1\n").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Trailing statistics comment (`--summary-comment`).
+
+use std::collections::BTreeMap;
+
+/// Mounts under the root that were left out, by reason (`"pseudo"`,
+/// `"duplicate"`, ...).
+pub type SkipCounts = BTreeMap<&'static str, usize>;
+
+/// What a run generated, for the closing `# recfstab: ...` comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Filesystem entries generated (swap excluded)
+    pub filesystems: usize,
+    /// Swap entries generated
+    pub swaps: usize,
+    /// Mounts under the root that were skipped
+    pub skipped: SkipCounts,
+}
+
+impl Summary {
+    /// Render as a comment line, e.g.
+    /// `# recfstab: 5 filesystems, 1 swap, 2 skipped (pseudo)`.
+    ///
+    /// With several skip reasons each gets its own count:
+    /// `3 skipped (2 pseudo, 1 duplicate)`.
+    pub fn comment(&self) -> String {
+        let total: usize = self.skipped.values().sum();
+        let reasons = match self.skipped.len() {
+            0 => String::new(),
+            1 => format!(" ({})", self.skipped.keys().next().unwrap_or(&"")),
+            _ => {
+                let mut by_count: Vec<_> = self.skipped.iter().collect();
+                by_count.sort_by(|a, b| b.1.cmp(a.1));
+                let parts: Vec<String> = by_count
+                    .iter()
+                    .map(|(reason, count)| format!("{} {}", count, reason))
+                    .collect();
+                format!(" ({})", parts.join(", "))
+            }
+        };
+        format!(
+            "# recfstab: {} {}, {} swap, {} skipped{}\n",
+            self.filesystems,
+            if self.filesystems == 1 {
+                "filesystem"
+            } else {
+                "filesystems"
+            },
+            self.swaps,
+            total,
+            reasons
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_comment() {
+        let mut summary = Summary {
+            filesystems: 5,
+            swaps: 1,
+            skipped: SkipCounts::from([("pseudo", 2)]),
+        };
+        assert_eq!(
+            summary.comment(),
+            "# recfstab: 5 filesystems, 1 swap, 2 skipped (pseudo)\n"
+        );
+
+        summary.skipped.insert("duplicate", 1);
+        assert_eq!(
+            summary.comment(),
+            "# recfstab: 5 filesystems, 1 swap, 3 skipped (2 pseudo, 1 duplicate)\n"
+        );
+
+        assert_eq!(
+            Summary {
+                filesystems: 1,
+                ..Summary::default()
+            }
+            .comment(),
+            "# recfstab: 1 filesystem, 0 swap, 0 skipped\n"
+        );
+    }
+}
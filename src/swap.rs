@@ -1,8 +1,13 @@
 //! Swap partition detection from /proc/swaps.
 
-use crate::device::get_device_identifier;
+use crate::device::{
+    block_device_path, classify_source, extract_device_path, get_device_identifier_with,
+    lookup_device_id, subvol_from_source, IdType,
+};
 use crate::error::Result;
-use crate::fstab::escape_fstab;
+use crate::filter::is_under_root;
+use crate::fstab::{make_fstab_target, prefix_target, unescape_fstab, FstabEntry};
+use crate::mount::MountInfo;
 use std::fs;
 
 /// Represents a swap entry from /proc/swaps.
@@ -71,39 +76,11 @@ fn parse_swap_line(line: &str) -> Option<SwapInfo> {
 
 /// Unescape special characters in /proc/swaps filenames.
 ///
-/// /proc/swaps uses octal escaping like fstab: \040 for space, etc.
+/// /proc/swaps uses octal escaping like fstab: \040 for space, \011 for tab,
+/// \134 for backslash. Escapes are decoded to bytes first, so multi-byte
+/// UTF-8 names survive the roundtrip through [`escape_fstab`](crate::fstab::escape_fstab).
 fn unescape_proc_swaps(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    let mut chars = s.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        if c == '\\' {
-            // Try to parse octal escape
-            let mut octal = String::new();
-            for _ in 0..3 {
-                if let Some(&digit) = chars.peek() {
-                    if digit.is_ascii_digit() && digit != '8' && digit != '9' {
-                        octal.push(chars.next().unwrap());
-                    } else {
-                        break;
-                    }
-                }
-            }
-            if octal.len() == 3 {
-                if let Ok(byte) = u8::from_str_radix(&octal, 8) {
-                    result.push(byte as char);
-                    continue;
-                }
-            }
-            // Invalid escape - keep the backslash and octal chars
-            result.push('\\');
-            result.push_str(&octal);
-        } else {
-            result.push(c);
-        }
-    }
-
-    result
+    unescape_fstab(s)
 }
 
 /// Check if a swap path is a zram device.
@@ -118,9 +95,8 @@ pub fn is_zram(path: &str) -> bool {
 ///
 /// Swap files should use their path directly in fstab, not UUID lookup.
 pub fn is_swap_file(path: &str) -> bool {
-    // Block devices start with /dev/
     // Swap files are regular files in the filesystem
-    !path.starts_with("/dev/")
+    !classify_source(path).is_block()
 }
 
 /// Check if a swap device is under the target root.
@@ -144,74 +120,261 @@ pub fn is_swap_under_root(swap: &SwapInfo, root: &str) -> bool {
     }
 }
 
+/// Identifier type for swap entries (`--swap-id-type`).
+///
+/// Like [`IdType`], plus `Device` for the raw device path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SwapIdType {
+    /// Filesystem UUID (default)
+    #[default]
+    Uuid,
+    /// Filesystem LABEL
+    Label,
+    /// Partition UUID (GPT PARTUUID)
+    Partuuid,
+    /// Partition LABEL (GPT PARTLABEL)
+    Partlabel,
+    /// Raw device path (/dev/sda2), no blkid lookup
+    Device,
+}
+
+impl SwapIdType {
+    /// The blkid tag to look up, or `None` for the raw device path.
+    pub fn blkid_tag(&self) -> Option<&'static str> {
+        match self {
+            SwapIdType::Uuid => Some("UUID"),
+            SwapIdType::Label => Some("LABEL"),
+            SwapIdType::Partuuid => Some("PARTUUID"),
+            SwapIdType::Partlabel => Some("PARTLABEL"),
+            SwapIdType::Device => None,
+        }
+    }
+}
+
+impl From<IdType> for SwapIdType {
+    fn from(id_type: IdType) -> Self {
+        match id_type {
+            IdType::Uuid => SwapIdType::Uuid,
+            IdType::Label => SwapIdType::Label,
+            IdType::Partuuid => SwapIdType::Partuuid,
+            IdType::Partlabel => SwapIdType::Partlabel,
+        }
+    }
+}
+
 /// Get the fstab source identifier for a swap entry.
 ///
 /// Uses UUID/LABEL for block devices, path for swap files.
-pub fn get_swap_identifier(swap: &SwapInfo, id_type: &str) -> String {
-    if is_swap_file(&swap.filename) {
-        // Swap files use their path directly
-        swap.filename.clone()
-    } else {
+pub fn get_swap_identifier(swap: &SwapInfo, id_type: SwapIdType) -> String {
+    get_swap_identifier_with(swap, id_type, lookup_device_id)
+}
+
+/// Like [`get_swap_identifier`], but resolves block devices through `lookup`.
+pub fn get_swap_identifier_with<F>(swap: &SwapInfo, id_type: SwapIdType, lookup: F) -> String
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
+    match id_type.blkid_tag() {
         // Block devices use UUID/LABEL/PARTUUID/PARTLABEL
-        get_device_identifier(&swap.filename, id_type)
+        Some(tag) if !is_swap_file(&swap.filename) => {
+            get_device_identifier_with(&swap.filename, tag, lookup)
+        }
+        // Swap files (and --swap-id-type device) use their path directly
+        _ => swap.filename.clone(),
+    }
+}
+
+/// What to write in a swap entry's mountpoint field (`--swap-mountpoint`).
+///
+/// Without one, block devices get `none` and swap files their path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SwapMountpoint {
+    /// `none` for every swap
+    None,
+    /// `swap` for every swap
+    Swap,
+    /// The swap file's path, or the device path for partitions
+    Path,
+}
+
+impl SwapMountpoint {
+    /// The mountpoint field for `swap`, given its default `target` (see
+    /// [`get_swap_target`]).
+    pub fn apply(&self, swap: &SwapInfo, target: &str) -> String {
+        match self {
+            SwapMountpoint::None => "none".to_string(),
+            SwapMountpoint::Swap => "swap".to_string(),
+            SwapMountpoint::Path if is_swap_file(&swap.filename) => target.to_string(),
+            SwapMountpoint::Path => swap.filename.clone(),
+        }
+    }
+}
+
+/// Like [`get_swap_target`], written in `style` when one is given.
+pub fn get_swap_target_with(
+    swap: &SwapInfo,
+    root: &str,
+    prefix: Option<&str>,
+    style: Option<SwapMountpoint>,
+) -> String {
+    let target = get_swap_target(swap, root, prefix);
+    match style {
+        Some(style) => style.apply(swap, &target),
+        None => target,
     }
 }
 
 /// Get the fstab target path for a swap entry under the given root.
 ///
-/// Swap files need their path relative to root, block devices use "none".
-pub fn get_swap_target(swap: &SwapInfo, root: &str) -> String {
+/// Swap files need their path relative to root (rebased under `prefix` when
+/// set, like mount targets), block devices use "none".
+pub fn get_swap_target(swap: &SwapInfo, root: &str, prefix: Option<&str>) -> String {
     if is_swap_file(&swap.filename) {
-        // Convert swap file path to relative path
-        let canonical_root = if root == "/" {
-            root.to_string()
-        } else {
-            root.trim_end_matches('/').to_string()
-        };
+        prefix_target(prefix, &swap_file_target(swap, root))
+    } else {
+        "none".to_string()
+    }
+}
+
+/// Path of a swap file relative to `root`.
+fn swap_file_target(swap: &SwapInfo, root: &str) -> String {
+    // Convert swap file path to relative path
+    let canonical_root = if root == "/" {
+        root.to_string()
+    } else {
+        root.trim_end_matches('/').to_string()
+    };
 
-        if swap.filename == canonical_root {
-            "/".to_string()
-        } else if let Some(relative) = swap.filename.strip_prefix(&canonical_root) {
-            if relative.is_empty() || relative.starts_with('/') {
-                if relative.is_empty() {
-                    "/".to_string()
-                } else {
-                    relative.to_string()
-                }
+    if swap.filename == canonical_root {
+        "/".to_string()
+    } else if let Some(relative) = swap.filename.strip_prefix(&canonical_root) {
+        if relative.is_empty() || relative.starts_with('/') {
+            if relative.is_empty() {
+                "/".to_string()
             } else {
-                format!("/{}", relative)
+                relative.to_string()
             }
         } else {
-            swap.filename.clone() // Fallback to absolute path
+            format!("/{}", relative)
         }
     } else {
-        "none".to_string()
+        swap.filename.clone() // Fallback to absolute path
+    }
+}
+
+/// Resolve a swapfile's target through the btrfs subvolume that holds it.
+///
+/// /proc/swaps reports the path where the swapfile was activated, which can
+/// differ from where its subvolume is mounted under `root` (e.g. activated at
+/// `/swap/swapfile` while `@swap` is mounted at `/mnt/swap`). This finds the
+/// btrfs mount containing the file, computes the file's path inside the
+/// filesystem, and maps it back through a mount of the same device under
+/// `root`.
+///
+/// Returns `None` for block devices, non-btrfs files, or when no mount of the
+/// subvolume exists under `root`.
+pub fn resolve_swapfile_target(
+    swap: &SwapInfo,
+    root: &str,
+    mounts: &[MountInfo],
+) -> Option<String> {
+    if !is_swap_file(&swap.filename) {
+        return None;
+    }
+
+    // Mount that actually holds the file: longest matching target
+    let holder = mounts
+        .iter()
+        .filter(|m| is_under_root(&swap.filename, &m.target))
+        .max_by_key(|m| m.target.len())?;
+    if holder.fstype != "btrfs" {
+        return None;
+    }
+
+    let device = extract_device_path(&holder.source);
+    let relative = make_fstab_target(&swap.filename, &holder.target);
+    let fs_path = join_fs_path(subvol_from_source(&holder.source).unwrap_or("/"), &relative);
+
+    // Mount of the same filesystem under root that exposes fs_path
+    let (mount, subvol) = mounts
+        .iter()
+        .filter(|m| m.fstype == "btrfs" && is_under_root(&m.target, root))
+        .filter(|m| extract_device_path(&m.source) == device)
+        .filter_map(|m| {
+            let subvol = subvol_from_source(&m.source).unwrap_or("/");
+            is_under_root(&fs_path, subvol).then_some((m, subvol))
+        })
+        .max_by_key(|(_, subvol)| subvol.len())?;
+
+    let inside = make_fstab_target(&fs_path, subvol);
+    let absolute = join_fs_path(&mount.target, &inside);
+    Some(make_fstab_target(&absolute, root))
+}
+
+/// Join an absolute base path and a "/"-prefixed relative path.
+fn join_fs_path(base: &str, relative: &str) -> String {
+    if relative == "/" {
+        base.to_string()
+    } else {
+        format!("{}{}", base.trim_end_matches('/'), relative)
+    }
+}
+
+/// Build the fstab entry for a swap device or file under the given root.
+pub fn swap_entry(
+    swap: &SwapInfo,
+    root: &str,
+    id_type: SwapIdType,
+    prefix: Option<&str>,
+) -> FstabEntry {
+    swap_entry_with(swap, root, id_type, prefix, lookup_device_id)
+}
+
+/// Like [`swap_entry`], but resolves block devices through `lookup`.
+pub fn swap_entry_with<F>(
+    swap: &SwapInfo,
+    root: &str,
+    id_type: SwapIdType,
+    prefix: Option<&str>,
+    lookup: F,
+) -> FstabEntry
+where
+    F: Fn(&str, &str) -> Option<String>,
+{
+    let fs_spec = get_swap_identifier_with(swap, id_type, lookup);
+    let id_fallback =
+        id_type.blkid_tag().is_some() && !is_swap_file(&swap.filename) && fs_spec == swap.filename;
+    // A raw line break in the comment would start a new fstab line
+    let comment = swap.filename.replace('\n', "\\012").replace('\r', "\\015");
+    FstabEntry {
+        comments: vec![comment],
+        fs_spec,
+        mountpoint: get_swap_target(swap, root, prefix),
+        fstype: "swap".to_string(),
+        options: "defaults".to_string(),
+        dump: 0,
+        pass: 0,
+        mountpoint_bytes: None,
+        id_fallback,
+        device: block_device_path(&swap.filename).map(String::from),
     }
 }
 
 /// Print swap entries as fstab lines.
-pub fn print_swap_entries(swaps: &[SwapInfo], root: &str, id_type: &str) {
+pub fn print_swap_entries(swaps: &[SwapInfo], root: &str, id_type: SwapIdType) {
     for swap in swaps {
         if !is_swap_under_root(swap, root) {
             continue;
         }
 
-        let identifier = get_swap_identifier(swap, id_type);
-        let target = get_swap_target(swap, root);
-
-        println!("# {}", swap.filename);
-        println!(
-            "{}\t{}\tswap\tdefaults\t0\t0",
-            escape_fstab(&identifier),
-            escape_fstab(&target),
-        );
-        println!();
+        print!("{}", swap_entry(swap, root, id_type, None).render());
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fstab::parse_fstab;
 
     #[test]
     fn test_is_zram() {
@@ -272,6 +435,45 @@ mod tests {
         assert_eq!(unescape_proc_swaps("/mnt/a\\040b\\040c"), "/mnt/a b c");
     }
 
+    #[test]
+    fn test_unescape_proc_swaps_multibyte() {
+        assert_eq!(unescape_proc_swaps("/mnt/sw\\303\\251ap"), "/mnt/swéap");
+        // Not a valid escape: kept literally
+        assert_eq!(unescape_proc_swaps("/mnt/a\\9b"), "/mnt/a\\9b");
+    }
+
+    #[test]
+    fn test_swapfile_special_chars_roundtrip() {
+        // The kernel escapes tab and backslash in /proc/swaps but not '#'
+        let line = "/mnt/swap\\011dir/#1\\134x    file\t1048576\t0\t-2";
+        let swap = parse_swap_line(line).unwrap();
+        assert_eq!(swap.filename, "/mnt/swap\tdir/#1\\x");
+
+        let entry = swap_entry(&swap, "/mnt", SwapIdType::Uuid, None);
+        assert_eq!(
+            entry.data_line(),
+            "/mnt/swap\\011dir/\\0431\\134x\t/swap\\011dir/\\0431\\134x\tswap\tdefaults\t0\t0"
+        );
+
+        let parsed = parse_fstab(&entry.render());
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].fs_spec, swap.filename);
+        assert_eq!(parsed[0].mountpoint, "/swap\tdir/#1\\x");
+    }
+
+    #[test]
+    fn test_swapfile_newline_stays_in_comment() {
+        let swap = parse_swap_line("/mnt/a\\012b file 1024 0 -2").unwrap();
+        assert_eq!(swap.filename, "/mnt/a\nb");
+
+        let entry = swap_entry(&swap, "/mnt", SwapIdType::Uuid, None);
+        let rendered = entry.render();
+        assert_eq!(rendered.lines().count(), 3);
+        let parsed = parse_fstab(&rendered);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].fs_spec, "/mnt/a\nb");
+    }
+
     #[test]
     fn test_is_swap_under_root() {
         let block_swap = SwapInfo {
@@ -314,9 +516,203 @@ mod tests {
         };
 
         // Block devices use "none"
-        assert_eq!(get_swap_target(&block_swap, "/mnt"), "none");
+        assert_eq!(get_swap_target(&block_swap, "/mnt", None), "none");
+        assert_eq!(
+            get_swap_target(&block_swap, "/mnt", Some("/srv/guest")),
+            "none"
+        );
 
         // Swap files get relative path
-        assert_eq!(get_swap_target(&file_swap, "/mnt"), "/swapfile");
+        assert_eq!(get_swap_target(&file_swap, "/mnt", None), "/swapfile");
+    }
+
+    #[test]
+    fn test_get_swap_target_with_prefix() {
+        let file_swap = SwapInfo {
+            filename: "/mnt/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        assert_eq!(
+            get_swap_target(&file_swap, "/mnt", Some("/srv/guest")),
+            "/srv/guest/swapfile"
+        );
+    }
+
+    #[test]
+    fn test_get_swap_target_with_style() {
+        let block_swap = SwapInfo {
+            filename: "/dev/sda2".to_string(),
+            swap_type: "partition".to_string(),
+        };
+        let file_swap = SwapInfo {
+            filename: "/mnt/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        let target = |swap, style| get_swap_target_with(swap, "/mnt", None, style);
+
+        assert_eq!(target(&block_swap, None), "none");
+        assert_eq!(target(&file_swap, None), "/swapfile");
+        assert_eq!(target(&block_swap, Some(SwapMountpoint::None)), "none");
+        assert_eq!(target(&file_swap, Some(SwapMountpoint::None)), "none");
+        assert_eq!(target(&block_swap, Some(SwapMountpoint::Swap)), "swap");
+        assert_eq!(target(&file_swap, Some(SwapMountpoint::Swap)), "swap");
+        assert_eq!(target(&block_swap, Some(SwapMountpoint::Path)), "/dev/sda2");
+        assert_eq!(target(&file_swap, Some(SwapMountpoint::Path)), "/swapfile");
+    }
+
+    #[test]
+    fn test_swap_entry() {
+        let file_swap = SwapInfo {
+            filename: "/mnt/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        let entry = swap_entry(&file_swap, "/mnt", SwapIdType::Uuid, None);
+        assert_eq!(entry.comments, vec!["/mnt/swapfile"]);
+        assert_eq!(
+            entry.data_line(),
+            "/mnt/swapfile\t/swapfile\tswap\tdefaults\t0\t0"
+        );
+    }
+
+    /// Stubbed blkid: /dev/sda2 has every tag, /dev/sdb2 has none.
+    fn stub_lookup(device: &str, tag: &str) -> Option<String> {
+        if device != "/dev/sda2" {
+            return None;
+        }
+        let value = match tag {
+            "UUID" => "1111-aaaa",
+            "LABEL" => "swap",
+            "PARTUUID" => "2222-bbbb",
+            "PARTLABEL" => "linux-swap",
+            _ => return None,
+        };
+        Some(format!("{}={}", tag, value))
+    }
+
+    #[test]
+    fn test_swap_identifier_matrix() {
+        let partition = SwapInfo {
+            filename: "/dev/sda2".to_string(),
+            swap_type: "partition".to_string(),
+        };
+        let missing = SwapInfo {
+            filename: "/dev/sdb2".to_string(),
+            swap_type: "partition".to_string(),
+        };
+        let cases = [
+            (IdType::Uuid, "UUID=1111-aaaa"),
+            (IdType::Label, "LABEL=swap"),
+            (IdType::Partuuid, "PARTUUID=2222-bbbb"),
+            (IdType::Partlabel, "PARTLABEL=linux-swap"),
+        ];
+
+        for (id_type, expected) in cases {
+            let tag = SwapIdType::from(id_type);
+            assert_eq!(
+                get_swap_identifier_with(&partition, tag, stub_lookup),
+                expected,
+                "id type {:?}",
+                id_type
+            );
+            // blkid failure falls back to the device path
+            assert_eq!(
+                get_swap_identifier_with(&missing, tag, stub_lookup),
+                "/dev/sdb2",
+                "id type {:?}",
+                id_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_swap_file_identifier_ignores_lookup() {
+        let file_swap = SwapInfo {
+            filename: "/mnt/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        assert_eq!(
+            get_swap_identifier_with(&file_swap, SwapIdType::Label, stub_lookup),
+            "/mnt/swapfile"
+        );
+    }
+
+    #[test]
+    fn test_swap_id_type_device_skips_lookup() {
+        let partition = SwapInfo {
+            filename: "/dev/sda2".to_string(),
+            swap_type: "partition".to_string(),
+        };
+        let lookup = |_: &str, _: &str| -> Option<String> { panic!("blkid must not run") };
+        assert_eq!(
+            get_swap_identifier_with(&partition, SwapIdType::Device, lookup),
+            "/dev/sda2"
+        );
+    }
+
+    fn btrfs(target: &str, source: &str) -> MountInfo {
+        MountInfo {
+            target: target.to_string(),
+            source: source.to_string(),
+            fstype: "btrfs".to_string(),
+            options: "rw,subvol=/".to_string(),
+            ..MountInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_swapfile_target_subvol_mount() {
+        // @swap activated at /swap on the host, mounted at /mnt/swap for install
+        let mounts = vec![
+            btrfs("/swap", "/dev/sda2[/@swap]"),
+            btrfs("/mnt", "/dev/sda2[/@]"),
+            btrfs("/mnt/swap", "/dev/sda2[/@swap]"),
+        ];
+        let swap = SwapInfo {
+            filename: "/swap/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        assert!(!is_swap_under_root(&swap, "/mnt"));
+        assert_eq!(
+            resolve_swapfile_target(&swap, "/mnt", &mounts),
+            Some("/swap/swapfile".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_swapfile_target_via_parent_subvol() {
+        // Only the top-level volume is mounted under root
+        let mounts = vec![
+            btrfs("/var/swap", "/dev/sda2[/@swap]"),
+            btrfs("/mnt/.btrfsroot", "/dev/sda2"),
+        ];
+        let swap = SwapInfo {
+            filename: "/var/swap/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        assert_eq!(
+            resolve_swapfile_target(&swap, "/mnt", &mounts),
+            Some("/.btrfsroot/@swap/swapfile".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_swapfile_target_unresolvable() {
+        let mounts = vec![
+            btrfs("/swap", "/dev/sda2[/@swap]"),
+            btrfs("/mnt", "/dev/sdb2[/@]"),
+        ];
+        let swap = SwapInfo {
+            filename: "/swap/swapfile".to_string(),
+            swap_type: "file".to_string(),
+        };
+        // Different device under root
+        assert_eq!(resolve_swapfile_target(&swap, "/mnt", &mounts), None);
+
+        // Block devices are never resolved
+        let partition = SwapInfo {
+            filename: "/dev/sda3".to_string(),
+            swap_type: "partition".to_string(),
+        };
+        assert_eq!(resolve_swapfile_target(&partition, "/mnt", &mounts), None);
     }
 }
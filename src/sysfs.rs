@@ -0,0 +1,208 @@
+//! Block device attributes read from sysfs.
+//!
+//! Every lookup has an `_in` variant taking the sysfs mount point, so tests
+//! can point it at a fixture tree instead of `/sys`.
+
+use std::fs;
+use std::path::Path;
+
+/// Where sysfs is mounted on the running system.
+pub const SYS_ROOT: &str = "/sys";
+
+/// Kernel name of a device node (`/dev/sdb1` -> `sdb1`).
+fn kernel_name(device: &str) -> Option<&str> {
+    let name = device.strip_prefix("/dev/")?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(name)
+}
+
+/// Whole disk a partition belongs to, by kernel naming convention.
+///
+/// Disks whose name ends in a digit number partitions with a `p`
+/// (`nvme0n1p1` -> `nvme0n1`, `mmcblk0p1` -> `mmcblk0`, `loop0p2` ->
+/// `loop0`); SCSI, virtio, IDE and Xen disks append the number directly
+/// (`sda1` -> `sda`, `vdb12` -> `vdb`, `xvda1` -> `xvda`). A `/dev/` prefix
+/// is kept. Anything else, including whole disks (`nvme0n1`, `loop0`,
+/// `dm-0`), is returned unchanged.
+pub fn parent_block_device(partition: &str) -> String {
+    let (dir, name) = match partition.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, partition),
+    };
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let base = &name[..name.len() - digits];
+    let parent = if digits == 0 {
+        name
+    } else if let Some(disk) = base
+        .strip_suffix('p')
+        .filter(|disk| disk.ends_with(|c: char| c.is_ascii_digit()))
+    {
+        disk
+    } else if ["sd", "vd", "hd", "xvd"].iter().any(|prefix| {
+        base.strip_prefix(prefix).is_some_and(|letters| {
+            !letters.is_empty() && letters.bytes().all(|b| b.is_ascii_lowercase())
+        })
+    }) {
+        base
+    } else {
+        name
+    };
+    match dir {
+        Some(dir) => format!("{}/{}", dir, parent),
+        None => parent.to_string(),
+    }
+}
+
+/// Read `/sys/class/block/<name>/<attr>`, trimmed.
+pub fn block_attr_in(sys_root: &Path, name: &str, attr: &str) -> Option<String> {
+    fs::read_to_string(sys_root.join("class/block").join(name).join(attr))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+/// Check whether a device is on removable media (USB sticks, SD cards, ...).
+pub fn is_removable(device: &str) -> bool {
+    is_removable_in(Path::new(SYS_ROOT), device)
+}
+
+/// Like [`is_removable`], with sysfs mounted at `sys_root`.
+///
+/// The `removable` attribute only exists on whole disks, so a partition is
+/// checked through its [`parent_block_device`], or failing that its parent
+/// directory in sysfs. Unknown devices are treated as fixed.
+pub fn is_removable_in(sys_root: &Path, device: &str) -> bool {
+    let Some(name) = kernel_name(device) else {
+        return false;
+    };
+    let value = block_attr_in(sys_root, name, "removable")
+        .or_else(|| block_attr_in(sys_root, &parent_block_device(name), "removable"))
+        .or_else(|| block_attr_in(sys_root, name, "../removable"));
+    value.as_deref() == Some("1")
+}
+
+/// File backing a loop device (`/dev/loop0` -> `/var/lib/images/app.sqfs`).
+pub fn loop_backing_file(device: &str) -> Option<String> {
+    loop_backing_file_in(Path::new(SYS_ROOT), device)
+}
+
+/// Like [`loop_backing_file`], with sysfs mounted at `sys_root`.
+///
+/// Returns `None` for anything that is not a bound loop device.
+pub fn loop_backing_file_in(sys_root: &Path, device: &str) -> Option<String> {
+    let name = kernel_name(device).filter(|name| name.starts_with("loop"))?;
+    block_attr_in(sys_root, name, "loop/backing_file").filter(|file| !file.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    /// sysfs fixture: removable `sdb` with partition `sdb1`, fixed `sda`.
+    fn fixture(name: &str) -> PathBuf {
+        let sys = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&sys);
+        for (disk, removable) in [("sda", "0"), ("sdb", "1")] {
+            let part = sys.join(format!("devices/{}/{}1", disk, disk));
+            fs::create_dir_all(&part).unwrap();
+            fs::write(sys.join(format!("devices/{}/removable", disk)), removable).unwrap();
+            fs::write(part.join("partition"), "1\n").unwrap();
+        }
+        fs::create_dir_all(sys.join("class/block")).unwrap();
+        for (link, dest) in [
+            ("sda", "devices/sda"),
+            ("sda1", "devices/sda/sda1"),
+            ("sdb", "devices/sdb"),
+            ("sdb1", "devices/sdb/sdb1"),
+        ] {
+            symlink(sys.join(dest), sys.join("class/block").join(link)).unwrap();
+        }
+        sys
+    }
+
+    #[test]
+    fn test_is_removable_in() {
+        let sys = fixture("recfstab_test_sysfs_removable");
+        assert!(is_removable_in(&sys, "/dev/sdb"));
+        assert!(is_removable_in(&sys, "/dev/sdb1"));
+        assert!(!is_removable_in(&sys, "/dev/sda"));
+        assert!(!is_removable_in(&sys, "/dev/sda1"));
+        // Unknown devices and non-/dev paths are fixed
+        assert!(!is_removable_in(&sys, "/dev/nvme0n1p1"));
+        assert!(!is_removable_in(&sys, "server:/share"));
+        let _ = fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_parent_block_device() {
+        for (partition, disk) in [
+            // NVMe: namespace n1, partition p1
+            ("nvme0n1p1", "nvme0n1"),
+            ("nvme1n2p15", "nvme1n2"),
+            // SD/eMMC
+            ("mmcblk0p1", "mmcblk0"),
+            ("mmcblk1p12", "mmcblk1"),
+            // SCSI/SATA/USB, virtio, IDE, Xen
+            ("sda1", "sda"),
+            ("sdab3", "sdab"),
+            ("vdb12", "vdb"),
+            ("hda2", "hda"),
+            ("xvda1", "xvda"),
+            // Partitioned loop and NBD devices
+            ("loop0p2", "loop0"),
+            ("nbd0p1", "nbd0"),
+            // Whole disks are their own parent
+            ("sda", "sda"),
+            ("nvme0n1", "nvme0n1"),
+            ("mmcblk0", "mmcblk0"),
+            ("loop0", "loop0"),
+            ("dm-0", "dm-0"),
+            ("md127", "md127"),
+            ("sr0", "sr0"),
+            ("zram0", "zram0"),
+            // /dev paths keep their prefix
+            ("/dev/nvme0n1p1", "/dev/nvme0n1"),
+            ("/dev/sda1", "/dev/sda"),
+        ] {
+            assert_eq!(parent_block_device(partition), disk, "{}", partition);
+        }
+    }
+
+    #[test]
+    fn test_is_removable_in_by_parent_name() {
+        // Flat fixture: class/block entries are plain directories, so only
+        // the name-derived parent can find the disk's attribute
+        let sys = std::env::temp_dir().join("recfstab_test_sysfs_parent_name");
+        let _ = fs::remove_dir_all(&sys);
+        for name in ["mmcblk0", "mmcblk0p1", "nvme0n1", "nvme0n1p2"] {
+            fs::create_dir_all(sys.join("class/block").join(name)).unwrap();
+        }
+        fs::write(sys.join("class/block/mmcblk0/removable"), "1\n").unwrap();
+        fs::write(sys.join("class/block/nvme0n1/removable"), "0\n").unwrap();
+
+        assert!(is_removable_in(&sys, "/dev/mmcblk0p1"));
+        assert!(!is_removable_in(&sys, "/dev/nvme0n1p2"));
+        let _ = fs::remove_dir_all(&sys);
+    }
+
+    #[test]
+    fn test_loop_backing_file_in() {
+        let sys = std::env::temp_dir().join("recfstab_test_sysfs_loop");
+        let _ = fs::remove_dir_all(&sys);
+        let dir = sys.join("class/block/loop0/loop");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("backing_file"), "/var/lib/images/app.sqfs\n").unwrap();
+
+        assert_eq!(
+            loop_backing_file_in(&sys, "/dev/loop0").as_deref(),
+            Some("/var/lib/images/app.sqfs")
+        );
+        // Unbound loop device, and devices that are not loops
+        assert_eq!(loop_backing_file_in(&sys, "/dev/loop1"), None);
+        assert_eq!(loop_backing_file_in(&sys, "/dev/sda1"), None);
+        let _ = fs::remove_dir_all(&sys);
+    }
+}
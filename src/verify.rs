@@ -0,0 +1,127 @@
+//! Compare generated options with the running mounts (`--verify-options`).
+//!
+//! An entry reproduces its mount when mounting with the fstab options would
+//! give the options the kernel reports now. Runtime-only options
+//! ([`RUNTIME_OPTIONS`]) are expected to differ and options mount(8)
+//! handles itself ([`USERSPACE_OPTIONS`]) never reach the kernel, so both
+//! are ignored.
+
+use crate::filter::RUNTIME_OPTIONS;
+
+/// Options consumed by mount(8) or its helpers; the kernel never reports
+/// them. `x-*`, `comment=` and the prefixes in [`USERSPACE_PREFIXES`] are
+/// userspace-only too.
+pub const USERSPACE_OPTIONS: &[&str] = &[
+    "_netdev", "auto", "bind", "defaults", "group", "loop", "noauto", "nofail", "nouser", "owner",
+    "rbind", "user", "users",
+];
+
+/// Prefixes of userspace-only `key=value` options.
+pub const USERSPACE_PREFIXES: &[&str] = &[
+    "comment=",
+    "credentials=",
+    "helper=",
+    "loop=",
+    "offset=",
+    "sizelimit=",
+    "uhelper=",
+    "x-",
+];
+
+/// How an entry's options differ from its running mount.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionMismatch {
+    /// In the fstab entry but not applied to the running mount
+    pub not_applied: Vec<String>,
+    /// Applied to the running mount but missing from the fstab entry
+    pub not_in_fstab: Vec<String>,
+}
+
+impl OptionMismatch {
+    /// Check whether the options match.
+    pub fn is_empty(&self) -> bool {
+        self.not_applied.is_empty() && self.not_in_fstab.is_empty()
+    }
+
+    /// Describe the mismatch for `mountpoint`, e.g.
+    /// `/home: not applied at runtime: compress=zstd`.
+    pub fn describe(&self, mountpoint: &str) -> String {
+        let mut parts = Vec::new();
+        if !self.not_applied.is_empty() {
+            parts.push(format!(
+                "not applied at runtime: {}",
+                self.not_applied.join(",")
+            ));
+        }
+        if !self.not_in_fstab.is_empty() {
+            parts.push(format!("not in fstab: {}", self.not_in_fstab.join(",")));
+        }
+        format!("{}: {}", mountpoint, parts.join("; "))
+    }
+}
+
+/// Check whether an option is compared at all (see the module docs).
+/// `subvolid=` is left out too: fstab uses `subvol=` instead.
+fn is_compared(option: &str) -> bool {
+    !option.is_empty()
+        && !RUNTIME_OPTIONS.contains(&option)
+        && !USERSPACE_OPTIONS.contains(&option)
+        && !USERSPACE_PREFIXES
+            .iter()
+            .any(|prefix| option.starts_with(prefix))
+        && !option.starts_with("subvolid=")
+}
+
+/// Compare the options of an fstab entry with the VFS options of the
+/// running mount.
+pub fn compare_options(fstab_options: &str, vfs_options: &str) -> OptionMismatch {
+    let compared = |options: &str| -> Vec<String> {
+        options
+            .split(',')
+            .map(str::trim)
+            .filter(|opt| is_compared(opt))
+            .map(String::from)
+            .collect()
+    };
+    let fstab = compared(fstab_options);
+    let running = compared(vfs_options);
+    OptionMismatch {
+        not_applied: fstab
+            .iter()
+            .filter(|opt| !running.contains(opt))
+            .cloned()
+            .collect(),
+        not_in_fstab: running
+            .iter()
+            .filter(|opt| !fstab.contains(opt))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_options_ignores_expected_differences() {
+        assert!(compare_options("defaults", "rw,relatime").is_empty());
+        assert!(compare_options(
+            "nofail,x-systemd.automount,subvol=/@home",
+            "rw,noatime,subvolid=257,subvol=/@home"
+        )
+        .is_empty());
+        assert!(compare_options("ro,errors=remount-ro", "rw,errors=remount-ro").is_empty());
+    }
+
+    #[test]
+    fn test_compare_options_reports_discrepancy() {
+        let mismatch = compare_options("compress=zstd,nodev", "rw,nodev,space_cache=v2");
+        assert_eq!(mismatch.not_applied, ["compress=zstd"]);
+        assert_eq!(mismatch.not_in_fstab, ["space_cache=v2"]);
+        assert_eq!(
+            mismatch.describe("/home"),
+            "/home: not applied at runtime: compress=zstd; not in fstab: space_cache=v2"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! ZFS dataset handling.
+//!
+//! Datasets with `mountpoint=legacy` are mounted through fstab like any other
+//! filesystem. Datasets with any other mountpoint are mounted by `zfs mount`
+//! (zfs-mount.service); an fstab entry for them would race the ZFS tooling,
+//! so they are left out.
+
+use std::process::Command;
+
+/// Check whether a ZFS dataset uses `mountpoint=legacy`.
+///
+/// Returns `None` if the `zfs` command is missing or the query fails.
+pub fn zfs_is_legacy(dataset: &str) -> Option<bool> {
+    zfs_is_legacy_with(dataset, query_mountpoint)
+}
+
+/// Like [`zfs_is_legacy`], but reads the mountpoint property through `query`.
+pub fn zfs_is_legacy_with<F>(dataset: &str, query: F) -> Option<bool>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    query(dataset).map(|value| value.trim() == "legacy")
+}
+
+/// Query a dataset's `mountpoint` property with `zfs get`.
+///
+/// Returns None silently on failure, like blkid lookups.
+pub fn query_mountpoint(dataset: &str) -> Option<String> {
+    let output = Command::new("zfs")
+        .args(["get", "-H", "-o", "value", "mountpoint", dataset])
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !value.is_empty() {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Remove `zfsutil` from a ZFS entry's options.
+///
+/// `zfsutil` marks a mount made by the ZFS tooling itself; mount.zfs rejects
+/// it for legacy datasets mounted from fstab.
+pub fn strip_zfsutil(options: &str) -> String {
+    let kept: Vec<&str> = options.split(',').filter(|opt| *opt != "zfsutil").collect();
+
+    if kept.is_empty() {
+        "defaults".to_string()
+    } else {
+        kept.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stub(dataset: &str) -> Option<String> {
+        match dataset {
+            "rpool/ROOT/default" => Some("legacy\n".to_string()),
+            "rpool/home" => Some("/home".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_zfs_is_legacy_with() {
+        assert_eq!(zfs_is_legacy_with("rpool/ROOT/default", stub), Some(true));
+        assert_eq!(zfs_is_legacy_with("rpool/home", stub), Some(false));
+        assert_eq!(zfs_is_legacy_with("tank/missing", stub), None);
+    }
+
+    #[test]
+    fn test_strip_zfsutil() {
+        assert_eq!(strip_zfsutil("xattr,zfsutil,posixacl"), "xattr,posixacl");
+        assert_eq!(strip_zfsutil("zfsutil"), "defaults");
+        assert_eq!(strip_zfsutil("defaults"), "defaults");
+    }
+}
@@ -54,6 +54,45 @@ fn test_missing_root_argument() {
     );
 }
 
+#[test]
+fn test_explain_options_lists_runtime_options() {
+    let output = run_recfstab(&["--explain", "options"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for opt in recfstab::filter::RUNTIME_OPTIONS {
+        assert!(stdout.contains(opt), "{} missing from: {}", opt, stdout);
+    }
+    for fs in recfstab::filter::PSEUDO_FILESYSTEMS {
+        assert!(stdout.contains(fs), "{} missing from: {}", fs, stdout);
+    }
+}
+
+#[test]
+fn test_explain_pass_and_id_types() {
+    let output = run_recfstab(&["--explain", "pass"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for fs in recfstab::fstab::FSCK_FILESYSTEMS
+        .iter()
+        .chain(recfstab::filter::NETWORK_FILESYSTEMS)
+    {
+        assert!(stdout.contains(fs), "{} missing from: {}", fs, stdout);
+    }
+    assert!(stdout.contains("--preserve-mount-state"), "{}", stdout);
+
+    let output = run_recfstab(&["--explain", "id-types"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for prefix in ["UUID=", "LABEL=", "PARTUUID=", "PARTLABEL="] {
+        assert!(
+            stdout.contains(prefix),
+            "{} missing from: {}",
+            prefix,
+            stdout
+        );
+    }
+}
+
 // =============================================================================
 // Error Path Tests
 // =============================================================================
@@ -113,6 +152,55 @@ fn test_empty_directory_no_mounts() {
     let _ = std::fs::remove_dir(&temp_dir);
 }
 
+#[test]
+fn test_output_atomic_leaves_file_on_error() {
+    let dir = std::env::temp_dir().join("recfstab_test_output_atomic");
+    let _ = std::fs::create_dir_all(&dir);
+    let fstab = dir.join("fstab");
+    std::fs::write(&fstab, "# existing\n").unwrap();
+
+    let output = run_recfstab(&[
+        "--output",
+        fstab.to_str().unwrap(),
+        "--write-mode",
+        "atomic",
+        "/nonexistent/path/12345",
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read_to_string(&fstab).unwrap(), "# existing\n");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_merge_conflicts_with_stream() {
+    let output = run_recfstab(&["--merge", "/etc/fstab", "--stream", "/"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_output_sorted_unique_conflicts_with_stream() {
+    let output = run_recfstab(&["--output-sorted-unique", "--stream", "/"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_merge_missing_file_reports_e008() {
+    let output = run_recfstab(&["--merge", "/nonexistent/fstab/12345", "/"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E008"), "stderr was: {}", stderr);
+}
+
+#[test]
+fn test_write_mode_requires_output() {
+    let output = run_recfstab(&["--write-mode", "atomic", "/"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--output"), "stderr was: {}", stderr);
+}
+
 // =============================================================================
 // Exit Code Tests
 // =============================================================================
@@ -335,3 +423,175 @@ fn test_pseudo_filesystems_excluded() {
         }
     }
 }
+
+#[test]
+fn test_profile_minimal_and_explicit_override() {
+    if !std::path::Path::new("/proc/mounts").exists() {
+        return;
+    }
+
+    let output = run_recfstab(&["--profile", "minimal", "/"]);
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            !stdout.lines().any(|line| line.starts_with('#')),
+            "minimal profile should drop comments, got: {}",
+            stdout
+        );
+        assert!(!stdout.contains("\tswap\t"), "got: {}", stdout);
+    }
+
+    // An explicit flag beats the profile
+    let output = run_recfstab(&["--profile", "minimal", "--no-comments=false", "/"]);
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.lines().any(|line| line.starts_with('#')),
+            "--no-comments=false should restore comments, got: {}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_env_defaults_and_flag_override() {
+    if !std::path::Path::new("/proc/mounts").exists() {
+        return;
+    }
+    let run_with_env = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_recfstab"))
+            .args(args)
+            .env("RECFSTAB_NO_COMMENTS", "1")
+            .output()
+            .expect("Failed to execute recfstab")
+    };
+
+    let output = run_with_env(&["/"]);
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            !stdout.lines().any(|line| line.starts_with('#')),
+            "RECFSTAB_NO_COMMENTS should drop comments, got: {}",
+            stdout
+        );
+    }
+
+    let output = run_with_env(&["--no-comments=false", "/"]);
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.lines().any(|line| line.starts_with('#')),
+            "--no-comments=false should override the environment, got: {}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_root_options_rejects_empty_value() {
+    let output = run_recfstab(&["--root-options", "", "/"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("must not be empty"),
+        "stderr was: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_profile_rejects_unknown_name() {
+    let output = run_recfstab(&["--profile", "laptop", "/"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("laptop"), "stderr was: {}", stderr);
+}
+
+#[test]
+fn test_devices_from_replaces_root() {
+    let output = run_recfstab(&["--devices-from", "/nonexistent/devices.list"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E008"), "stderr was: {}", stderr);
+
+    let output = run_recfstab(&["--devices-from", "/nonexistent/devices.list", "/"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cannot be used with"),
+        "stderr was: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_explain_entry_reads_devices_from() {
+    let list = std::env::temp_dir().join("recfstab_test_explain_devices_from.list");
+    std::fs::write(&list, "/dev/recfstab-test1 /data ext4 nodev\n").unwrap();
+    let list_arg = list.to_str().unwrap();
+
+    let output = run_recfstab(&["--devices-from", list_arg, "--explain-entry", "/data"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr was: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        stdout.contains("source:      /dev/recfstab-test1\n"),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("filtered:    nodev\n"),
+        "stdout was: {}",
+        stdout
+    );
+
+    let _ = std::fs::remove_file(&list);
+}
+
+#[test]
+fn test_log_format_json_verbose() {
+    let list = std::env::temp_dir().join("recfstab_test_log_format_json.list");
+    std::fs::write(
+        &list,
+        "proc /proc proc\n/dev/recfstab-test1 /srv ext4\n/dev/recfstab-test2 /short\n",
+    )
+    .unwrap();
+    let list_arg = list.to_str().unwrap();
+
+    let output = run_recfstab(&["--log-format", "json", "-v", "--devices-from", list_arg]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stderr line is JSON"))
+        .collect();
+    let skipped = events
+        .iter()
+        .find(|event| event["event"] == "mount-skipped")
+        .expect("skip reason logged with -v");
+    assert_eq!(skipped["level"], "debug");
+    assert_eq!(skipped["target"], "/proc");
+    assert_eq!(skipped["reason"], "pseudo");
+    let bad_line = events
+        .iter()
+        .find(|event| event["event"] == "device-list-line-skipped")
+        .expect("bad list line warned about");
+    assert_eq!(bad_line["level"], "warning");
+    assert_eq!(bad_line["line"], "3");
+
+    // --quiet leaves only errors
+    let output = run_recfstab(&[
+        "--log-format",
+        "json",
+        "--quiet",
+        "--devices-from",
+        list_arg,
+    ]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let _ = std::fs::remove_file(&list);
+}